@@ -0,0 +1,282 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use rand07::Rng;
+use solana_sdk::pubkey::Pubkey;
+
+use crate::{
+    error::Error,
+    models::{AggSessionCreateRequest, AggSessionState},
+};
+
+/// Long enough for every signer to run both steps, a blockhash expires well before this
+pub const SESSION_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// Upper bound on open sessions, the oldest ones are dropped first but never one that is
+/// being broadcast
+const MAX_SESSIONS: usize = 10_000;
+
+/// Everything needed to aggregate once every signer has submitted
+#[derive(Debug, Clone)]
+pub struct AggSession {
+    pub params: AggSessionCreateRequest,
+    pub keys: Vec<Pubkey>,
+    /// Partial signatures by signer, base58 as submitted
+    pub signatures: HashMap<Pubkey, String>,
+    pub state: AggSessionState,
+    pub transaction_id: Option<String>,
+    /// Why the last aggregation failed, a signer can resubmit to try again
+    pub error: Option<String>,
+    created: Instant,
+}
+
+impl AggSession {
+    pub fn submitted(&self) -> Vec<Pubkey> {
+        self.keys
+            .iter()
+            .filter(|key| self.signatures.contains_key(key))
+            .copied()
+            .collect()
+    }
+
+    pub fn missing(&self) -> Vec<Pubkey> {
+        self.keys
+            .iter()
+            .filter(|key| !self.signatures.contains_key(key))
+            .copied()
+            .collect()
+    }
+
+    /// The partial signatures in the order of `keys`, once all of them are in
+    pub fn ordered_signatures(&self) -> Option<Vec<String>> {
+        self.keys
+            .iter()
+            .map(|key| self.signatures.get(key).cloned())
+            .collect()
+    }
+}
+
+/// Tracks which signers of an aggregated transaction have sent their partial signature,
+/// so a coordinator doesn't have to collect them itself
+#[derive(Debug)]
+pub struct AggSessionStore {
+    sessions: Mutex<HashMap<String, AggSession>>,
+    ttl: Duration,
+}
+
+impl AggSessionStore {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            sessions: Mutex::new(HashMap::new()),
+            ttl,
+        }
+    }
+
+    /// Returns the new session id
+    pub fn create(&self, params: AggSessionCreateRequest, keys: Vec<Pubkey>) -> String {
+        let id = hex::encode(rand07::thread_rng().r#gen::<[u8; 16]>());
+        let mut sessions = self.sessions.lock().unwrap();
+        let now = Instant::now();
+        sessions.retain(|_, session| now.duration_since(session.created) < self.ttl);
+
+        if sessions.len() >= MAX_SESSIONS {
+            let oldest = sessions
+                .iter()
+                .filter(|(_, session)| session.state != AggSessionState::Broadcasting)
+                .min_by_key(|(_, session)| session.created)
+                .map(|(id, _)| id.clone());
+            if let Some(oldest) = oldest {
+                sessions.remove(&oldest);
+            }
+        }
+        sessions.insert(
+            id.clone(),
+            AggSession {
+                params,
+                keys,
+                signatures: HashMap::new(),
+                state: AggSessionState::Collecting,
+                transaction_id: None,
+                error: None,
+                created: now,
+            },
+        );
+        id
+    }
+
+    pub fn get(&self, id: &str) -> Result<AggSession, Error> {
+        let sessions = self.sessions.lock().unwrap();
+        match sessions.get(id) {
+            Some(session) if session.created.elapsed() < self.ttl => Ok(session.clone()),
+            _ => Err(Error::AggSessionNotFound(id.to_string())),
+        }
+    }
+
+    /// Records `signer`'s partial signature, replacing an earlier one. When it was the last
+    /// one missing and `auto_broadcast` is set, the session moves to `Broadcasting` and the
+    /// caller has to aggregate and report back through `finish`
+    pub fn submit(
+        &self,
+        id: &str,
+        signer: Pubkey,
+        signature: String,
+    ) -> Result<(AggSession, bool), Error> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let session = match sessions.get_mut(id) {
+            Some(session) if session.created.elapsed() < self.ttl => session,
+            _ => return Err(Error::AggSessionNotFound(id.to_string())),
+        };
+        if session.state != AggSessionState::Collecting {
+            return Err(Error::AggSessionClosed(id.to_string()));
+        }
+        if !session.keys.contains(&signer) {
+            return Err(Error::InvalidRequest(format!(
+                "{} is not one of the session keys",
+                signer
+            )));
+        }
+
+        session.signatures.insert(signer, signature);
+        let broadcast =
+            session.params.auto_broadcast.unwrap_or(true) && session.missing().is_empty();
+        if broadcast {
+            session.state = AggSessionState::Broadcasting;
+            session.error = None;
+        }
+        Ok((session.clone(), broadcast))
+    }
+
+    /// Stores the outcome of the aggregation started by `submit`. A failure reopens the
+    /// session so a signer with a bad partial signature can resubmit
+    pub fn finish(&self, id: &str, result: Result<String, &Error>) -> Option<AggSession> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let session = sessions.get_mut(id)?;
+        match result {
+            Ok(transaction_id) => {
+                session.state = AggSessionState::Broadcast;
+                session.transaction_id = Some(transaction_id);
+            }
+            Err(e) => {
+                session.state = AggSessionState::Collecting;
+                session.error = Some(e.to_string());
+            }
+        }
+        Some(session.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use solana_sdk::pubkey::Pubkey;
+
+    use crate::{
+        agg_session::{AggSessionStore, MAX_SESSIONS},
+        error::Error,
+        models::{AggSessionCreateRequest, AggSessionState, Network, SendOptions},
+    };
+
+    fn params() -> AggSessionCreateRequest {
+        AggSessionCreateRequest {
+            keys: Vec::new(),
+            amount_sol: Some(1.0),
+            amount_lamports: None,
+            to: Pubkey::new_unique().to_string(),
+            memo: None,
+            tip_account: None,
+            tip_lamports: None,
+            recent_block_hash: None,
+            net: Some(Network::Devnet),
+            nonce_account: None,
+            nonce_authority: None,
+            auto_broadcast: None,
+            send_options: SendOptions::default(),
+        }
+    }
+
+    #[test]
+    fn test_last_submission_starts_broadcast() {
+        let store = AggSessionStore::new(Duration::from_secs(60));
+        let keys = vec![Pubkey::new_unique(), Pubkey::new_unique()];
+        let id = store.create(params(), keys.clone());
+
+        let (session, broadcast) = store.submit(&id, keys[1], "b".to_string()).unwrap();
+        assert!(!broadcast);
+        assert_eq!(session.submitted(), vec![keys[1]]);
+        assert_eq!(session.missing(), vec![keys[0]]);
+        assert_eq!(session.ordered_signatures(), None);
+
+        let (session, broadcast) = store.submit(&id, keys[0], "a".to_string()).unwrap();
+        assert!(broadcast);
+        assert_eq!(session.state, AggSessionState::Broadcasting);
+        assert_eq!(
+            session.ordered_signatures(),
+            Some(vec!["a".to_string(), "b".to_string()])
+        );
+        assert!(matches!(
+            store.submit(&id, keys[0], "a".to_string()),
+            Err(Error::AggSessionClosed(_))
+        ));
+
+        store.finish(&id, Ok("sig".to_string()));
+        let session = store.get(&id).unwrap();
+        assert_eq!(session.state, AggSessionState::Broadcast);
+        assert_eq!(session.transaction_id.as_deref(), Some("sig"));
+    }
+
+    #[test]
+    fn test_failed_broadcast_reopens_session() {
+        let store = AggSessionStore::new(Duration::from_secs(60));
+        let key = Pubkey::new_unique();
+        let id = store.create(params(), vec![key]);
+
+        let (_, broadcast) = store.submit(&id, key, "bad".to_string()).unwrap();
+        assert!(broadcast);
+        store.finish(&id, Err(&Error::InvalidSignature));
+
+        let (session, broadcast) = store.submit(&id, key, "good".to_string()).unwrap();
+        assert!(broadcast);
+        assert_eq!(session.error, None);
+    }
+
+    #[test]
+    fn test_eviction_skips_broadcasting_sessions() {
+        let store = AggSessionStore::new(Duration::from_secs(60));
+        let key = Pubkey::new_unique();
+        let broadcasting = store.create(params(), vec![key]);
+        store.submit(&broadcasting, key, "a".to_string()).unwrap();
+        let collecting = store.create(params(), vec![key]);
+        for _ in 2..MAX_SESSIONS {
+            store.create(params(), vec![key]);
+        }
+
+        store.create(params(), vec![key]);
+        assert_eq!(
+            store.get(&broadcasting).unwrap().state,
+            AggSessionState::Broadcasting
+        );
+        assert!(matches!(
+            store.get(&collecting),
+            Err(Error::AggSessionNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_unknown_signer_and_session_are_rejected() {
+        let store = AggSessionStore::new(Duration::from_secs(60));
+        let id = store.create(params(), vec![Pubkey::new_unique()]);
+
+        assert!(matches!(
+            store.submit(&id, Pubkey::new_unique(), "a".to_string()),
+            Err(Error::InvalidRequest(_))
+        ));
+        assert!(matches!(
+            store.get("missing"),
+            Err(Error::AggSessionNotFound(_))
+        ));
+    }
+}
@@ -0,0 +1,218 @@
+//! Withdrawals scheduled by /api/deactivate_stake with `auto_withdraw_destination`.
+//!
+//! Schedules only live in memory: a restart forgets them, and the withdraw authority keypair
+//! stays in the server's memory until the withdrawal goes through. A failed withdrawal is
+//! retried on the next poll, so the stake account should still be checked after the cooldown.
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{
+    pubkey::Pubkey,
+    signature::{Keypair, Signature, Signer},
+};
+
+use crate::{
+    error::Error,
+    models::{Network, SendOptions},
+    rpc_utils::{DEFAULT_MAX_RETRIES, confirm_transaction, get_latest_blockhash, send_transaction},
+    staking::{create_withdraw_stake_transaction, get_closable},
+};
+
+/// How often the current epoch is looked up, stake accounts are only read once per epoch
+pub const POLL_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// Upper bound on pending withdrawals, each one holds a keypair in memory
+const MAX_SCHEDULED: usize = 10_000;
+
+pub struct ScheduledWithdrawal {
+    pub net: Network,
+    pub stake_account: Pubkey,
+    pub destination: Pubkey,
+    /// Withdraw authority of the stake account, signs the withdrawal
+    pub authority: Keypair,
+    /// Epoch of the last look at the stake account, None until the first poll
+    checked_epoch: Option<u64>,
+}
+
+impl ScheduledWithdrawal {
+    pub fn new(
+        net: Network,
+        stake_account: Pubkey,
+        destination: Pubkey,
+        authority: Keypair,
+    ) -> Self {
+        Self {
+            net,
+            stake_account,
+            destination,
+            authority,
+            checked_epoch: None,
+        }
+    }
+}
+
+/// Pending withdrawals by stake account, a new schedule for the same account replaces the old one
+#[derive(Default)]
+pub struct AutoWithdrawStore {
+    entries: Mutex<HashMap<Pubkey, ScheduledWithdrawal>>,
+}
+
+impl AutoWithdrawStore {
+    pub fn schedule(&self, withdrawal: ScheduledWithdrawal) -> Result<(), Error> {
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= MAX_SCHEDULED && !entries.contains_key(&withdrawal.stake_account) {
+            return Err(Error::InvalidRequest(format!(
+                "{} withdrawals are already scheduled, withdraw manually instead",
+                MAX_SCHEDULED
+            )));
+        }
+        entries.insert(withdrawal.stake_account, withdrawal);
+        Ok(())
+    }
+
+    /// Takes out every withdrawal on `net` not yet checked in `epoch`
+    fn take_due(&self, net: Network, epoch: u64) -> Vec<ScheduledWithdrawal> {
+        let mut entries = self.entries.lock().unwrap();
+        let due: Vec<Pubkey> = entries
+            .values()
+            .filter(|w| w.net == net && w.checked_epoch.is_none_or(|checked| checked < epoch))
+            .map(|w| w.stake_account)
+            .collect();
+        due.iter().filter_map(|key| entries.remove(key)).collect()
+    }
+
+    /// Puts a withdrawal back unless it was rescheduled in the meantime
+    fn put_back(&self, withdrawal: ScheduledWithdrawal) {
+        let mut entries = self.entries.lock().unwrap();
+        entries
+            .entry(withdrawal.stake_account)
+            .or_insert(withdrawal);
+    }
+
+    fn networks(&self) -> HashSet<Network> {
+        let entries = self.entries.lock().unwrap();
+        entries.values().map(|w| w.net).collect()
+    }
+}
+
+/// Polls forever, meant to be spawned once at startup
+pub async fn run(store: Arc<AutoWithdrawStore>, clients: HashMap<Network, Arc<RpcClient>>) {
+    let mut interval = tokio::time::interval(POLL_INTERVAL);
+    loop {
+        interval.tick().await;
+        let store = store.clone();
+        let clients = clients.clone();
+        // The RPC client is blocking
+        let _ = tokio::task::spawn_blocking(move || poll(&store, &clients)).await;
+    }
+}
+
+fn poll(store: &AutoWithdrawStore, clients: &HashMap<Network, Arc<RpcClient>>) {
+    for net in store.networks() {
+        let rpc_client = &clients[&net];
+        let epoch = match rpc_client.get_epoch_info() {
+            Ok(info) => info.epoch,
+            Err(e) => {
+                tracing::warn!(?net, "Auto withdraw skipped, epoch lookup failed: {}", e);
+                continue;
+            }
+        };
+        for mut withdrawal in store.take_due(net, epoch) {
+            match try_withdraw(rpc_client, &withdrawal) {
+                Ok(Some(sig)) => tracing::info!(
+                    stake_account = %withdrawal.stake_account,
+                    signature = %sig,
+                    "Auto withdrew stake to {}",
+                    withdrawal.destination
+                ),
+                Ok(None) => {
+                    withdrawal.checked_epoch = Some(epoch);
+                    store.put_back(withdrawal);
+                }
+                // Emptied in the meantime, e.g. withdrawn by hand
+                Err(Error::InvalidStakeAccount(e))
+                    if matches!(rpc_client.get_balance(&withdrawal.stake_account), Ok(0)) =>
+                {
+                    tracing::warn!("Auto withdraw dropped: {}", e);
+                }
+                Err(e) => {
+                    tracing::warn!(
+                        stake_account = %withdrawal.stake_account,
+                        "Auto withdraw failed, retrying on the next poll: {}",
+                        e
+                    );
+                    store.put_back(withdrawal);
+                }
+            }
+        }
+    }
+}
+
+/// Withdraws the whole balance, closing the account, once deactivation is complete. None
+/// while still cooling down
+fn try_withdraw(
+    rpc_client: &RpcClient,
+    withdrawal: &ScheduledWithdrawal,
+) -> Result<Option<Signature>, Error> {
+    let lamports = match get_closable(rpc_client, &withdrawal.stake_account) {
+        Ok(lamports) => lamports,
+        Err(Error::WithdrawalFailed(_)) => return Ok(None),
+        Err(e) => return Err(e),
+    };
+
+    let mut tx = create_withdraw_stake_transaction(
+        &withdrawal.stake_account,
+        &withdrawal.destination,
+        &withdrawal.authority.pubkey(),
+        lamports,
+    );
+    let recent_hash = get_latest_blockhash(rpc_client, DEFAULT_MAX_RETRIES)?;
+    tx.sign(&[&withdrawal.authority], recent_hash);
+
+    let options = SendOptions::default();
+    let sig = send_transaction(rpc_client, &tx, &options)?;
+    confirm_transaction(rpc_client, &sig, &recent_hash, options.confirm_timeout())?;
+    Ok(Some(sig))
+}
+
+#[cfg(test)]
+mod tests {
+    use solana_sdk::{pubkey::Pubkey, signature::Keypair};
+
+    use crate::{
+        auto_withdraw::{AutoWithdrawStore, ScheduledWithdrawal},
+        models::Network,
+    };
+
+    fn withdrawal(net: Network) -> ScheduledWithdrawal {
+        ScheduledWithdrawal::new(
+            net,
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Keypair::new(),
+        )
+    }
+
+    #[test]
+    fn test_checked_once_per_epoch() {
+        let store = AutoWithdrawStore::default();
+        store.schedule(withdrawal(Network::Devnet)).unwrap();
+        store.schedule(withdrawal(Network::Testnet)).unwrap();
+        assert_eq!(store.networks().len(), 2);
+
+        let mut due = store.take_due(Network::Devnet, 10);
+        assert_eq!(due.len(), 1);
+        assert!(store.take_due(Network::Devnet, 10).is_empty());
+
+        let mut checked = due.remove(0);
+        checked.checked_epoch = Some(10);
+        store.put_back(checked);
+        assert!(store.take_due(Network::Devnet, 10).is_empty());
+        assert_eq!(store.take_due(Network::Devnet, 11).len(), 1);
+    }
+}
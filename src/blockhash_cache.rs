@@ -0,0 +1,83 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use solana_sdk::hash::Hash;
+
+use crate::models::Network;
+
+/// Well within the ~60s a blockhash stays valid, short enough that transactions still get
+/// nearly their whole validity window
+pub const BLOCKHASH_CACHE_TTL: Duration = Duration::from_secs(2);
+
+/// The latest blockhash per cluster, shared by every request sending in the same couple of
+/// seconds instead of each one asking the node
+#[derive(Debug)]
+pub struct BlockhashCache {
+    /// Only locked to read or store an entry, never while fetching, so a slow node doesn't hold
+    /// up other requests. Misses at the same time each fetch their own
+    entries: HashMap<Network, Mutex<Option<(Hash, Instant)>>>,
+    ttl: Duration,
+}
+
+impl BlockhashCache {
+    pub fn new(ttl: Duration) -> Self {
+        let entries = [Network::Mainnet, Network::Testnet, Network::Devnet]
+            .into_iter()
+            .map(|net| (net, Mutex::new(None)))
+            .collect();
+        Self { entries, ttl }
+    }
+
+    /// The cached blockhash of `net` while it is fresh
+    pub fn get(&self, net: Network) -> Option<Hash> {
+        let entry = self.entries[&net].lock().unwrap();
+        entry
+            .filter(|(_, fetched)| fetched.elapsed() < self.ttl)
+            .map(|(hash, _)| hash)
+    }
+
+    /// Caches `hash` as the latest blockhash of `net`, just fetched
+    pub fn store(&self, net: Network, hash: Hash) {
+        *self.entries[&net].lock().unwrap() = Some((hash, Instant::now()));
+    }
+
+    /// Forget the blockhash of `net`, e.g. once the node reported it unknown
+    pub fn invalidate(&self, net: Network) {
+        *self.entries[&net].lock().unwrap() = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use solana_sdk::hash::Hash;
+
+    use crate::{blockhash_cache::BlockhashCache, models::Network};
+
+    #[test]
+    fn test_reused_until_invalidated() {
+        let cache = BlockhashCache::new(Duration::from_secs(60));
+        assert_eq!(cache.get(Network::Devnet), None);
+
+        let first = Hash::new_unique();
+        cache.store(Network::Devnet, first);
+        assert_eq!(cache.get(Network::Devnet), Some(first));
+
+        // Every cluster has its own entry
+        assert_eq!(cache.get(Network::Testnet), None);
+
+        cache.invalidate(Network::Devnet);
+        assert_eq!(cache.get(Network::Devnet), None);
+    }
+
+    #[test]
+    fn test_expires_after_ttl() {
+        let cache = BlockhashCache::new(Duration::ZERO);
+        cache.store(Network::Devnet, Hash::new_unique());
+        assert_eq!(cache.get(Network::Devnet), None);
+    }
+}
@@ -0,0 +1,157 @@
+use std::{env, sync::Arc, time::Duration};
+
+use solana_client::rpc_client::{RpcClient, RpcClientConfig};
+use solana_sdk::commitment_config::CommitmentConfig;
+
+use crate::{
+    error::Error,
+    metrics::{MeteredSender, Metrics},
+    models::Network,
+};
+
+/// Used when RPC_TIMEOUT_SECS isn't set, and by the internal clients without access to the config
+pub const DEFAULT_RPC_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub const DEFAULT_IDEMPOTENCY_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// The largest memo that still fits in one packet next to a token transfer
+pub const DEFAULT_MAX_MEMO_BYTES: usize = 566;
+
+pub const DEFAULT_RATE_LIMIT_BURST: u32 = 20;
+
+/// Far above any valid request, the largest ones carry a few hundred keys or signatures
+pub const DEFAULT_MAX_BODY_BYTES: usize = 1024 * 1024;
+
+/// Long enough for a send to be confirmed, shorter than the usual 60s container stop timeout
+pub const DEFAULT_SHUTDOWN_GRACE_PERIOD: Duration = Duration::from_secs(45);
+
+/// Server settings, read once from the environment at startup
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub host: String,
+    pub port: u16,
+    /// Origins allowed by CORS, empty means any origin is allowed
+    pub cors_allowed_origins: Vec<String>,
+    /// Upper bound for a single RPC request before giving up
+    pub rpc_timeout: Duration,
+    /// Cluster pinged by /ready, None skips the RPC check entirely
+    pub ready_check_network: Option<Network>,
+    /// Used by requests that leave out net, None makes net required
+    pub default_network: Option<Network>,
+    /// How long an idempotency key keeps returning the original transaction
+    pub idempotency_ttl: Duration,
+    /// Longest memo accepted, the whole transaction has to fit in a 1232 byte packet
+    pub max_memo_bytes: usize,
+    /// Required in the X-API-Key header when set, None leaves the API open
+    pub api_key: Option<String>,
+    /// Sustained requests per second allowed per client IP, None disables rate limiting
+    pub rate_limit_per_sec: Option<f64>,
+    /// Requests a client can make in a burst before being limited
+    pub rate_limit_burst: u32,
+    /// Refuse every operation that moves funds on mainnet
+    pub safe_mode: bool,
+    /// How long in-flight requests get to finish after SIGTERM or Ctrl+C
+    pub shutdown_grace_period: Duration,
+    /// Larger request bodies are rejected with 413 before being parsed
+    pub max_body_bytes: usize,
+}
+
+impl Config {
+    pub fn from_env() -> Result<Self, Error> {
+        Ok(Self {
+            host: env::var("HOST").unwrap_or_else(|_| "127.0.0.1".to_string()),
+            port: match env::var("PORT") {
+                Ok(port) => port.parse().map_err(|_| {
+                    Error::InvalidConfig(format!("PORT is not a valid port: {}", port))
+                })?,
+                Err(_) => 8000,
+            },
+            cors_allowed_origins: list_var("CORS_ALLOWED_ORIGINS"),
+            rpc_timeout: env::var("RPC_TIMEOUT_SECS")
+                .ok()
+                .and_then(|secs| secs.parse().ok())
+                .map(Duration::from_secs)
+                .unwrap_or(DEFAULT_RPC_TIMEOUT),
+            ready_check_network: match env::var("READY_CHECK_NETWORK") {
+                Ok(net) if net == "none" => None,
+                Ok(net) => Some(net.parse()?),
+                Err(_) => Some(Network::Devnet),
+            },
+            default_network: match env::var("DEFAULT_NETWORK") {
+                Ok(net) if !net.is_empty() => Some(net.parse()?),
+                _ => None,
+            },
+            idempotency_ttl: env::var("IDEMPOTENCY_TTL_SECS")
+                .ok()
+                .and_then(|secs| secs.parse().ok())
+                .map(Duration::from_secs)
+                .unwrap_or(DEFAULT_IDEMPOTENCY_TTL),
+            max_memo_bytes: env::var("MAX_MEMO_BYTES")
+                .ok()
+                .and_then(|bytes| bytes.parse().ok())
+                .unwrap_or(DEFAULT_MAX_MEMO_BYTES),
+            api_key: env::var("API_KEY").ok().filter(|key| !key.is_empty()),
+            rate_limit_per_sec: match env::var("RATE_LIMIT_PER_SEC") {
+                Ok(rate) => match rate.parse::<f64>() {
+                    Ok(rate) if rate > 0.0 && rate.is_finite() => Some(rate),
+                    _ => {
+                        return Err(Error::InvalidConfig(format!(
+                            "RATE_LIMIT_PER_SEC must be a positive number: {}",
+                            rate
+                        )));
+                    }
+                },
+                Err(_) => None,
+            },
+            rate_limit_burst: env::var("RATE_LIMIT_BURST")
+                .ok()
+                .and_then(|burst| burst.parse().ok())
+                .unwrap_or(DEFAULT_RATE_LIMIT_BURST),
+            safe_mode: matches!(env::var("SAFE_MODE").as_deref(), Ok("1" | "true" | "yes")),
+            shutdown_grace_period: env::var("SHUTDOWN_GRACE_SECS")
+                .ok()
+                .and_then(|secs| secs.parse().ok())
+                .map(Duration::from_secs)
+                .unwrap_or(DEFAULT_SHUTDOWN_GRACE_PERIOD),
+            max_body_bytes: env::var("MAX_BODY_BYTES")
+                .ok()
+                .and_then(|bytes| bytes.parse().ok())
+                .unwrap_or(DEFAULT_MAX_BODY_BYTES),
+        })
+    }
+
+    /// The request's own network, else DEFAULT_NETWORK
+    pub fn resolve_network(&self, net: Option<Network>) -> Result<Network, Error> {
+        resolve_network(net, self.default_network)
+    }
+
+    pub fn bind_address(&self) -> String {
+        format!("{}:{}", self.host, self.port)
+    }
+
+    /// Same as `RpcClient::new_with_timeout`, with every call timed into `metrics`
+    pub fn rpc_client(&self, net: Network, metrics: Arc<Metrics>) -> RpcClient {
+        RpcClient::new_sender(
+            MeteredSender::new(net, net.get_cluster_url(), self.rpc_timeout, metrics),
+            RpcClientConfig::with_commitment(CommitmentConfig::default()),
+        )
+    }
+}
+
+/// For callers holding only the default, like websocket sessions that outlive the request
+pub fn resolve_network(net: Option<Network>, default: Option<Network>) -> Result<Network, Error> {
+    net.or(default).ok_or_else(|| {
+        Error::InvalidRequest("net is required unless DEFAULT_NETWORK is set".to_string())
+    })
+}
+
+/// Comma separated list, blank entries are ignored
+fn list_var(name: &str) -> Vec<String> {
+    env::var(name)
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(str::to_string)
+        .collect()
+}
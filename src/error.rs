@@ -1,7 +1,8 @@
 use std::fmt::{Display, Formatter};
 
 use bs58::decode::Error as Bs58Error;
-use solana_client::client_error::ClientError;
+use poem::http::StatusCode;
+use solana_client::client_error::{ClientError, ClientErrorKind};
 use solana_sdk::program_error::ProgramError;
 
 use crate::serialization::Error as DeserializationError;
@@ -25,7 +26,7 @@ pub enum Error {
     KeyPairIsNotInKeys,
     TransactionCreationFailed(String),
     SplTokenError(spl_token::error::TokenError),
-    TokenAccountNotFound,
+    TokenAccountNotFound(String),
     TokenMintNotFound,
     ProgramError(ProgramError),
 
@@ -35,8 +36,265 @@ pub enum Error {
     DeactivationFailed(String),
     WithdrawalFailed(String),
     InvalidPublicKey(String),
+    InvalidBlockHash(String),
     InsufficientBalance(String),
     BalanceCheckFailed(String),
+    InvalidNonceAccount(String),
+    VoteAccountsFailed(ClientError),
+    InvalidStakeAccount(String),
+    EpochInfoFailed(ClientError),
+    InflationRewardFailed(ClientError),
+    TokenAccountAlreadyExists(String),
+    TokenAccountsFailed(ClientError),
+    RpcTimeout(ClientError),
+    RpcUnhealthy(ClientError),
+    InvalidConfig(String),
+    IdempotencyKeyInUse(String),
+    InvalidAmount(f64),
+    AmountPrecisionLoss {
+        amount: f64,
+        decimals: u8,
+    },
+    InvalidRequest(String),
+    SubscriptionFailed(String),
+    MemoTooLong {
+        len: usize,
+        max: usize,
+    },
+    Unauthorized,
+    RateLimited(u64),
+    MainnetDisabled,
+    InvalidPartialSignatures(Vec<String>),
+    InflationRateFailed(ClientError),
+    RentExemptionFailed(ClientError),
+    AirdropRateLimited(ClientError),
+    ConfirmationTimeout(String),
+    AccountInfoFailed(ClientError),
+    InvalidJson(String),
+    PayloadTooLarge(usize),
+    TokenAccountNotEmpty {
+        account: String,
+        amount: u64,
+    },
+    AggSessionNotFound(String),
+    AggSessionClosed(String),
+    SerializationVersionMismatch {
+        found: u8,
+        supported: u8,
+    },
+    FirstMessagesMismatch(String),
+    InvalidFields(Vec<(String, Error)>),
+    SimulationFailed(ClientError),
+    TransactionWouldFail(String),
+    InvalidTokenMetadata(String),
+    SupplyFailed(ClientError),
+    BlockhashExpired(String),
+    VanityNotFound(u64),
+    Internal(String),
+    TransactionTooLarge {
+        size: usize,
+        max: usize,
+    },
+    MainnetNotConfirmed,
+    IdempotencyKeyReused(String),
+    FeeLookupFailed(ClientError),
+}
+
+impl Error {
+    /// Machine-readable code for the error, stable across wording changes of the message
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::WrongNetwork(_) => "WRONG_NETWORK",
+            Self::BadBase58(_) => "BAD_BASE58",
+            Self::WrongKeyPair(_) => "WRONG_KEYPAIR",
+            Self::AirdropFailed(_) => "AIRDROP_FAILED",
+            Self::RecentHashFailed(_) => "RECENT_HASH_FAILED",
+            Self::ConfirmingTransactionFailed(_) => "CONFIRM_FAILED",
+            Self::BalaceFailed(_) => "BALANCE_FAILED",
+            Self::SendTransactionFailed(_) => "SEND_FAILED",
+            Self::DeserializationFailed { .. } => "DESERIALIZATION_FAILED",
+            Self::MismatchMessages => "MISMATCH_MESSAGES",
+            Self::InvalidSignature => "INVALID_SIGNATURE",
+            Self::KeyPairIsNotInKeys => "KEYPAIR_NOT_IN_KEYS",
+            Self::TransactionCreationFailed(_) => "TRANSACTION_CREATION_FAILED",
+            Self::SplTokenError(_) => "SPL_TOKEN_ERROR",
+            Self::TokenAccountNotFound(_) => "TOKEN_ACCOUNT_NOT_FOUND",
+            Self::TokenMintNotFound => "TOKEN_MINT_NOT_FOUND",
+            Self::ProgramError(_) => "PROGRAM_ERROR",
+
+            Self::StakeAccountCreationFailed(_) => "STAKE_ACCOUNT_CREATION_FAILED",
+            Self::InvalidStakeAccountSeed(_) => "INVALID_STAKE_ACCOUNT_SEED",
+            Self::StakeDelegationFailed(_) => "STAKE_DELEGATION_FAILED",
+            Self::DeactivationFailed(_) => "DEACTIVATION_FAILED",
+            Self::WithdrawalFailed(_) => "WITHDRAWAL_FAILED",
+            Self::InvalidPublicKey(_) => "INVALID_PUBLIC_KEY",
+            Self::InvalidBlockHash(_) => "INVALID_BLOCK_HASH",
+            Self::InsufficientBalance(_) => "INSUFFICIENT_BALANCE",
+            Self::BalanceCheckFailed(_) => "BALANCE_CHECK_FAILED",
+            Self::InvalidNonceAccount(_) => "INVALID_NONCE_ACCOUNT",
+            Self::VoteAccountsFailed(_) => "VOTE_ACCOUNTS_FAILED",
+            Self::InvalidStakeAccount(_) => "INVALID_STAKE_ACCOUNT",
+            Self::EpochInfoFailed(_) => "EPOCH_INFO_FAILED",
+            Self::InflationRewardFailed(_) => "INFLATION_REWARD_FAILED",
+            Self::TokenAccountAlreadyExists(_) => "TOKEN_ACCOUNT_ALREADY_EXISTS",
+            Self::TokenAccountsFailed(_) => "TOKEN_ACCOUNTS_FAILED",
+            Self::RpcTimeout(_) => "RPC_TIMEOUT",
+            Self::RpcUnhealthy(_) => "RPC_UNHEALTHY",
+            Self::InvalidConfig(_) => "INVALID_CONFIG",
+            Self::IdempotencyKeyInUse(_) => "IDEMPOTENCY_KEY_IN_USE",
+            Self::InvalidAmount(_) => "INVALID_AMOUNT",
+            Self::AmountPrecisionLoss { .. } => "AMOUNT_PRECISION_LOSS",
+            Self::InvalidRequest(_) => "INVALID_REQUEST",
+            Self::SubscriptionFailed(_) => "SUBSCRIPTION_FAILED",
+            Self::MemoTooLong { .. } => "MEMO_TOO_LONG",
+            Self::Unauthorized => "UNAUTHORIZED",
+            Self::RateLimited(_) => "RATE_LIMITED",
+            Self::MainnetDisabled => "MAINNET_DISABLED",
+            Self::InvalidPartialSignatures(_) => "INVALID_PARTIAL_SIGNATURES",
+            Self::InflationRateFailed(_) => "INFLATION_RATE_FAILED",
+            Self::RentExemptionFailed(_) => "RENT_EXEMPTION_FAILED",
+            Self::AirdropRateLimited(_) => "AIRDROP_RATE_LIMITED",
+            Self::ConfirmationTimeout(_) => "CONFIRMATION_TIMEOUT",
+            Self::AccountInfoFailed(_) => "ACCOUNT_INFO_FAILED",
+            Self::InvalidJson(_) => "INVALID_JSON",
+            Self::PayloadTooLarge(_) => "PAYLOAD_TOO_LARGE",
+            Self::TokenAccountNotEmpty { .. } => "TOKEN_ACCOUNT_NOT_EMPTY",
+            Self::AggSessionNotFound(_) => "AGG_SESSION_NOT_FOUND",
+            Self::AggSessionClosed(_) => "AGG_SESSION_CLOSED",
+            Self::SerializationVersionMismatch { .. } => "SERIALIZATION_VERSION_MISMATCH",
+            Self::FirstMessagesMismatch(_) => "FIRST_MESSAGES_MISMATCH",
+            Self::InvalidFields(_) => "INVALID_FIELDS",
+            Self::SimulationFailed(_) => "SIMULATION_FAILED",
+            Self::TransactionWouldFail(_) => "TRANSACTION_WOULD_FAIL",
+            Self::InvalidTokenMetadata(_) => "INVALID_TOKEN_METADATA",
+            Self::SupplyFailed(_) => "SUPPLY_FAILED",
+            Self::BlockhashExpired(_) => "BLOCKHASH_EXPIRED",
+            Self::VanityNotFound(_) => "VANITY_NOT_FOUND",
+            Self::Internal(_) => "INTERNAL_ERROR",
+            Self::TransactionTooLarge { .. } => "TRANSACTION_TOO_LARGE",
+            Self::MainnetNotConfirmed => "MAINNET_NOT_CONFIRMED",
+            Self::IdempotencyKeyReused(_) => "IDEMPOTENCY_KEY_REUSED",
+            Self::FeeLookupFailed(_) => "FEE_LOOKUP_FAILED",
+        }
+    }
+
+    /// Replace an RPC failure caused by the client timeout with `RpcTimeout`
+    pub fn detect_rpc_timeout(self) -> Self {
+        match self {
+            Self::AirdropFailed(e)
+            | Self::RecentHashFailed(e)
+            | Self::ConfirmingTransactionFailed(e)
+            | Self::BalaceFailed(e)
+            | Self::SendTransactionFailed(e)
+            | Self::VoteAccountsFailed(e)
+            | Self::EpochInfoFailed(e)
+            | Self::InflationRewardFailed(e)
+            | Self::InflationRateFailed(e)
+            | Self::RentExemptionFailed(e)
+            | Self::AccountInfoFailed(e)
+            | Self::TokenAccountsFailed(e)
+            | Self::SimulationFailed(e)
+            | Self::SupplyFailed(e)
+            | Self::FeeLookupFailed(e)
+                if is_timeout(&e) =>
+            {
+                Self::RpcTimeout(e)
+            }
+            other => other,
+        }
+    }
+
+    /// HTTP status to report: bad input is 400, upstream RPC trouble 502/504
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            Self::AirdropFailed(_)
+            | Self::RecentHashFailed(_)
+            | Self::BalaceFailed(_)
+            | Self::SendTransactionFailed(_)
+            | Self::StakeAccountCreationFailed(_)
+            | Self::BalanceCheckFailed(_)
+            | Self::VoteAccountsFailed(_)
+            | Self::EpochInfoFailed(_)
+            | Self::InflationRewardFailed(_)
+            | Self::TokenAccountsFailed(_)
+            | Self::SubscriptionFailed(_)
+            | Self::InflationRateFailed(_)
+            | Self::RentExemptionFailed(_)
+            | Self::AccountInfoFailed(_)
+            | Self::SimulationFailed(_)
+            | Self::SupplyFailed(_)
+            | Self::FeeLookupFailed(_) => StatusCode::BAD_GATEWAY,
+            Self::ConfirmingTransactionFailed(_)
+            | Self::RpcTimeout(_)
+            | Self::ConfirmationTimeout(_) => StatusCode::GATEWAY_TIMEOUT,
+            Self::TokenAccountNotFound(_)
+            | Self::TokenMintNotFound
+            | Self::AggSessionNotFound(_) => StatusCode::NOT_FOUND,
+            Self::InsufficientBalance(_)
+            | Self::StakeDelegationFailed(_)
+            | Self::DeactivationFailed(_)
+            | Self::WithdrawalFailed(_)
+            | Self::TokenAccountNotEmpty { .. }
+            | Self::TransactionWouldFail(_)
+            | Self::InvalidTokenMetadata(_)
+            | Self::VanityNotFound(_)
+            | Self::IdempotencyKeyReused(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            Self::WrongNetwork(_)
+            | Self::BadBase58(_)
+            | Self::WrongKeyPair(_)
+            | Self::DeserializationFailed { .. }
+            | Self::MismatchMessages
+            | Self::InvalidSignature
+            | Self::KeyPairIsNotInKeys
+            | Self::TransactionCreationFailed(_)
+            | Self::SplTokenError(_)
+            | Self::ProgramError(_)
+            | Self::InvalidStakeAccountSeed(_)
+            | Self::InvalidPublicKey(_)
+            | Self::InvalidBlockHash(_)
+            | Self::InvalidNonceAccount(_)
+            | Self::InvalidStakeAccount(_)
+            | Self::InvalidAmount(_)
+            | Self::AmountPrecisionLoss { .. }
+            | Self::InvalidRequest(_)
+            | Self::MemoTooLong { .. }
+            | Self::InvalidPartialSignatures(_)
+            | Self::InvalidJson(_)
+            | Self::SerializationVersionMismatch { .. }
+            | Self::FirstMessagesMismatch(_)
+            | Self::InvalidFields(_)
+            | Self::TransactionTooLarge { .. }
+            | Self::MainnetNotConfirmed => StatusCode::BAD_REQUEST,
+            Self::TokenAccountAlreadyExists(_)
+            | Self::IdempotencyKeyInUse(_)
+            | Self::BlockhashExpired(_)
+            | Self::AggSessionClosed(_) => StatusCode::CONFLICT,
+            Self::RpcUnhealthy(_) => StatusCode::SERVICE_UNAVAILABLE,
+            Self::InvalidConfig(_) | Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::Unauthorized => StatusCode::UNAUTHORIZED,
+            Self::RateLimited(_) | Self::AirdropRateLimited(_) => StatusCode::TOO_MANY_REQUESTS,
+            Self::MainnetDisabled => StatusCode::FORBIDDEN,
+            Self::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+        }
+    }
+}
+
+/// The RpcClient timeout surfaces as a reqwest error
+pub fn is_timeout(e: &ClientError) -> bool {
+    matches!(e.kind(), ClientErrorKind::Reqwest(err) if err.is_timeout())
+}
+
+/// Public RPC nodes answer with HTTP 429 (or a JSON-RPC message saying so) when throttling
+pub fn is_rate_limited(e: &ClientError) -> bool {
+    match e.kind() {
+        ClientErrorKind::Reqwest(err) => err
+            .status()
+            .is_some_and(|status| status.as_u16() == StatusCode::TOO_MANY_REQUESTS.as_u16()),
+        _ => {
+            let msg = e.to_string().to_lowercase();
+            msg.contains("429") || msg.contains("too many requests") || msg.contains("rate limit")
+        }
+    }
 }
 
 impl Display for Error {
@@ -44,7 +302,7 @@ impl Display for Error {
         match self {
             Self::WrongNetwork(net) => write!(
                 f,
-                "Unrecognized network: {}, please select Mainnet/Testnet/Devnet",
+                "Unrecognized network: {}, please select mainnet (mainnet-beta, main), testnet (test) or devnet (dev)",
                 net
             ),
             Self::BadBase58(e) => write!(f, "Based58 Error: {}", e),
@@ -73,7 +331,7 @@ impl Display for Error {
                 write!(f, "Transaction creation failed: {}", msg)
             }
             Self::SplTokenError(e) => write!(f, "SPL Token error: {}", e),
-            Self::TokenAccountNotFound => write!(f, "Token account not found"),
+            Self::TokenAccountNotFound(e) => write!(f, "Token account not found: {}", e),
             Self::TokenMintNotFound => write!(f, "Token mint not found"),
             Self::ProgramError(e) => write!(f, "Program error: {}", e),
 
@@ -85,8 +343,122 @@ impl Display for Error {
             Self::DeactivationFailed(e) => write!(f, "Failed to deactivate stake: {}", e),
             Self::WithdrawalFailed(e) => write!(f, "Failed to withdraw stake: {}", e),
             Self::InvalidPublicKey(e) => write!(f, "invalid public key: {}", e),
+            Self::InvalidBlockHash(e) => write!(f, "invalid block hash: {}", e),
             Self::InsufficientBalance(e) => write!(f, "insufficient balance: {}", e),
             Self::BalanceCheckFailed(e) => write!(f, " balance check fail: {}", e),
+            Self::InvalidNonceAccount(e) => write!(f, "invalid nonce account: {}", e),
+            Self::VoteAccountsFailed(e) => write!(f, "Failed fetching vote accounts: {}", e),
+            Self::InvalidStakeAccount(e) => write!(f, "invalid stake account: {}", e),
+            Self::EpochInfoFailed(e) => write!(f, "Failed fetching epoch info: {}", e),
+            Self::InflationRewardFailed(e) => write!(f, "Failed fetching inflation rewards: {}", e),
+            Self::TokenAccountAlreadyExists(e) => write!(f, "Token account already exists: {}", e),
+            Self::TokenAccountsFailed(e) => write!(f, "Failed fetching token accounts: {}", e),
+            Self::RpcTimeout(e) => write!(f, "RPC request timed out: {}", e),
+            Self::RpcUnhealthy(e) => write!(f, "RPC node is unhealthy: {}", e),
+            Self::InvalidConfig(e) => write!(f, "invalid configuration: {}", e),
+            Self::IdempotencyKeyInUse(key) => write!(
+                f,
+                "a request with idempotency key {} is still in progress",
+                key
+            ),
+            Self::InvalidAmount(amount) => write!(
+                f,
+                "Invalid amount {}, must be a positive finite number within range",
+                amount
+            ),
+            Self::AmountPrecisionLoss { amount, decimals } => write!(
+                f,
+                "Amount {} with {} decimals can not be represented exactly, use amount_base_units instead",
+                amount, decimals
+            ),
+            Self::InvalidRequest(e) => write!(f, "Invalid request: {}", e),
+            Self::SubscriptionFailed(e) => write!(f, "Failed subscribing to the RPC node: {}", e),
+            Self::MemoTooLong { len, max } => {
+                write!(f, "Memo is {} bytes, at most {} are allowed", len, max)
+            }
+            Self::Unauthorized => write!(f, "Missing or invalid API key"),
+            Self::RateLimited(retry_after) => {
+                write!(f, "Too many requests, retry in {} seconds", retry_after)
+            }
+            Self::MainnetDisabled => write!(f, "mainnet disabled in safe mode"),
+            Self::InvalidPartialSignatures(signers) => {
+                write!(f, "Invalid partial signatures from: {}", signers.join(", "))
+            }
+            Self::InflationRateFailed(e) => write!(f, "Failed fetching inflation rate: {}", e),
+            Self::RentExemptionFailed(e) => {
+                write!(f, "Failed fetching the rent exemption minimum: {}", e)
+            }
+            Self::AirdropRateLimited(e) => write!(
+                f,
+                "Airdrop rate limited by the faucet, wait a few minutes before retrying or request a smaller amount: {}",
+                e
+            ),
+            Self::ConfirmationTimeout(sig) => write!(
+                f,
+                "Transaction {} was not confirmed in time, it may still land, check its status later",
+                sig
+            ),
+            Self::AccountInfoFailed(e) => write!(f, "Failed fetching account: {}", e),
+            Self::InvalidJson(e) => write!(f, "Invalid JSON body: {}", e),
+            Self::PayloadTooLarge(max) => write!(f, "Request body is larger than {} bytes", max),
+            Self::TokenAccountNotEmpty { account, amount } => write!(
+                f,
+                "Token account {} still holds {} base units, transfer or burn them before closing",
+                account, amount
+            ),
+            Self::AggSessionNotFound(id) => {
+                write!(f, "Signing session not found or expired: {}", id)
+            }
+            Self::AggSessionClosed(id) => write!(
+                f,
+                "Signing session {} is no longer accepting signatures",
+                id
+            ),
+            Self::SerializationVersionMismatch { found, supported } => write!(
+                f,
+                "Messages use serialization version {}, this server supports {}, upgrade the client or the server",
+                found, supported
+            ),
+            Self::FirstMessagesMismatch(e) => write!(f, "first_messages do not match keys: {}", e),
+            Self::InvalidFields(fields) => {
+                let fields: Vec<String> = fields
+                    .iter()
+                    .map(|(field, e)| format!("{}: {}", field, e))
+                    .collect();
+                write!(f, "Invalid fields: {}", fields.join("; "))
+            }
+            Self::SimulationFailed(e) => write!(f, "Failed to simulate transaction: {}", e),
+            Self::TransactionWouldFail(e) => write!(f, "Transaction fails in simulation: {}", e),
+            Self::InvalidTokenMetadata(account) => {
+                write!(f, "{} does not hold valid Metaplex metadata", account)
+            }
+            Self::SupplyFailed(e) => write!(f, "Failed fetching the supply: {}", e),
+            Self::BlockhashExpired(hash) => write!(
+                f,
+                "Blockhash {} expired before the transaction was sent, sign again with a fresh one (aggregated transactions restart from step two)",
+                hash
+            ),
+            Self::VanityNotFound(attempts) => write!(
+                f,
+                "No matching keypair in {} attempts, try a shorter prefix, case_sensitive false or more max_attempts",
+                attempts
+            ),
+            Self::Internal(e) => write!(f, "Internal error: {}", e),
+            Self::TransactionTooLarge { size, max } => write!(
+                f,
+                "Transaction is {} bytes, at most {} fit in a packet",
+                size, max
+            ),
+            Self::MainnetNotConfirmed => write!(
+                f,
+                "mainnet operations must be explicitly confirmed with \"confirm_mainnet\": true"
+            ),
+            Self::IdempotencyKeyReused(key) => write!(
+                f,
+                "idempotency key {} was already used for a different request",
+                key
+            ),
+            Self::FeeLookupFailed(e) => write!(f, "Failed looking up the fee: {}", e),
         }
     }
 }
@@ -0,0 +1,194 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use sha2::{Digest, Sha256};
+
+/// Alternative to the `idempotency_key` body field
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+
+/// Upper bound on remembered keys, the least recently used completed ones are dropped first
+const MAX_ENTRIES: usize = 10_000;
+
+#[derive(Debug, Clone, PartialEq)]
+enum State {
+    Pending,
+    Done(String),
+}
+
+#[derive(Debug)]
+struct Entry {
+    state: State,
+    /// Of the request that took the key, a retry has to send the same one
+    fingerprint: Vec<u8>,
+    created: Instant,
+    last_used: Instant,
+}
+
+/// Remembers which transaction was sent for an idempotency key, so a retried
+/// request gets the original transaction id instead of sending it again
+#[derive(Debug)]
+pub struct IdempotencyStore {
+    entries: Mutex<HashMap<String, Entry>>,
+    ttl: Duration,
+}
+
+pub enum Reserve<'a> {
+    /// First time this key is seen, the caller owns it until the reservation is dropped
+    Acquired(Reservation<'a>),
+    /// A previous request with this key already sent a transaction
+    Completed(String),
+    /// Another request with this key is still being processed
+    InProgress,
+    /// The key was taken by a request with a different body
+    Mismatch,
+}
+
+/// Releases the key on drop unless `complete` was called, so failed requests can be retried
+pub struct Reservation<'a> {
+    store: &'a IdempotencyStore,
+    key: String,
+    completed: bool,
+}
+
+impl IdempotencyStore {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            entries: Mutex::new(HashMap::new()),
+            ttl,
+        }
+    }
+
+    /// Takes `key` for `request`, the same key with another request is refused
+    pub fn reserve<T: serde::Serialize>(&self, key: &str, request: &T) -> Reserve<'_> {
+        let fingerprint = fingerprint(request);
+        let mut entries = self.entries.lock().unwrap();
+        let now = Instant::now();
+        entries.retain(|_, entry| now.duration_since(entry.created) < self.ttl);
+
+        if let Some(entry) = entries.get_mut(key) {
+            if entry.fingerprint != fingerprint {
+                return Reserve::Mismatch;
+            }
+            entry.last_used = now;
+            return match &entry.state {
+                State::Done(transaction_id) => Reserve::Completed(transaction_id.clone()),
+                State::Pending => Reserve::InProgress,
+            };
+        }
+
+        if entries.len() >= MAX_ENTRIES {
+            let least_recent = entries
+                .iter()
+                .filter(|(_, entry)| entry.state != State::Pending)
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone());
+            if let Some(least_recent) = least_recent {
+                entries.remove(&least_recent);
+            }
+        }
+        entries.insert(
+            key.to_string(),
+            Entry {
+                state: State::Pending,
+                fingerprint,
+                created: now,
+                last_used: now,
+            },
+        );
+
+        Reserve::Acquired(Reservation {
+            store: self,
+            key: key.to_string(),
+            completed: false,
+        })
+    }
+}
+
+/// Hash of a request body without its idempotency key, which may come as a header instead
+fn fingerprint<T: serde::Serialize>(request: &T) -> Vec<u8> {
+    let mut value = serde_json::to_value(request).unwrap_or_default();
+    if let Some(fields) = value.as_object_mut() {
+        fields.remove("idempotency_key");
+    }
+    Sha256::digest(value.to_string().as_bytes()).to_vec()
+}
+
+impl Reservation<'_> {
+    pub fn complete(mut self, transaction_id: String) {
+        let mut entries = self.store.entries.lock().unwrap();
+        if let Some(entry) = entries.get_mut(&self.key) {
+            entry.state = State::Done(transaction_id);
+        }
+        self.completed = true;
+    }
+}
+
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        if !self.completed {
+            self.store.entries.lock().unwrap().remove(&self.key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::idempotency::{IdempotencyStore, Reserve, fingerprint};
+
+    #[test]
+    fn test_completed_key_returns_transaction_id() {
+        let store = IdempotencyStore::new(Duration::from_secs(60));
+        let Reserve::Acquired(reservation) = store.reserve("key", &"body") else {
+            panic!("first use of a key should be acquired");
+        };
+        assert!(matches!(store.reserve("key", &"body"), Reserve::InProgress));
+
+        reservation.complete("sig".to_string());
+        assert!(matches!(store.reserve("key", &"body"), Reserve::Completed(id) if id == "sig"));
+    }
+
+    #[test]
+    fn test_dropped_reservation_releases_key() {
+        let store = IdempotencyStore::new(Duration::from_secs(60));
+        drop(store.reserve("key", &"body"));
+        assert!(matches!(
+            store.reserve("key", &"body"),
+            Reserve::Acquired(_)
+        ));
+    }
+
+    #[test]
+    fn test_expired_key_can_be_reused() {
+        let store = IdempotencyStore::new(Duration::ZERO);
+        if let Reserve::Acquired(reservation) = store.reserve("key", &"body") {
+            reservation.complete("sig".to_string());
+        }
+        assert!(matches!(
+            store.reserve("key", &"body"),
+            Reserve::Acquired(_)
+        ));
+    }
+
+    #[test]
+    fn test_key_bound_to_request() {
+        let store = IdempotencyStore::new(Duration::from_secs(60));
+        if let Reserve::Acquired(reservation) = store.reserve("key", &"first") {
+            reservation.complete("sig".to_string());
+        }
+        assert!(matches!(store.reserve("key", &"second"), Reserve::Mismatch));
+
+        // Where the key came from doesn't matter, only the rest of the body
+        let with_key = serde_json::json!({"to": "a", "idempotency_key": "key"});
+        let without_key = serde_json::json!({"to": "a"});
+        assert_eq!(fingerprint(&with_key), fingerprint(&without_key));
+        assert_ne!(
+            fingerprint(&without_key),
+            fingerprint(&serde_json::json!({"to": "b"}))
+        );
+    }
+}
@@ -1,26 +1,75 @@
+use base64::{Engine, prelude::BASE64_STANDARD};
+use bip39::{Language, Mnemonic, Seed};
 use poem::{
-    IntoResponse, Response, Route, Server, get, handler, listener::TcpListener, post, web::Json,
+    EndpointExt, IntoResponse, Response, Route, Server,
+    error::ParseJsonError,
+    get, handler,
+    http::HeaderMap,
+    listener::TcpListener,
+    middleware::{Cors, Tracing},
+    post,
+    web::{Data, Json, Path, Query, websocket::WebSocket},
 };
 use serde_json;
-use solana_client::rpc_client::RpcClient;
+use solana_client::{
+    client_error::{ClientError, ClientErrorKind},
+    rpc_client::RpcClient,
+    rpc_request::TokenAccountsFilter,
+    rpc_response::RpcVoteAccountInfo,
+};
 use solana_sdk::{
+    account::Account as SolanaAccount,
+    derivation_path::DerivationPath,
     hash::Hash as SolanaHash,
+    instruction::{AccountMeta, Instruction},
+    message::Message,
     native_token,
+    packet::PACKET_DATA_SIZE,
     program_pack::Pack,
     pubkey::Pubkey,
-    signature::{Keypair, Signer},
+    signature::{Keypair, Signature, Signer},
+    signer::keypair::keypair_from_seed_and_derivation_path,
+    stake::state::StakeStateV2,
     transaction::Transaction,
 };
-use std::str::FromStr;
+use std::{collections::HashMap, str::FromStr, sync::Arc, time::Duration};
+use tracing_subscriber::{EnvFilter, fmt::format::FmtSpan};
 
 use crate::{
+    agg_session::AggSession,
+    auto_withdraw::ScheduledWithdrawal,
+    config::Config,
     error::Error,
+    idempotency::{IDEMPOTENCY_KEY_HEADER, Reserve},
+    metrics::{ErrorCode, Metrics},
+    middleware::{
+        ApiKeyAuth, AssignRequestId, BodyLimit, RateLimit, RequestId, RequestMetrics,
+        ResponseEnvelope, current_request_id, wants_envelope,
+    },
     models::*,
-    serialization::{AggMessage1, PartialSignature, SecretAggStepOne, Serialize},
+    nonce::{
+        NonceParams, create_nonce_account_transaction, get_nonce_blockhash, get_nonce_data,
+        nonce_account_rent,
+    },
+    rpc_utils::{
+        Confirmation, DEFAULT_CONFIRM_TIMEOUT, DEFAULT_MAX_RETRIES, check_blockhash_valid,
+        confirm_signatures, confirm_transaction, get_block_height, get_fee_for_message,
+        get_landed_slot, get_latest_blockhash_with_expiry, is_blockhash_valid, request_airdrop,
+        send_outcome_unknown, simulate_compute_units, split_airdrop,
+    },
+    serialization::{
+        AggMessage1, FieldError, PartialSignature, SERIALIZATION_VERSION, SecretAggStepOne,
+        Serialize, SigningPackage, build_and_measure, deserialize_transaction_base64,
+        deserialize_transaction_bs58,
+    },
+    solana_pay::{SOL_DECIMALS, TransferRequest},
     staking::{
+        check_minimum_delegation, check_stake_seed, check_withdrawable,
         create_deactivate_stake_transaction, create_stake_account_transaction,
-        create_withdraw_stake_transaction,
+        create_withdraw_stake_transaction, get_closable, get_stake_account, get_withdrawable,
+        select_validators, stake_account_address,
     },
+    state::AppState,
     tss::{
         aggregate_deactivate_stake_signatures_and_broadcast,
         aggregate_stake_signatures_and_broadcast,
@@ -38,529 +87,3424 @@ use crate::{
         SplAggregateSignaturesResponse, SplSendSingleRequest, SplSendSingleResponse,
         SplTokenBalanceRequest, SplTokenBalanceResponse,
     },
-    spl_token_utils::create_spl_token_transaction,
+    spl_token_utils::{
+        approve_transaction, check_source_account, close_token_account_transaction,
+        create_associated_token_account_transaction, create_spl_token_transaction,
+        current_authority, get_associated_token_address, get_metadata_address,
+        get_optional_account, get_token_amount_with_decimals, parse_metadata, resolve_mint,
+        resolve_token_program, revoke_transaction, set_authority_transaction,
+        spl_token_transfer_instructions, unpack_mint, unpack_token_account,
+    },
 };
-use spl_associated_token_account::get_associated_token_address;
+mod agg_session;
+mod auto_withdraw;
+mod blockhash_cache;
+mod config;
 mod error;
+mod idempotency;
+mod metrics;
+mod middleware;
 mod models;
+mod nonce;
+mod openapi;
+mod rpc_utils;
 mod serialization;
+mod solana_pay;
 mod spl_token_utils;
 mod staking;
+mod state;
 mod tss;
+mod vanity;
+mod ws;
+
+/// A plain transfer appended to the transaction, e.g. to a Jito tip account.
+/// It is signed along with the rest, so it can't be stripped by whoever relays it
+#[derive(Debug, Clone, Copy)]
+pub struct Tip {
+    pub account: Pubkey,
+    pub lamports: u64,
+}
+
+/// Memo instructions placed around the transfer, one instruction per memo in order
+#[derive(Debug, Clone, Default)]
+pub struct Memos {
+    pub before: Vec<String>,
+    pub after: Vec<String>,
+}
+
+impl Memos {
+    /// The single `memo` goes first, then `memos`, all on the `position` side of the transfer
+    pub fn new(memo: Option<String>, memos: Option<Vec<String>>, position: MemoPosition) -> Self {
+        let all = memo.into_iter().chain(memos.unwrap_or_default()).collect();
+        match position {
+            MemoPosition::Before => Self {
+                before: all,
+                after: Vec::new(),
+            },
+            MemoPosition::After => Self {
+                before: Vec::new(),
+                after: all,
+            },
+        }
+    }
+
+    /// Combined size of every memo in bytes
+    pub fn total_bytes(&self) -> usize {
+        self.before.iter().chain(&self.after).map(String::len).sum()
+    }
+}
+
+/// A lone memo after the transfer, the layout every signer of an aggregated transfer rebuilds
+impl From<Option<String>> for Memos {
+    fn from(memo: Option<String>) -> Self {
+        Self::new(memo, None, MemoPosition::After)
+    }
+}
+
+fn memo_instruction(memo: &str) -> Instruction {
+    Instruction {
+        program_id: spl_memo::id(),
+        accounts: Vec::new(),
+        data: memo.as_bytes().to_vec(),
+    }
+}
+
+/// The transfer of `amount` lamports from `from` between its memos, followed by the tip if any
+fn transfer_instructions(
+    amount: u64,
+    to: &Pubkey,
+    memos: &Memos,
+    from: &Pubkey,
+    tip: Option<&Tip>,
+) -> Vec<Instruction> {
+    let mut instructions: Vec<Instruction> = memos
+        .before
+        .iter()
+        .map(|memo| memo_instruction(memo))
+        .collect();
+    instructions.push(solana_sdk::system_instruction::transfer(from, to, amount));
+    instructions.extend(memos.after.iter().map(|memo| memo_instruction(memo)));
+    if let Some(tip) = tip {
+        instructions.push(solana_sdk::system_instruction::transfer(
+            from,
+            &tip.account,
+            tip.lamports,
+        ));
+    }
+    instructions
+}
 
+/// `amount` is in lamports. Pass a nonce to use a durable nonce instead of a recent blockhash
 pub fn create_unsigned_transaction(
-    amount: f64,
+    amount: u64,
     to: &Pubkey,
-    memo: Option<String>,
+    memos: &Memos,
     payer: &Pubkey,
+    nonce: Option<&NonceParams>,
+    tip: Option<&Tip>,
 ) -> Transaction {
-    let amount = native_token::sol_to_lamports(amount);
-    let transfer_ins = solana_sdk::system_instruction::transfer(payer, to, amount);
-    let msg = match memo {
-        None => solana_sdk::message::Message::new(&[transfer_ins], Some(payer)),
-        Some(memo) => {
-            let memo_ins = solana_sdk::instruction::Instruction {
-                program_id: spl_memo::id(),
-                accounts: Vec::new(),
-                data: memo.into_bytes(),
-            };
-            solana_sdk::message::Message::new(&[transfer_ins, memo_ins], Some(payer))
-        }
-    };
+    let instructions = transfer_instructions(amount, to, memos, payer, tip);
+    let msg = crate::nonce::new_message(instructions, payer, nonce);
     Transaction::new_unsigned(msg)
 }
 
+/// Unsigned transaction for `message`, refused up front when it can't fit in a packet
+fn build_transaction(message: Message) -> Result<Transaction, Error> {
+    let (tx, size) = build_and_measure(message);
+    if size > PACKET_DATA_SIZE {
+        return Err(Error::TransactionTooLarge {
+            size,
+            max: PACKET_DATA_SIZE,
+        });
+    }
+    Ok(tx)
+}
+
+/// Signs `tx` over `recent_hash` and sends it. When the node doesn't know that blockhash the
+/// transaction is signed again over a fresh one and sent once more. This needs every signer at
+/// hand, aggregated signatures cover the blockhash and can't be redone here. Returns the
+/// blockhash that was finally used, confirmation has to wait on that one.
+async fn send_with_blockhash_retry<F: Future<Output = Result<SolanaHash, Error>>>(
+    tx: &mut Transaction,
+    signers: &[&Keypair],
+    recent_hash: SolanaHash,
+    fresh_blockhash: impl FnOnce() -> F,
+    mut send: impl FnMut(&Transaction) -> Result<Signature, Error>,
+) -> Result<(Signature, SolanaHash), Error> {
+    tx.sign(signers, recent_hash);
+    match send(tx) {
+        Err(Error::BlockhashExpired(stale)) => {
+            let fresh = fresh_blockhash().await?;
+            tracing::warn!(
+                "Blockhash {} not found, signing again over {}",
+                stale,
+                fresh
+            );
+            tx.sign(signers, fresh);
+            send(tx).map(|sig| (sig, fresh))
+        }
+        result => result.map(|sig| (sig, recent_hash)),
+    }
+}
+
+/// Make sure `sender` can cover `amount` and the fee payer of `tx` its fee. When they are the
+/// same account it has to cover both. The blockhash of `tx` must already be set
+fn check_sufficient_balance(
+    rpc_client: &RpcClient,
+    sender: &Pubkey,
+    tx: &Transaction,
+    amount: u64,
+) -> Result<(), Error> {
+    let fee = rpc_client
+        .get_fee_for_message(&tx.message)
+        .map_err(|e| Error::BalanceCheckFailed(e.to_string()))?;
+
+    let fee_payer = tx.message.account_keys[0];
+    if fee_payer == *sender {
+        return require_balance(
+            rpc_client,
+            sender,
+            amount.saturating_add(fee),
+            &format!("{} transfer + {} fee", amount, fee),
+        );
+    }
+    require_balance(rpc_client, sender, amount, &format!("{} transfer", amount))?;
+    require_balance(rpc_client, &fee_payer, fee, &format!("{} fee", fee))
+}
+
+fn require_balance(
+    rpc_client: &RpcClient,
+    account: &Pubkey,
+    required: u64,
+    breakdown: &str,
+) -> Result<(), Error> {
+    let balance = rpc_client
+        .get_balance(account)
+        .map_err(|e| Error::BalanceCheckFailed(e.to_string()))?;
+    if balance < required {
+        return Err(Error::InsufficientBalance(format!(
+            "{} has {} lamports but needs {} ({}), short by {}",
+            account,
+            balance,
+            required,
+            breakdown,
+            required - balance
+        )));
+    }
+    Ok(())
+}
+
+/// Validates `amount_percent`, which replaces amount_sol and amount_lamports
+fn check_amount_percent(
+    percent: f64,
+    amount_sol: Option<f64>,
+    amount_lamports: Option<u64>,
+) -> Result<(), Error> {
+    if amount_sol.is_some() || amount_lamports.is_some() {
+        return Err(Error::InvalidRequest(
+            "give either amount_percent or an amount, not both".to_string(),
+        ));
+    }
+    if !(percent > 0.0 && percent <= 100.0) {
+        return Err(Error::InvalidRequest(
+            "amount_percent must be above 0 and at most 100".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// `percent` of `spendable` lamports, rounded down so 100% never overshoots
+fn percent_of(spendable: u64, percent: f64) -> u64 {
+    ((spendable as f64 * percent / 100.0).floor() as u64).min(spendable)
+}
+
+/// What a wallet without data has to keep to stay rent exempt, below it (and above zero) the
+/// runtime rejects the transaction that leaves it there
+fn basic_rent_minimum(rpc_client: &RpcClient) -> Result<u64, Error> {
+    rpc_client
+        .get_minimum_balance_for_rent_exemption(0)
+        .map_err(Error::RentExemptionFailed)
+}
+
+/// `percent` of what `sender` can spare. The sender keeps the rent-exempt minimum of an empty
+/// account, plus the fee of `message` when it pays it and `extra` (the tip) on top.
+fn percent_of_balance(
+    rpc_client: &RpcClient,
+    sender: &Pubkey,
+    message: &Message,
+    extra: u64,
+    percent: f64,
+) -> Result<u64, Error> {
+    let balance = rpc_client
+        .get_balance(sender)
+        .map_err(|e| Error::BalanceCheckFailed(e.to_string()))?;
+    let rent_minimum = basic_rent_minimum(rpc_client)?;
+    let fee = if message.account_keys[0] == *sender {
+        rpc_client
+            .get_fee_for_message(message)
+            .map_err(|e| Error::BalanceCheckFailed(e.to_string()))?
+    } else {
+        0
+    };
+
+    let reserve = rent_minimum.saturating_add(fee).saturating_add(extra);
+    let amount = percent_of(balance.saturating_sub(reserve), percent);
+    if amount == 0 {
+        return Err(Error::InsufficientBalance(format!(
+            "{} has {} lamports, nothing is left to send after keeping {} ({} rent-exempt minimum + {} fee + {} tip)",
+            sender, balance, reserve, rent_minimum, fee, extra
+        )));
+    }
+    Ok(amount)
+}
+
 fn parse_keypair_bs58(s: &str) -> Result<Keypair, Error> {
     let decoded = bs58::decode(s).into_vec()?;
     Ok(Keypair::from_bytes(&decoded)?)
 }
 
+/// The 64 byte keypair, secret then public key, in `format`
+fn encode_keypair(keypair: &Keypair, format: KeyFormat) -> String {
+    match format {
+        KeyFormat::Base58 => keypair.to_base58_string(),
+        KeyFormat::JsonArray => serde_json::to_string(&keypair.to_bytes()[..]).unwrap_or_default(),
+        KeyFormat::Hex => hex::encode(keypair.to_bytes()),
+    }
+}
+
+// Both pubkeys and hashes are 32 bytes of base58, so the underlying reason is the same
+fn describe_bad_32_byte_base58(s: &str) -> String {
+    match bs58::decode(s).into_vec() {
+        Err(e) => format!("invalid base58: {}", e),
+        Ok(bytes) => format!(
+            "invalid base58 length: expected 32 bytes, found {}",
+            bytes.len()
+        ),
+    }
+}
+
 fn parse_pubkey(s: &str) -> Result<Pubkey, Error> {
-    Pubkey::from_str(s).map_err(|_| {
-        Error::BadBase58(bs58::decode::Error::InvalidCharacter {
-            character: ' ',
-            index: 0,
+    Pubkey::from_str(s).map_err(|_| Error::InvalidPublicKey(describe_bad_32_byte_base58(s)))
+}
+
+/// Run first in every handler that moves funds, so mainnet can't be reached in safe mode
+fn check_safe_mode(config: &Config, net: Network) -> Result<(), Error> {
+    if config.safe_mode && net == Network::Mainnet {
+        return Err(Error::MainnetDisabled);
+    }
+    Ok(())
+}
+
+/// Unlike safe mode this doesn't turn mainnet off, each request has to opt in to it
+fn check_mainnet_confirmed(net: Network, options: &SendOptions) -> Result<(), Error> {
+    if net == Network::Mainnet && !options.confirm_mainnet {
+        return Err(Error::MainnetNotConfirmed);
+    }
+    Ok(())
+}
+
+fn parse_amount(amount: f64) -> Result<f64, Error> {
+    if amount.is_finite() && amount > 0.0 {
+        Ok(amount)
+    } else {
+        Err(Error::InvalidAmount(amount))
+    }
+}
+
+/// Well above the total supply, anything larger is a mistake rather than a transfer
+const MAX_SOL_AMOUNT: f64 = 1e10;
+
+/// `native_token::sol_to_lamports` saturates silently on a huge amount, so reject it first
+fn sol_to_lamports(amount_sol: f64) -> Result<u64, Error> {
+    let amount_sol = parse_amount(amount_sol)?;
+    if amount_sol > MAX_SOL_AMOUNT {
+        return Err(Error::InvalidAmount(amount_sol));
+    }
+    Ok(native_token::sol_to_lamports(amount_sol))
+}
+
+/// Exactly one unit has to be given, SOL amounts are converted to lamports
+fn resolve_lamports(amount_sol: Option<f64>, amount_lamports: Option<u64>) -> Result<u64, Error> {
+    let lamports = match (amount_sol, amount_lamports) {
+        (Some(_), Some(_)) => {
+            return Err(Error::InvalidRequest(
+                "give either amount_sol or amount_lamports, not both".to_string(),
+            ));
+        }
+        (None, None) => {
+            return Err(Error::InvalidRequest(
+                "amount_sol or amount_lamports is required".to_string(),
+            ));
+        }
+        (Some(sol), None) => sol_to_lamports(sol)?,
+        (None, Some(lamports)) => lamports,
+    };
+    // Also catches SOL amounts below one lamport
+    if lamports == 0 {
+        return Err(Error::InvalidAmount(0.0));
+    }
+    Ok(lamports)
+}
+
+/// Memos are JSON strings so always valid UTF-8, only their size needs checking
+fn check_memo(memo: Option<&str>, max_memo_bytes: usize) -> Result<(), Error> {
+    match memo {
+        Some(memo) if memo.len() > max_memo_bytes => Err(Error::MemoTooLong {
+            len: memo.len(),
+            max: max_memo_bytes,
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Every memo shares one packet, so their combined size is held to the single memo limit
+fn check_memos(memos: &Memos, max_memo_bytes: usize) -> Result<(), Error> {
+    if memos.total_bytes() > max_memo_bytes {
+        return Err(Error::MemoTooLong {
+            len: memos.total_bytes(),
+            max: max_memo_bytes,
+        });
+    }
+    Ok(())
+}
+
+fn parse_instruction(raw: &RawInstruction) -> Result<Instruction, Error> {
+    let program_id = parse_pubkey(&raw.program_id)?;
+    let accounts = raw
+        .accounts
+        .iter()
+        .map(|meta| {
+            Ok(AccountMeta {
+                pubkey: parse_pubkey(&meta.pubkey)?,
+                is_signer: meta.is_signer,
+                is_writable: meta.is_writable,
+            })
         })
+        .collect::<Result<_, Error>>()?;
+    let data = BASE64_STANDARD
+        .decode(&raw.data_base64)
+        .map_err(|e| Error::InvalidRequest(format!("data_base64: {}", e)))?;
+    Ok(Instruction {
+        program_id,
+        accounts,
+        data,
     })
 }
 
+/// Collects the error of every bad field instead of stopping at the first one,
+/// so a form can point out all of them at once
+#[derive(Default)]
+struct FieldErrors(Vec<(String, Error)>);
+
+impl FieldErrors {
+    fn check<T>(&mut self, field: impl Into<String>, result: Result<T, Error>) -> Option<T> {
+        match result {
+            Ok(value) => Some(value),
+            Err(e) => {
+                self.0.push((field.into(), e));
+                None
+            }
+        }
+    }
+
+    /// Checks every item of a list field, a bad one is named by its index, e.g. `keys[2]`
+    fn check_all<T, U>(
+        &mut self,
+        field: &str,
+        items: &[T],
+        parse: impl Fn(&T) -> Result<U, Error>,
+    ) -> Option<Vec<U>> {
+        let mut parsed = Vec::with_capacity(items.len());
+        for (i, item) in items.iter().enumerate() {
+            if let Some(value) = self.check(format!("{}[{}]", field, i), parse(item)) {
+                parsed.push(value);
+            }
+        }
+        (parsed.len() == items.len()).then_some(parsed)
+    }
+
+    fn into_error(self) -> Error {
+        Error::InvalidFields(self.0)
+    }
+}
+
 fn parse_hash(s: &str) -> Result<SolanaHash, Error> {
-    SolanaHash::from_str(s).map_err(|_| {
-        Error::BadBase58(bs58::decode::Error::InvalidCharacter {
-            character: ' ',
-            index: 0,
-        })
-    })
+    SolanaHash::from_str(s).map_err(|_| Error::InvalidBlockHash(describe_bad_32_byte_base58(s)))
+}
+
+fn parse_nonce(
+    account: Option<&str>,
+    authority: Option<&str>,
+) -> Result<Option<NonceParams>, Error> {
+    let Some(account) = account else {
+        return Ok(None);
+    };
+    Ok(Some(NonceParams {
+        account: parse_pubkey(account)?,
+        authority: authority.map(parse_pubkey).transpose()?,
+    }))
+}
+
+/// Both tip fields go together, a zero tip is refused rather than silently dropped
+fn parse_tip(account: Option<&str>, lamports: Option<u64>) -> Result<Option<Tip>, Error> {
+    match (account, lamports) {
+        (None, None) => Ok(None),
+        (Some(_), Some(0)) => Err(Error::InvalidAmount(0.0)),
+        (Some(account), Some(lamports)) => Ok(Some(Tip {
+            account: parse_pubkey(account)?,
+            lamports,
+        })),
+        _ => Err(Error::InvalidRequest(
+            "tip_account and tip_lamports have to be given together".to_string(),
+        )),
+    }
+}
+
+// An explicit hash always wins, otherwise fall back to the value stored in the nonce account
+fn resolve_block_hash(
+    state: &AppState,
+    recent_block_hash: Option<&str>,
+    nonce: Option<&NonceParams>,
+    net: Option<Network>,
+) -> Result<SolanaHash, Error> {
+    match (
+        recent_block_hash,
+        nonce,
+        net.or(state.config.default_network),
+    ) {
+        (Some(hash), _, _) => parse_hash(hash),
+        (None, Some(nonce), Some(net)) => {
+            let rpc_client = state.rpc_client(net);
+            get_nonce_blockhash(&rpc_client, &nonce.account)
+        }
+        (None, _, _) => Err(Error::InvalidBlockHash(
+            "recent_block_hash is required unless nonce_account and net are provided".to_string(),
+        )),
+    }
+}
+
+// getMultipleAccounts accepts at most this many keys per call
+const MAX_MULTIPLE_ACCOUNTS: usize = 100;
+
+fn get_multiple_accounts_chunked(
+    rpc_client: &RpcClient,
+    pubkeys: &[Pubkey],
+) -> Result<Vec<Option<SolanaAccount>>, ClientError> {
+    let mut accounts = Vec::with_capacity(pubkeys.len());
+    for chunk in pubkeys.chunks(MAX_MULTIPLE_ACCOUNTS) {
+        accounts.extend(rpc_client.get_multiple_accounts(chunk)?);
+    }
+    Ok(accounts)
 }
 
 //  function to create error responses
-fn error_response(error: String) -> Response {
-    let error_resp = ErrorResponse { error };
+fn error_response(error: Error) -> Response {
+    let error = error.detect_rpc_timeout();
+    let errors = match &error {
+        Error::InvalidFields(fields) => Some(
+            fields
+                .iter()
+                .map(|(field, e)| FieldErrorResponse {
+                    field: field.clone(),
+                    code: e.code().to_string(),
+                    message: e.to_string(),
+                })
+                .collect(),
+        ),
+        _ => None,
+    };
+    let error_resp = ErrorResponse {
+        code: error.code().to_string(),
+        error: error.to_string(),
+        errors,
+        request_id: current_request_id(),
+    };
+    let body = if wants_envelope() {
+        serde_json::to_string(&Envelope::<()> {
+            success: false,
+            data: None,
+            error: Some(error_resp),
+        })
+    } else {
+        serde_json::to_string(&error_resp)
+    };
     Response::builder()
-        .status(poem::http::StatusCode::BAD_REQUEST)
+        .status(error.status_code())
         .content_type("application/json")
-        .body(serde_json::to_string(&error_resp).unwrap_or_default())
+        .extension(ErrorCode(error.code()))
+        .body(body.unwrap_or_default())
 }
 
 //  function to create success responses
 fn success_response<T: serde::Serialize>(data: T) -> Response {
+    let body = if wants_envelope() {
+        serde_json::to_string(&Envelope {
+            success: true,
+            data: Some(data),
+            error: None,
+        })
+    } else {
+        serde_json::to_string(&data)
+    };
     Response::builder()
         .status(poem::http::StatusCode::OK)
         .content_type("application/json")
-        .body(serde_json::to_string(&data).unwrap_or_default())
+        .body(body.unwrap_or_default())
+}
+
+#[handler]
+async fn health() -> impl IntoResponse {
+    let response = HealthResponse {
+        status: "ok".to_string(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+    };
+    success_response(response)
+}
+
+#[handler]
+async fn version() -> impl IntoResponse {
+    let response = VersionResponse {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        serialization_version: SERIALIZATION_VERSION,
+    };
+    success_response(response)
+}
+
+/// A client built against other message encodings gets told so, instead of failing to decode
+fn check_serialization_version(found: Option<u8>) -> Result<(), Error> {
+    match found {
+        Some(found) if found != SERIALIZATION_VERSION => Err(Error::SerializationVersionMismatch {
+            found,
+            supported: SERIALIZATION_VERSION,
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Prometheus text format
+#[handler]
+async fn prometheus_metrics(state: Data<&AppState>) -> impl IntoResponse {
+    Response::builder()
+        .status(poem::http::StatusCode::OK)
+        .content_type("text/plain; version=0.0.4")
+        .body(state.metrics.render())
+}
+
+#[handler]
+async fn ready(state: Data<&AppState>) -> impl IntoResponse {
+    if let Some(net) = state.config.ready_check_network {
+        if let Err(e) = state.rpc_client(net).get_health() {
+            return error_response(Error::RpcUnhealthy(e));
+        }
+    }
+
+    let response = ReadyResponse {
+        status: "ready".to_string(),
+        checked_network: state.config.ready_check_network,
+    };
+    success_response(response)
+}
+
+#[handler]
+fn ws_confirm(
+    websocket: WebSocket,
+    request_id: Data<&RequestId>,
+    state: Data<&AppState>,
+) -> impl IntoResponse {
+    let request_id = request_id.0.0.clone();
+    let default_network = state.config.default_network;
+    websocket.on_upgrade(move |socket| ws::confirm_session(socket, request_id, default_network))
+}
+
+#[handler]
+async fn confirm_many(req: Json<ConfirmManyRequest>, state: Data<&AppState>) -> impl IntoResponse {
+    let net = match state.config.resolve_network(req.net) {
+        Ok(net) => net,
+        Err(e) => return error_response(e),
+    };
+
+    // Invalid signatures are reported per entry instead of failing the whole batch
+    let parsed: Vec<Result<Signature, String>> = req
+        .signatures
+        .iter()
+        .map(|s| Signature::from_str(s).map_err(|e| e.to_string()))
+        .collect();
+    let valid: Vec<Signature> = parsed
+        .iter()
+        .filter_map(|p| p.as_ref().ok())
+        .copied()
+        .collect();
+
+    let timeout = req
+        .confirm_timeout_secs
+        .map_or(DEFAULT_CONFIRM_TIMEOUT, Duration::from_secs);
+    let rpc_client = state.rpc_client(net);
+    let mut confirmations = match confirm_signatures(&rpc_client, &valid, timeout) {
+        Ok(confirmations) => confirmations.into_iter(),
+        Err(e) => return error_response(e),
+    };
+
+    let statuses = req
+        .signatures
+        .iter()
+        .zip(parsed)
+        .map(|(signature, parsed)| {
+            let (status, slot, error) = match parsed.map(|_| confirmations.next()) {
+                Ok(Some(Confirmation::Confirmed { slot })) => ("confirmed", Some(slot), None),
+                Ok(Some(Confirmation::Failed { slot, error })) => {
+                    ("failed", Some(slot), Some(error))
+                }
+                Ok(Some(Confirmation::Pending) | None) => ("pending", None, None),
+                Err(e) => ("invalid", None, Some(e)),
+            };
+            SignatureConfirmation {
+                signature: signature.clone(),
+                status: status.to_string(),
+                slot,
+                error,
+            }
+        })
+        .collect();
+
+    success_response(ConfirmManyResponse { statuses })
 }
 
 #[handler]
-async fn generate_keypair() -> impl IntoResponse {
+async fn generate_keypair(query: poem::Result<Query<GenerateKeypairQuery>>) -> impl IntoResponse {
+    let format = match query {
+        Ok(query) => query.format.unwrap_or_default(),
+        Err(e) => return error_response(Error::InvalidRequest(e.to_string())),
+    };
+
     let keypair = Keypair::generate(&mut rand07::thread_rng());
     let response = GenerateKeypairResponse {
+        secret_share: encode_keypair(&keypair, format),
+        public_share: keypair.pubkey().to_string(),
+        public_share_hex: hex::encode(keypair.pubkey().to_bytes()),
+    };
+    success_response(response)
+}
+
+#[handler]
+async fn generate_vanity(req: Json<GenerateVanityRequest>) -> impl IntoResponse {
+    let case_sensitive = req.case_sensitive.unwrap_or(true);
+    if let Err(e) = vanity::check_prefix(&req.prefix, case_sensitive) {
+        return error_response(e);
+    }
+    let max_attempts = req
+        .max_attempts
+        .unwrap_or(vanity::DEFAULT_VANITY_ATTEMPTS)
+        .min(vanity::MAX_VANITY_ATTEMPTS);
+
+    // Keeps the CPU bound search off the async workers
+    let prefix = req.prefix.clone();
+    let found = match tokio::task::spawn_blocking(move || {
+        vanity::search(&prefix, case_sensitive, max_attempts)
+    })
+    .await
+    {
+        Ok(found) => found,
+        Err(e) => return error_response(Error::Internal(e.to_string())),
+    };
+    let Some((keypair, attempts)) = found else {
+        return error_response(Error::VanityNotFound(max_attempts));
+    };
+
+    let response = GenerateVanityResponse {
         secret_share: keypair.to_base58_string(),
         public_share: keypair.pubkey().to_string(),
+        attempts,
     };
     success_response(response)
 }
 
 #[handler]
-async fn balance(req: Json<BalanceRequest>) -> impl IntoResponse {
+async fn balance(req: Json<BalanceRequest>, state: Data<&AppState>) -> impl IntoResponse {
+    let net = match state.config.resolve_network(req.net) {
+        Ok(net) => net,
+        Err(e) => return error_response(e),
+    };
+
     let address = match parse_pubkey(&req.address) {
         Ok(addr) => addr,
-        Err(e) => return error_response(e.to_string()),
+        Err(e) => return error_response(e),
     };
 
-    let rpc_client = RpcClient::new(req.net.get_cluster_url().to_string());
+    let rpc_client = state.rpc_client(net);
     let balance = match rpc_client.get_balance(&address) {
         Ok(bal) => bal,
-        Err(e) => return error_response(Error::BalaceFailed(e).to_string()),
+        Err(e) => return error_response(Error::BalaceFailed(e)),
     };
 
     let response = BalanceResponse {
         address: address.to_string(),
         balance,
+        balance_sol: native_token::lamports_to_sol(balance),
     };
     success_response(response)
 }
 
 #[handler]
-async fn airdrop(req: Json<AirdropRequest>) -> impl IntoResponse {
-    let to = match parse_pubkey(&req.to) {
-        Ok(addr) => addr,
-        Err(e) => return error_response(e.to_string()),
+async fn balances(req: Json<BalancesRequest>, state: Data<&AppState>) -> impl IntoResponse {
+    let net = match state.config.resolve_network(req.net) {
+        Ok(net) => net,
+        Err(e) => return error_response(e),
     };
 
-    let rpc_client = RpcClient::new(req.net.get_cluster_url().to_string());
-    let amount = native_token::sol_to_lamports(req.amount);
+    // Invalid addresses are reported per entry instead of failing the whole batch
+    let parsed: Vec<Result<Pubkey, Error>> =
+        req.addresses.iter().map(|a| parse_pubkey(a)).collect();
+    let valid: Vec<Pubkey> = parsed
+        .iter()
+        .filter_map(|p| p.as_ref().ok())
+        .copied()
+        .collect();
 
-    let sig = match rpc_client.request_airdrop(&to, amount) {
-        Ok(signature) => signature,
-        Err(e) => return error_response(Error::AirdropFailed(e).to_string()),
+    let rpc_client = state.rpc_client(net);
+    let mut accounts = match get_multiple_accounts_chunked(&rpc_client, &valid) {
+        Ok(accounts) => accounts.into_iter(),
+        Err(e) => return error_response(Error::BalaceFailed(e)),
     };
 
-    let recent_hash = match rpc_client.get_latest_blockhash() {
-        Ok(hash) => hash,
-        Err(e) => return error_response(Error::RecentHashFailed(e).to_string()),
+    let balances = req
+        .addresses
+        .iter()
+        .zip(parsed)
+        .map(|(address, parsed)| match parsed {
+            Ok(_) => {
+                let account = accounts.next().flatten();
+                let balance = account.as_ref().map_or(0, |account| account.lamports);
+                AddressBalance {
+                    address: address.clone(),
+                    balance,
+                    balance_sol: native_token::lamports_to_sol(balance),
+                    exists: account.is_some(),
+                    error: None,
+                }
+            }
+            Err(e) => AddressBalance {
+                address: address.clone(),
+                balance: 0,
+                balance_sol: 0.0,
+                exists: false,
+                error: Some(e.to_string()),
+            },
+        })
+        .collect();
+
+    let response = BalancesResponse { balances };
+    success_response(response)
+}
+
+#[handler]
+async fn airdrop(req: Json<AirdropRequest>, state: Data<&AppState>) -> impl IntoResponse {
+    let net = match state.config.resolve_network(req.net) {
+        Ok(net) => net,
+        Err(e) => return error_response(e),
     };
 
-    if let Err(e) =
-        rpc_client.confirm_transaction_with_spinner(&sig, &recent_hash, rpc_client.commitment())
-    {
-        return error_response(Error::ConfirmingTransactionFailed(e).to_string());
+    if let Err(e) = check_safe_mode(&state.config, net) {
+        return error_response(e);
+    }
+
+    let amount = match resolve_lamports(req.amount_sol, req.amount_lamports) {
+        Ok(amount) => amount,
+        Err(e) => return error_response(e),
+    };
+
+    let to = match parse_pubkey(&req.to) {
+        Ok(addr) => addr,
+        Err(e) => return error_response(e),
+    };
+
+    let rpc_client = state.rpc_client(net);
+    let retries = req.retries.unwrap_or(0);
+
+    // A failure after the first airdrop keeps what was funded so far
+    let mut airdrops: Vec<(Signature, u64)> = Vec::new();
+    let mut error = None;
+    for lamports in split_airdrop(amount, net.airdrop_cap_lamports()) {
+        match request_airdrop(&rpc_client, &to, lamports, retries) {
+            Ok(sig) => airdrops.push((sig, lamports)),
+            Err(e) if airdrops.is_empty() => return error_response(e),
+            Err(e) => {
+                error = Some(e.to_string());
+                break;
+            }
+        }
+    }
+
+    let wait_for_confirmation = req.wait_for_confirmation.unwrap_or(true);
+    if wait_for_confirmation {
+        let sigs: Vec<Signature> = airdrops.iter().map(|(sig, _)| *sig).collect();
+        let confirmations = match confirm_signatures(&rpc_client, &sigs, DEFAULT_CONFIRM_TIMEOUT) {
+            Ok(confirmations) => confirmations,
+            Err(e) => return error_response(e),
+        };
+        let mut confirmed = Vec::new();
+        let mut confirmation_error = None;
+        for ((sig, lamports), confirmation) in airdrops.into_iter().zip(confirmations) {
+            match confirmation {
+                Confirmation::Confirmed { .. } => confirmed.push((sig, lamports)),
+                Confirmation::Failed { error, .. } => {
+                    confirmation_error.get_or_insert(Error::ConfirmingTransactionFailed(
+                        ClientErrorKind::Custom(error).into(),
+                    ));
+                }
+                Confirmation::Pending => {
+                    confirmation_error.get_or_insert(Error::ConfirmationTimeout(sig.to_string()));
+                }
+            }
+        }
+        if let Some(e) = confirmation_error {
+            if confirmed.is_empty() {
+                return error_response(e);
+            }
+            error.get_or_insert(e.to_string());
+        }
+        airdrops = confirmed;
     }
 
     let response = AirdropResponse {
-        transaction_id: sig.to_string(),
+        transaction_id: airdrops[0].0.to_string(),
+        transaction_ids: airdrops.iter().map(|(sig, _)| sig.to_string()).collect(),
+        to: to.to_string(),
+        lamports: airdrops.iter().map(|(_, lamports)| lamports).sum(),
+        requested_lamports: amount,
+        confirmed: wait_for_confirmation,
+        error,
     };
     success_response(response)
 }
 
 #[handler]
-async fn send_single(req: Json<SendSingleRequest>) -> impl IntoResponse {
+async fn send_single(
+    req: Json<SendSingleRequest>,
+    headers: &HeaderMap,
+    state: Data<&AppState>,
+) -> impl IntoResponse {
+    let net = match state.config.resolve_network(req.net) {
+        Ok(net) => net,
+        Err(e) => return error_response(e),
+    };
+
+    if let Err(e) = check_safe_mode(&state.config, net) {
+        return error_response(e);
+    }
+
+    if let Err(e) = check_mainnet_confirmed(net, &req.send_options) {
+        return error_response(e);
+    }
+
+    // None until the balance is known when sending a percentage of it
+    let amount = match req.amount_percent {
+        Some(percent) => match check_amount_percent(percent, req.amount_sol, req.amount_lamports) {
+            Ok(()) => None,
+            Err(e) => return error_response(e),
+        },
+        None => match resolve_lamports(req.amount_sol, req.amount_lamports) {
+            Ok(amount) => Some(amount),
+            Err(e) => return error_response(e),
+        },
+    };
+
+    let memos = Memos::new(
+        req.memo.clone(),
+        req.memos.clone(),
+        req.memo_position.unwrap_or_default(),
+    );
+    if let Err(e) = check_memos(&memos, state.config.max_memo_bytes) {
+        return error_response(e);
+    }
+
     let keypair = match parse_keypair_bs58(&req.keypair) {
         Ok(kp) => kp,
-        Err(e) => return error_response(e.to_string()),
+        Err(e) => return error_response(e),
+    };
+
+    let to = match parse_pubkey(&req.to) {
+        Ok(addr) => addr,
+        Err(e) => return error_response(e),
+    };
+
+    let tip = match parse_tip(req.tip_account.as_deref(), req.tip_lamports) {
+        Ok(tip) => tip,
+        Err(e) => return error_response(e),
+    };
+
+    // Some(memo) when the response carries a Solana Pay URL, checked before anything is sent
+    let pay_url_memo = match req
+        .solana_pay_url
+        .then(|| solana_pay_memo(&memos, tip.as_ref()))
+        .transpose()
+    {
+        Ok(memo) => memo,
+        Err(e) => return error_response(e),
+    };
+
+    // A sponsor paying the fee has to sign too, the same key given twice is only used once
+    let fee_payer = match req
+        .fee_payer_keypair
+        .as_deref()
+        .map(parse_keypair_bs58)
+        .transpose()
+    {
+        Ok(fee_payer) => fee_payer.filter(|fee_payer| fee_payer.pubkey() != keypair.pubkey()),
+        Err(e) => return error_response(e),
+    };
+    match (&fee_payer, req.fee_payer.as_deref()) {
+        (Some(fee_payer), Some(expected)) if fee_payer.pubkey().to_string() != expected => {
+            return error_response(Error::InvalidRequest(format!(
+                "fee_payer_keypair belongs to {}, not fee_payer {}",
+                fee_payer.pubkey(),
+                expected
+            )));
+        }
+        (None, Some(expected)) if expected != keypair.pubkey().to_string() => {
+            return error_response(Error::InvalidRequest(
+                "fee_payer_keypair is required, the fee payer has to sign the transaction"
+                    .to_string(),
+            ));
+        }
+        _ => {}
+    }
+
+    let idempotency_key = req.idempotency_key.as_deref().or_else(|| {
+        headers
+            .get(IDEMPOTENCY_KEY_HEADER)
+            .and_then(|v| v.to_str().ok())
+    });
+    let reservation = match idempotency_key.map(|key| state.idempotency.reserve(key, &*req)) {
+        None => None,
+        Some(Reserve::Acquired(reservation)) => Some(reservation),
+        Some(Reserve::Completed(transaction_id)) => {
+            return success_response(SendSingleResponse {
+                transaction_id,
+                amount_lamports: None,
+                solana_pay_url: None,
+                slot: None,
+                block_time: None,
+            });
+        }
+        Some(Reserve::InProgress) => {
+            return error_response(Error::IdempotencyKeyInUse(
+                idempotency_key.unwrap_or_default().to_string(),
+            ));
+        }
+        Some(Reserve::Mismatch) => {
+            return error_response(Error::IdempotencyKeyReused(
+                idempotency_key.unwrap_or_default().to_string(),
+            ));
+        }
+    };
+
+    let rpc_client = state.rpc_client(net);
+    let fee_payer_pubkey = fee_payer
+        .as_ref()
+        .map_or(keypair.pubkey(), |fp| fp.pubkey());
+
+    let recent_hash = match &req.recent_block_hash {
+        Some(hash) => match parse_hash(hash) {
+            Ok(hash) => hash,
+            Err(e) => return error_response(e),
+        },
+        None => match state
+            .latest_blockhash(net, req.send_options.max_retries())
+            .await
+        {
+            Ok(hash) => hash,
+            Err(e) => return error_response(e),
+        },
+    };
+    if req.recent_block_hash.is_some() && req.check_block_hash {
+        if let Err(e) = check_blockhash_valid(&rpc_client, &recent_hash) {
+            return error_response(e);
+        }
+    }
+
+    // The fee doesn't depend on the amount, a zero transfer prices the real one
+    let amount = match (amount, req.amount_percent) {
+        (Some(amount), _) => amount,
+        (None, percent) => {
+            let instructions =
+                transfer_instructions(0, &to, &memos, &keypair.pubkey(), tip.as_ref());
+            let message =
+                Message::new_with_blockhash(&instructions, Some(&fee_payer_pubkey), &recent_hash);
+            let tip_lamports = tip.as_ref().map_or(0, |tip| tip.lamports);
+            match percent_of_balance(
+                &rpc_client,
+                &keypair.pubkey(),
+                &message,
+                tip_lamports,
+                percent.unwrap_or(100.0),
+            ) {
+                Ok(amount) => amount,
+                Err(e) => return error_response(e),
+            }
+        }
+    };
+
+    let instructions = transfer_instructions(amount, &to, &memos, &keypair.pubkey(), tip.as_ref());
+    let mut tx = match build_transaction(Message::new(&instructions, Some(&fee_payer_pubkey))) {
+        Ok(tx) => tx,
+        Err(e) => return error_response(e),
+    };
+
+    if req.check_balance {
+        tx.message.recent_blockhash = recent_hash;
+        let total = amount.saturating_add(tip.map_or(0, |tip| tip.lamports));
+        if let Err(e) = check_sufficient_balance(&rpc_client, &keypair.pubkey(), &tx, total) {
+            return error_response(e);
+        }
+    }
+
+    let mut signers = vec![&keypair];
+    signers.extend(fee_payer.as_ref());
+
+    // A stale blockhash is only retried here, the keypair is at hand to sign again
+    let (sig, recent_hash) = match send_with_blockhash_retry(
+        &mut tx,
+        &signers,
+        recent_hash,
+        || state.latest_blockhash(net, req.send_options.max_retries()),
+        |tx| state.send_transaction(net, tx, &req.send_options),
+    )
+    .await
+    {
+        Ok(sent) => sent,
+        Err(e) => {
+            // The node may have it anyway, a retry has to get this signature rather than send again
+            if let Some(reservation) = reservation.filter(|_| send_outcome_unknown(&e)) {
+                reservation.complete(tx.signatures[0].to_string());
+            }
+            return error_response(e);
+        }
+    };
+
+    // Remember the signature as soon as it is sent, a retry after a confirmation timeout must not resend
+    if let Some(reservation) = reservation {
+        reservation.complete(sig.to_string());
+    }
+
+    if let Err(e) = confirm_transaction(
+        &rpc_client,
+        &sig,
+        &recent_hash,
+        req.send_options.confirm_timeout(),
+    ) {
+        return error_response(e);
+    }
+
+    let (slot, block_time) = get_landed_slot(&rpc_client, &sig);
+    let solana_pay_url = pay_url_memo
+        .map(|memo| TransferRequest::new(to, Some((amount, SOL_DECIMALS)), None, memo).to_url());
+    let response = SendSingleResponse {
+        transaction_id: sig.to_string(),
+        amount_lamports: Some(amount),
+        solana_pay_url,
+        slot,
+        block_time,
+    };
+    success_response(response)
+}
+
+/// Largest compute budget a single transaction can request
+const MAX_COMPUTE_UNIT_LIMIT: u64 = 1_400_000;
+
+/// Simulates what /api/send_single would send, nothing is signed or broadcast
+#[handler]
+async fn estimate_compute_units(
+    req: Json<SendSingleRequest>,
+    state: Data<&AppState>,
+) -> impl IntoResponse {
+    let net = match state.config.resolve_network(req.net) {
+        Ok(net) => net,
+        Err(e) => return error_response(e),
+    };
+
+    // The units don't depend on the amount, a percentage is simulated as a zero transfer
+    let amount = match req.amount_percent {
+        Some(percent) => match check_amount_percent(percent, req.amount_sol, req.amount_lamports) {
+            Ok(()) => 0,
+            Err(e) => return error_response(e),
+        },
+        None => match resolve_lamports(req.amount_sol, req.amount_lamports) {
+            Ok(amount) => amount,
+            Err(e) => return error_response(e),
+        },
+    };
+
+    let memos = Memos::new(
+        req.memo.clone(),
+        req.memos.clone(),
+        req.memo_position.unwrap_or_default(),
+    );
+    if let Err(e) = check_memos(&memos, state.config.max_memo_bytes) {
+        return error_response(e);
+    }
+
+    let keypair = match parse_keypair_bs58(&req.keypair) {
+        Ok(kp) => kp,
+        Err(e) => return error_response(e),
+    };
+
+    let to = match parse_pubkey(&req.to) {
+        Ok(addr) => addr,
+        Err(e) => return error_response(e),
+    };
+
+    let tip = match parse_tip(req.tip_account.as_deref(), req.tip_lamports) {
+        Ok(tip) => tip,
+        Err(e) => return error_response(e),
+    };
+
+    let fee_payer = match (req.fee_payer.as_deref(), req.fee_payer_keypair.as_deref()) {
+        (Some(fee_payer), _) => parse_pubkey(fee_payer),
+        (None, Some(fee_payer_keypair)) => {
+            parse_keypair_bs58(fee_payer_keypair).map(|kp| kp.pubkey())
+        }
+        (None, None) => Ok(keypair.pubkey()),
+    };
+    let fee_payer = match fee_payer {
+        Ok(fee_payer) => fee_payer,
+        Err(e) => return error_response(e),
+    };
+
+    let instructions = transfer_instructions(amount, &to, &memos, &keypair.pubkey(), tip.as_ref());
+    let tx = match build_transaction(Message::new(&instructions, Some(&fee_payer))) {
+        Ok(tx) => tx,
+        Err(e) => return error_response(e),
+    };
+
+    let units_consumed = match simulate_compute_units(&state.rpc_client(net), &tx) {
+        Ok(units) => units,
+        Err(e) => return error_response(e),
+    };
+
+    let response = EstimateComputeUnitsResponse {
+        units_consumed,
+        suggested_limit: (units_consumed + units_consumed.div_ceil(10)).min(MAX_COMPUTE_UNIT_LIMIT),
+    };
+    success_response(response)
+}
+
+#[handler]
+async fn send_raw_instructions(
+    req: Json<SendRawInstructionsRequest>,
+    state: Data<&AppState>,
+) -> impl IntoResponse {
+    let net = match state.config.resolve_network(req.net) {
+        Ok(net) => net,
+        Err(e) => return error_response(e),
+    };
+
+    if let Err(e) = check_safe_mode(&state.config, net) {
+        return error_response(e);
+    }
+
+    if let Err(e) = check_mainnet_confirmed(net, &req.send_options) {
+        return error_response(e);
+    }
+
+    let keypair = match parse_keypair_bs58(&req.keypair) {
+        Ok(kp) => kp,
+        Err(e) => return error_response(e),
+    };
+
+    if req.instructions.is_empty() {
+        return error_response(Error::InvalidRequest(
+            "instructions must not be empty".to_string(),
+        ));
+    }
+    let instructions: Vec<Instruction> = match req
+        .instructions
+        .iter()
+        .map(parse_instruction)
+        .collect::<Result<_, _>>()
+    {
+        Ok(instructions) => instructions,
+        Err(e) => return error_response(e),
+    };
+
+    let msg = Message::new(&instructions, Some(&keypair.pubkey()));
+    let mut tx = match build_transaction(msg) {
+        Ok(tx) => tx,
+        Err(e) => return error_response(e),
+    };
+
+    let rpc_client = state.rpc_client(net);
+    let recent_hash = match state
+        .latest_blockhash(net, req.send_options.max_retries())
+        .await
+    {
+        Ok(hash) => hash,
+        Err(e) => return error_response(e),
+    };
+    if let Err(e) = tx.try_sign(&[&keypair], recent_hash) {
+        return error_response(Error::TransactionCreationFailed(format!(
+            "only {} can sign: {}",
+            keypair.pubkey(),
+            e
+        )));
+    }
+
+    let sig = match state.send_transaction(net, &tx, &req.send_options) {
+        Ok(signature) => signature,
+        Err(e) => return error_response(e),
+    };
+
+    if let Err(e) = confirm_transaction(
+        &rpc_client,
+        &sig,
+        &recent_hash,
+        req.send_options.confirm_timeout(),
+    ) {
+        return error_response(e);
+    }
+
+    let response = SendRawInstructionsResponse {
+        transaction_id: sig.to_string(),
+    };
+    success_response(response)
+}
+
+#[handler]
+async fn validate_address(req: Json<ValidateAddressRequest>) -> impl IntoResponse {
+    let response = match parse_pubkey(&req.address) {
+        Ok(address) => ValidateAddressResponse {
+            valid: true,
+            is_on_curve: address.is_on_curve(),
+        },
+        Err(_) => ValidateAddressResponse {
+            valid: false,
+            is_on_curve: false,
+        },
+    };
+    success_response(response)
+}
+
+#[handler]
+async fn keypair_pubkey(req: Json<KeypairPubkeyRequest>) -> impl IntoResponse {
+    let keypair = match parse_keypair_bs58(&req.keypair) {
+        Ok(kp) => kp,
+        Err(e) => return error_response(e),
+    };
+
+    let response = KeypairPubkeyResponse {
+        public_key: keypair.pubkey().to_string(),
+    };
+    success_response(response)
+}
+
+/// Same derivation as the Solana CLI and wallets: BIP39 seed, then SLIP-0010 along the path
+fn derive_keypair_from_mnemonic(
+    mnemonic: &str,
+    passphrase: &str,
+    derivation_path: &DerivationPath,
+) -> Result<Keypair, Error> {
+    // The phrase itself is never echoed back, it is as sensitive as the key
+    let mnemonic = Mnemonic::from_phrase(mnemonic.trim(), Language::English)
+        .map_err(|e| Error::InvalidRequest(format!("mnemonic: {}", e)))?;
+    let seed = Seed::new(&mnemonic, passphrase);
+    keypair_from_seed_and_derivation_path(seed.as_bytes(), Some(derivation_path.clone()))
+        .map_err(|e| Error::InvalidRequest(format!("derivation_path: {}", e)))
+}
+
+#[handler]
+async fn keypair_from_mnemonic(req: Json<KeypairFromMnemonicRequest>) -> impl IntoResponse {
+    let derivation_path = match req.derivation_path.as_deref() {
+        None => DerivationPath::new_bip44(Some(0), Some(0)),
+        Some(path) => match DerivationPath::from_absolute_path_str(path) {
+            Ok(path) => path,
+            Err(e) => {
+                return error_response(Error::InvalidRequest(format!("derivation_path: {}", e)));
+            }
+        },
+    };
+
+    let keypair = match derive_keypair_from_mnemonic(
+        &req.mnemonic,
+        req.passphrase.as_deref().unwrap_or_default(),
+        &derivation_path,
+    ) {
+        Ok(kp) => kp,
+        Err(e) => return error_response(e),
+    };
+
+    let response = KeypairFromMnemonicResponse {
+        secret_share: keypair.to_base58_string(),
+        public_share: keypair.pubkey().to_string(),
+        // Debug is the m/44'/501'/.. form
+        derivation_path: format!("{:?}", derivation_path),
+    };
+    success_response(response)
+}
+
+#[handler]
+async fn decode_transaction(req: Json<DecodeTransactionRequest>) -> impl IntoResponse {
+    let tx = match req.encoding {
+        TransactionEncoding::Base64 => deserialize_transaction_base64(&req.transaction),
+        TransactionEncoding::Base58 => deserialize_transaction_bs58(&req.transaction),
+    };
+    let tx = match tx {
+        Ok(tx) => tx,
+        Err(e) => {
+            return error_response(Error::InvalidRequest(format!("transaction: {}", e)));
+        }
+    };
+
+    let message = &tx.message;
+    let message_data = tx.message_data();
+    let keys = &message.account_keys;
+
+    let accounts = keys
+        .iter()
+        .enumerate()
+        .map(|(i, key)| DecodedAccount {
+            pubkey: key.to_string(),
+            is_signer: message.is_signer(i),
+            is_writable: message.is_writable(i),
+        })
+        .collect();
+
+    let signatures: Vec<_> = keys
+        .iter()
+        .take(message.header.num_required_signatures as usize)
+        .enumerate()
+        .map(|(i, signer)| {
+            let signature = tx
+                .signatures
+                .get(i)
+                .filter(|sig| **sig != Signature::default());
+            DecodedSignature {
+                signer: signer.to_string(),
+                signature: signature.map(|sig| sig.to_string()),
+                valid: signature.is_some_and(|sig| sig.verify(signer.as_ref(), &message_data)),
+            }
+        })
+        .collect();
+    let missing_signers = signatures
+        .iter()
+        .filter(|sig| sig.signature.is_none())
+        .map(|sig| sig.signer.clone())
+        .collect();
+
+    let instructions = message
+        .instructions
+        .iter()
+        .map(|ix| DecodedInstruction {
+            program_id: keys[ix.program_id_index as usize].to_string(),
+            accounts: ix
+                .accounts
+                .iter()
+                .map(|&i| keys[i as usize].to_string())
+                .collect(),
+            data: bs58::encode(&ix.data).into_string(),
+        })
+        .collect();
+
+    let response = DecodeTransactionResponse {
+        fee_payer: keys.first().map(|key| key.to_string()),
+        recent_block_hash: message.recent_blockhash.to_string(),
+        message_hash: message.hash().to_string(),
+        accounts,
+        signatures,
+        missing_signers,
+        instructions,
+    };
+    success_response(response)
+}
+
+#[handler]
+async fn recent_block_hash(
+    req: Json<RecentBlockHashRequest>,
+    state: Data<&AppState>,
+) -> impl IntoResponse {
+    let net = match state.config.resolve_network(req.net) {
+        Ok(net) => net,
+        Err(e) => return error_response(e),
+    };
+
+    let rpc_client = state.rpc_client(net);
+    let (recent_hash, last_valid_block_height) =
+        match get_latest_blockhash_with_expiry(&rpc_client, DEFAULT_MAX_RETRIES) {
+            Ok(latest) => latest,
+            Err(e) => return error_response(e),
+        };
+
+    let current_block_height = match get_block_height(&rpc_client, DEFAULT_MAX_RETRIES) {
+        Ok(height) => height,
+        Err(e) => return error_response(e),
+    };
+
+    let valid = match is_blockhash_valid(&rpc_client, &recent_hash, DEFAULT_MAX_RETRIES) {
+        Ok(valid) => valid,
+        Err(e) => return error_response(e),
+    };
+
+    // Priced with a single signature, which is all an aggregated transaction carries
+    let payer = Pubkey::new_unique();
+    let message = Message::new_with_blockhash(
+        &[solana_sdk::system_instruction::transfer(
+            &payer,
+            &Pubkey::new_unique(),
+            1,
+        )],
+        Some(&payer),
+        &recent_hash,
+    );
+    let fee_lamports = match get_fee_for_message(&rpc_client, &message, DEFAULT_MAX_RETRIES) {
+        Ok(fee) => fee,
+        Err(e) => return error_response(e),
+    };
+
+    let response = RecentBlockHashResponse {
+        recent_block_hash: recent_hash.to_string(),
+        last_valid_block_height,
+        current_block_height,
+        valid,
+        fee_lamports,
+    };
+    success_response(response)
+}
+
+/// The keys of an aggregate_keys request and its signer, the first key when none is given
+fn parse_agg_keys(keys: &[String], signer: Option<&str>) -> Result<(Vec<Pubkey>, Pubkey), Error> {
+    let keys: Vec<Pubkey> = keys
+        .iter()
+        .map(|k| parse_pubkey(k))
+        .collect::<Result<_, _>>()?;
+    let signer = match signer {
+        Some(signer) => parse_pubkey(signer)?,
+        None => *keys
+            .first()
+            .ok_or_else(|| Error::InvalidRequest("keys must not be empty".to_string()))?,
+    };
+    Ok((keys, signer))
+}
+
+#[handler]
+async fn aggregate_keys(req: Json<AggregateKeysRequest>) -> impl IntoResponse {
+    let (keys, signer) = match parse_agg_keys(&req.keys, req.signer.as_deref()) {
+        Ok(parsed) => parsed,
+        Err(e) => return error_response(e),
+    };
+
+    // Only the coefficient depends on the signer, the aggregated key does not
+    let aggkey = match key_agg(keys, Some(signer)) {
+        Ok(key) => key,
+        Err(e) => return error_response(e),
+    };
+
+    let aggpubkey = Pubkey::new(&*aggkey.agg_public_key.to_bytes(true));
+    let response = AggregateKeysResponse {
+        aggregated_public_key: aggpubkey.to_string(),
+        raw_bytes_hex: hex::encode(aggpubkey.to_bytes()),
+        compressed: true,
+        signer: signer.to_string(),
+        signer_coefficient: hex::encode(&*aggkey.musig_coefficient.to_bytes()),
+    };
+    success_response(response)
+}
+
+#[handler]
+async fn agg_send_step_one(req: Json<AggSendStepOneRequest>) -> impl IntoResponse {
+    match agg_step_one(&req.keypair) {
+        Ok(response) => success_response(response),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Step one only depends on the keypair, the send, stake, deactivate and withdraw flows share it
+fn agg_step_one(keypair: &str) -> Result<AggSendStepOneResponse, Error> {
+    let keypair = parse_keypair_bs58(keypair)?;
+    let (first_msg, secret) = step_one(keypair);
+    Ok(AggSendStepOneResponse {
+        message_1: first_msg.serialize_bs58(),
+        secret_state: secret.serialize_bs58(),
+        serialization_version: SERIALIZATION_VERSION,
+    })
+}
+
+#[handler]
+async fn agg_send_step_two(
+    req: Json<AggSendStepTwoRequest>,
+    state: Data<&AppState>,
+) -> impl IntoResponse {
+    if let Err(e) = check_serialization_version(req.serialization_version) {
+        return error_response(e);
+    }
+
+    let mut errors = FieldErrors::default();
+    let amount = errors.check(
+        "amount",
+        resolve_lamports(req.amount_sol, req.amount_lamports),
+    );
+    let memo = errors.check(
+        "memo",
+        check_memo(req.memo.as_deref(), state.config.max_memo_bytes),
+    );
+    let keypair = errors.check("keypair", parse_keypair_bs58(&req.keypair));
+    let to = errors.check("to", parse_pubkey(&req.to));
+    let tip = errors.check(
+        "tip_account",
+        parse_tip(req.tip_account.as_deref(), req.tip_lamports),
+    );
+    let nonce = errors.check(
+        "nonce_account",
+        parse_nonce(req.nonce_account.as_deref(), req.nonce_authority.as_deref()),
+    );
+    let recent_block_hash = errors.check(
+        "recent_block_hash",
+        req.recent_block_hash.as_deref().map(parse_hash).transpose(),
+    );
+    let keys = errors.check_all("keys", &req.keys, |k| parse_pubkey(k));
+    let first_messages = errors.check_all("first_messages", &req.first_messages, |m| {
+        AggMessage1::deserialize_bs58(m).with_field("first_messages")
+    });
+    let secret_state = errors.check(
+        "secret_state",
+        SecretAggStepOne::deserialize_bs58(&req.secret_state).with_field("secret_state"),
+    );
+    let (
+        Some(amount),
+        Some(()),
+        Some(keypair),
+        Some(to),
+        Some(tip),
+        Some(nonce),
+        Some(_),
+        Some(keys),
+        Some(first_messages),
+        Some(secret_state),
+    ) = (
+        amount,
+        memo,
+        keypair,
+        to,
+        tip,
+        nonce,
+        recent_block_hash,
+        keys,
+        first_messages,
+        secret_state,
+    )
+    else {
+        return error_response(errors.into_error());
+    };
+
+    // Only reads the nonce account once every field is valid
+    let block_hash = match resolve_block_hash(
+        &state,
+        req.recent_block_hash.as_deref(),
+        nonce.as_ref(),
+        req.net,
+    ) {
+        Ok(hash) => hash,
+        Err(e) => return error_response(e),
+    };
+
+    let sig = match step_two(
+        keypair,
+        amount,
+        to,
+        req.memo.clone(),
+        block_hash,
+        keys,
+        first_messages,
+        secret_state,
+        nonce,
+        tip,
+    ) {
+        Ok(signature) => signature,
+        Err(e) => return error_response(e),
+    };
+
+    let response = AggSendStepTwoResponse {
+        partial_signature: sig.serialize_bs58(),
+        serialization_version: SERIALIZATION_VERSION,
+    };
+    success_response(response)
+}
+
+#[handler]
+async fn signing_package_create(
+    req: Json<SigningPackageCreateRequest>,
+    state: Data<&AppState>,
+) -> impl IntoResponse {
+    let amount = match resolve_lamports(req.amount_sol, req.amount_lamports) {
+        Ok(amount) => amount,
+        Err(e) => return error_response(e),
+    };
+
+    if let Err(e) = check_memo(req.memo.as_deref(), state.config.max_memo_bytes) {
+        return error_response(e);
+    }
+
+    let to = match parse_pubkey(&req.to) {
+        Ok(addr) => addr,
+        Err(e) => return error_response(e),
+    };
+
+    let tip = match parse_tip(req.tip_account.as_deref(), req.tip_lamports) {
+        Ok(tip) => tip,
+        Err(e) => return error_response(e),
+    };
+
+    let nonce = match parse_nonce(req.nonce_account.as_deref(), req.nonce_authority.as_deref()) {
+        Ok(nonce) => nonce,
+        Err(e) => return error_response(e),
+    };
+
+    // Resolved here, the offline signer has no way to read the nonce account
+    let block_hash = match resolve_block_hash(
+        &state,
+        req.recent_block_hash.as_deref(),
+        nonce.as_ref(),
+        req.net,
+    ) {
+        Ok(hash) => hash,
+        Err(e) => return error_response(e),
+    };
+
+    let keys: Vec<Pubkey> = match req
+        .keys
+        .iter()
+        .map(|k| parse_pubkey(k))
+        .collect::<Result<_, _>>()
+    {
+        Ok(keys) => keys,
+        Err(e) => return error_response(e),
+    };
+
+    let first_messages: Vec<Vec<u8>> = match req
+        .first_messages
+        .iter()
+        .map(|m| {
+            let msg = AggMessage1::deserialize_bs58(m).with_field("first_messages")?;
+            let mut bytes = Vec::with_capacity(msg.size_hint());
+            msg.serialize(&mut bytes);
+            Ok(bytes)
+        })
+        .collect::<Result<_, Error>>()
+    {
+        Ok(msgs) => msgs,
+        Err(e) => return error_response(e),
+    };
+
+    let package = SigningPackage {
+        amount,
+        to,
+        memo: req.memo.clone(),
+        tip: tip.map(|tip| (tip.account, tip.lamports)),
+        recent_block_hash: block_hash,
+        nonce_account: nonce.as_ref().map(|nonce| nonce.account),
+        nonce_authority: nonce.as_ref().and_then(|nonce| nonce.authority),
+        keys,
+        first_messages,
+    };
+
+    let response = SigningPackageCreateResponse {
+        package: package.serialize_bs58(),
+    };
+    success_response(response)
+}
+
+/// Step two from a signing package alone, needs no RPC so it can run on an offline machine
+#[handler]
+async fn signing_package_step_two(req: Json<SigningPackageStepTwoRequest>) -> impl IntoResponse {
+    let package = match SigningPackage::deserialize_bs58(&req.package).with_field("package") {
+        Ok(package) => package,
+        Err(e) => return error_response(e),
+    };
+
+    let first_messages = match package.first_messages().with_field("package") {
+        Ok(msgs) => msgs,
+        Err(e) => return error_response(e),
+    };
+
+    let keypair = match parse_keypair_bs58(&req.keypair) {
+        Ok(kp) => kp,
+        Err(e) => return error_response(e),
+    };
+
+    let secret_state =
+        match SecretAggStepOne::deserialize_bs58(&req.secret_state).with_field("secret_state") {
+            Ok(state) => state,
+            Err(e) => return error_response(e),
+        };
+
+    let nonce = package.nonce_account.map(|account| NonceParams {
+        account,
+        authority: package.nonce_authority,
+    });
+    let tip = package
+        .tip
+        .map(|(account, lamports)| Tip { account, lamports });
+
+    let sig = match step_two(
+        keypair,
+        package.amount,
+        package.to,
+        package.memo,
+        package.recent_block_hash,
+        package.keys,
+        first_messages,
+        secret_state,
+        nonce,
+        tip,
+    ) {
+        Ok(signature) => signature,
+        Err(e) => return error_response(e),
+    };
+
+    let response = AggSendStepTwoResponse {
+        partial_signature: sig.serialize_bs58(),
+        serialization_version: SERIALIZATION_VERSION,
+    };
+    success_response(response)
+}
+
+#[handler]
+async fn aggregate_signatures(
+    req: Json<AggregateSignaturesRequest>,
+    state: Data<&AppState>,
+) -> impl IntoResponse {
+    let net = match state.config.resolve_network(req.net) {
+        Ok(net) => net,
+        Err(e) => return error_response(e),
+    };
+
+    if let Err(e) = check_safe_mode(&state.config, net) {
+        return error_response(e);
+    }
+
+    if let Err(e) = check_mainnet_confirmed(net, &req.send_options) {
+        return error_response(e);
+    }
+
+    let sig = match aggregate_and_send(&state, &req).await {
+        Ok(signature) => signature,
+        Err(e) => return error_response(e),
+    };
+
+    let (slot, block_time) = get_landed_slot(&state.rpc_client(net), &sig);
+    let response = AggregateSignaturesResponse {
+        transaction_id: sig.to_string(),
+        slot,
+        block_time,
+    };
+    success_response(response)
+}
+
+/// Combine the partial signatures, broadcast and confirm. Shared by /api/aggregate_signatures
+/// and signing sessions, which aggregate once the last signer submits
+async fn aggregate_and_send(
+    state: &AppState,
+    req: &AggregateSignaturesRequest,
+) -> Result<Signature, Error> {
+    let net = state.config.resolve_network(req.net)?;
+    check_serialization_version(req.serialization_version)?;
+    let mut errors = FieldErrors::default();
+    let amount = errors.check(
+        "amount",
+        resolve_lamports(req.amount_sol, req.amount_lamports),
+    );
+    let memo = errors.check(
+        "memo",
+        check_memo(req.memo.as_deref(), state.config.max_memo_bytes),
+    );
+    let to = errors.check("to", parse_pubkey(&req.to));
+    let tip = errors.check(
+        "tip_account",
+        parse_tip(req.tip_account.as_deref(), req.tip_lamports),
+    );
+    let nonce = errors.check(
+        "nonce_account",
+        parse_nonce(req.nonce_account.as_deref(), req.nonce_authority.as_deref()),
+    );
+    let recent_block_hash = errors.check(
+        "recent_block_hash",
+        req.recent_block_hash.as_deref().map(parse_hash).transpose(),
+    );
+    let keys = errors.check_all("keys", &req.keys, |k| parse_pubkey(k));
+    let signatures = errors.check_all("signatures", &req.signatures, |s| {
+        PartialSignature::deserialize_bs58(s).with_field("signatures")
+    });
+    let first_messages = match &req.first_messages {
+        None => Some(None),
+        Some(first_messages) => errors
+            .check_all("first_messages", first_messages, |m| {
+                AggMessage1::deserialize_bs58(m).with_field("first_messages")
+            })
+            .map(Some),
+    };
+    let (
+        Some(amount),
+        Some(()),
+        Some(to),
+        Some(tip),
+        Some(nonce),
+        Some(_),
+        Some(keys),
+        Some(signatures),
+        Some(first_messages),
+    ) = (
+        amount,
+        memo,
+        to,
+        tip,
+        nonce,
+        recent_block_hash,
+        keys,
+        signatures,
+        first_messages,
+    )
+    else {
+        return Err(errors.into_error());
+    };
+
+    let block_hash = resolve_block_hash(
+        state,
+        req.recent_block_hash.as_deref(),
+        nonce.as_ref(),
+        Some(net),
+    )?;
+
+    // Name the signers that sent a bad partial signature instead of failing on the combined one
+    if let Some(first_messages) = first_messages {
+        let invalid = tss::verify_partial_signatures(
+            amount,
+            to,
+            req.memo.clone(),
+            block_hash,
+            keys.clone(),
+            &first_messages,
+            &signatures,
+            nonce.as_ref(),
+            tip.as_ref(),
+        )?;
+        if !invalid.is_empty() {
+            let signers = invalid.iter().map(|k| k.to_string()).collect();
+            return Err(Error::InvalidPartialSignatures(signers));
+        }
+    }
+
+    let tx = sign_and_broadcast(
+        amount,
+        to,
+        req.memo.clone(),
+        block_hash,
+        keys,
+        signatures,
+        nonce,
+        tip,
+    )?;
+
+    let rpc_client = state.rpc_client(net);
+    let sig = state.send_transaction(net, &tx, &req.send_options)?;
+
+    // A stored nonce is usually older than the blockhash validity window, so confirm against a fresh one
+    let confirm_hash = match nonce {
+        None => block_hash,
+        Some(_) => {
+            state
+                .latest_blockhash(net, req.send_options.max_retries())
+                .await?
+        }
+    };
+
+    confirm_transaction(
+        &rpc_client,
+        &sig,
+        &confirm_hash,
+        req.send_options.confirm_timeout(),
+    )?;
+    Ok(sig)
+}
+
+#[handler]
+async fn agg_session_create(
+    req: Json<AggSessionCreateRequest>,
+    state: Data<&AppState>,
+) -> impl IntoResponse {
+    let net = match state.config.resolve_network(req.net) {
+        Ok(net) => net,
+        Err(e) => return error_response(e),
+    };
+
+    if let Err(e) = check_safe_mode(&state.config, net) {
+        return error_response(e);
+    }
+
+    if let Err(e) = check_mainnet_confirmed(net, &req.send_options) {
+        return error_response(e);
+    }
+
+    // Catch bad parameters now rather than after every signer has submitted
+    if let Err(e) = resolve_lamports(req.amount_sol, req.amount_lamports) {
+        return error_response(e);
+    }
+
+    if let Err(e) = check_memo(req.memo.as_deref(), state.config.max_memo_bytes) {
+        return error_response(e);
+    }
+
+    if let Err(e) = parse_pubkey(&req.to) {
+        return error_response(e);
+    }
+
+    if let Err(e) = parse_nonce(req.nonce_account.as_deref(), req.nonce_authority.as_deref()) {
+        return error_response(e);
+    }
+
+    if let Err(e) = parse_tip(req.tip_account.as_deref(), req.tip_lamports) {
+        return error_response(e);
+    }
+
+    if let Some(Err(e)) = req.recent_block_hash.as_deref().map(parse_hash) {
+        return error_response(e);
+    }
+
+    let keys: Vec<Pubkey> = match req
+        .keys
+        .iter()
+        .map(|k| parse_pubkey(k))
+        .collect::<Result<_, _>>()
+    {
+        Ok(keys) => keys,
+        Err(e) => return error_response(e),
+    };
+    if keys.is_empty() {
+        return error_response(Error::InvalidRequest("keys must not be empty".to_string()));
+    }
+
+    let session_id = state.agg_sessions.create(req.0, keys);
+    success_response(AggSessionCreateResponse { session_id })
+}
+
+#[handler]
+async fn agg_session_submit(
+    Path(id): Path<String>,
+    req: Json<AggSessionSubmitRequest>,
+    state: Data<&AppState>,
+) -> impl IntoResponse {
+    let signer = match parse_pubkey(&req.signer) {
+        Ok(signer) => signer,
+        Err(e) => return error_response(e),
+    };
+
+    if let Err(e) = PartialSignature::deserialize_bs58(&req.signature).with_field("signature") {
+        return error_response(e);
+    }
+
+    let (session, broadcast) = match state
+        .agg_sessions
+        .submit(&id, signer, req.signature.clone())
+    {
+        Ok(submitted) => submitted,
+        Err(e) => return error_response(e),
+    };
+    if !broadcast {
+        return success_response(session_status(&id, &session));
+    }
+
+    // The last signer is in, aggregate now. A failure reopens the session for resubmission
+    let params = &session.params;
+    let aggregate_req = AggregateSignaturesRequest {
+        signatures: session.ordered_signatures().unwrap_or_default(),
+        amount_sol: params.amount_sol,
+        amount_lamports: params.amount_lamports,
+        to: params.to.clone(),
+        memo: params.memo.clone(),
+        recent_block_hash: params.recent_block_hash.clone(),
+        net: params.net,
+        keys: params.keys.clone(),
+        nonce_account: params.nonce_account.clone(),
+        nonce_authority: params.nonce_authority.clone(),
+        first_messages: None,
+        serialization_version: None,
+        tip_account: params.tip_account.clone(),
+        tip_lamports: params.tip_lamports,
+        send_options: params.send_options.clone(),
+    };
+    let result = aggregate_and_send(&state, &aggregate_req).await;
+    let finished = state
+        .agg_sessions
+        .finish(&id, result.as_ref().map(|sig| sig.to_string()));
+    match (result, finished) {
+        (Ok(_), Some(session)) => success_response(session_status(&id, &session)),
+        // Already broadcast, so report it even though the session expired meanwhile
+        (Ok(sig), None) => {
+            let mut session = session;
+            session.state = AggSessionState::Broadcast;
+            session.transaction_id = Some(sig.to_string());
+            success_response(session_status(&id, &session))
+        }
+        (Err(e), _) => error_response(e),
+    }
+}
+
+#[handler]
+async fn agg_session_status(Path(id): Path<String>, state: Data<&AppState>) -> impl IntoResponse {
+    match state.agg_sessions.get(&id) {
+        Ok(session) => success_response(session_status(&id, &session)),
+        Err(e) => error_response(e),
+    }
+}
+
+fn session_status(id: &str, session: &AggSession) -> AggSessionStatusResponse {
+    AggSessionStatusResponse {
+        session_id: id.to_string(),
+        state: session.state,
+        submitted: session.submitted().iter().map(|k| k.to_string()).collect(),
+        missing: session.missing().iter().map(|k| k.to_string()).collect(),
+        signatures: session.ordered_signatures(),
+        transaction_id: session.transaction_id.clone(),
+        error: session.error.clone(),
+    }
+}
+
+#[handler]
+async fn verify_partial_signatures(
+    req: Json<VerifyPartialSignaturesRequest>,
+    state: Data<&AppState>,
+) -> impl IntoResponse {
+    let amount = match resolve_lamports(req.amount_sol, req.amount_lamports) {
+        Ok(amount) => amount,
+        Err(e) => return error_response(e),
+    };
+
+    let to = match parse_pubkey(&req.to) {
+        Ok(addr) => addr,
+        Err(e) => return error_response(e),
+    };
+
+    let tip = match parse_tip(req.tip_account.as_deref(), req.tip_lamports) {
+        Ok(tip) => tip,
+        Err(e) => return error_response(e),
+    };
+
+    let nonce = match parse_nonce(req.nonce_account.as_deref(), req.nonce_authority.as_deref()) {
+        Ok(nonce) => nonce,
+        Err(e) => return error_response(e),
+    };
+
+    let block_hash = match resolve_block_hash(
+        &state,
+        req.recent_block_hash.as_deref(),
+        nonce.as_ref(),
+        req.net,
+    ) {
+        Ok(hash) => hash,
+        Err(e) => return error_response(e),
+    };
+
+    let keys: Vec<Pubkey> = match req
+        .keys
+        .iter()
+        .map(|k| parse_pubkey(k))
+        .collect::<Result<_, _>>()
+    {
+        Ok(keys) => keys,
+        Err(e) => return error_response(e),
+    };
+
+    let first_messages: Vec<AggMessage1> = match req
+        .first_messages
+        .iter()
+        .map(|m| AggMessage1::deserialize_bs58(m).with_field("first_messages"))
+        .collect::<Result<_, _>>()
+    {
+        Ok(msgs) => msgs,
+        Err(e) => return error_response(e),
+    };
+
+    let signatures: Vec<PartialSignature> = match req
+        .signatures
+        .iter()
+        .map(|s| PartialSignature::deserialize_bs58(s).with_field("signatures"))
+        .collect::<Result<_, _>>()
+    {
+        Ok(sigs) => sigs,
+        Err(e) => return error_response(e),
+    };
+
+    let invalid = match tss::verify_partial_signatures(
+        amount,
+        to,
+        req.memo.clone(),
+        block_hash,
+        keys,
+        &first_messages,
+        &signatures,
+        nonce.as_ref(),
+        tip.as_ref(),
+    ) {
+        Ok(invalid) => invalid,
+        Err(e) => return error_response(e),
+    };
+
+    let results = first_messages
+        .iter()
+        .map(|msg| PartialSignatureCheck {
+            signer: msg.sender.to_string(),
+            valid: !invalid.contains(&msg.sender),
+        })
+        .collect();
+    let response = VerifyPartialSignaturesResponse {
+        valid: invalid.is_empty(),
+        results,
+    };
+    success_response(response)
+}
+
+#[handler]
+async fn verify_signature(req: Json<VerifySignatureRequest>) -> impl IntoResponse {
+    let message = match BASE64_STANDARD.decode(&req.message_base64) {
+        Ok(message) => message,
+        Err(e) => {
+            return error_response(Error::InvalidRequest(format!("message_base64: {}", e)));
+        }
+    };
+
+    let signature = match Signature::from_str(&req.signature) {
+        Ok(signature) => signature,
+        Err(e) => return error_response(Error::InvalidRequest(format!("signature: {}", e))),
+    };
+
+    let public_key = match parse_pubkey(&req.public_key) {
+        Ok(key) => key,
+        Err(e) => return error_response(e),
+    };
+
+    let response = VerifySignatureResponse {
+        valid: signature.verify(public_key.as_ref(), &message),
+    };
+    success_response(response)
+}
+
+#[cfg(feature = "testing-helpers")]
+#[handler]
+async fn agg_send_all(req: Json<AggSendAllRequest>, state: Data<&AppState>) -> impl IntoResponse {
+    let net = match state.config.resolve_network(req.net) {
+        Ok(net) => net,
+        Err(e) => return error_response(e),
+    };
+
+    if let Err(e) = check_safe_mode(&state.config, net) {
+        return error_response(e);
+    }
+
+    if let Err(e) = check_mainnet_confirmed(net, &req.send_options) {
+        return error_response(e);
+    }
+
+    let amount = match resolve_lamports(req.amount_sol, req.amount_lamports) {
+        Ok(amount) => amount,
+        Err(e) => return error_response(e),
+    };
+
+    if let Err(e) = check_memo(req.memo.as_deref(), state.config.max_memo_bytes) {
+        return error_response(e);
+    }
+
+    let keypairs: Vec<Keypair> = match req
+        .keypairs
+        .iter()
+        .map(|k| parse_keypair_bs58(k))
+        .collect::<Result<_, _>>()
+    {
+        Ok(keypairs) => keypairs,
+        Err(e) => return error_response(e),
+    };
+
+    let to = match parse_pubkey(&req.to) {
+        Ok(addr) => addr,
+        Err(e) => return error_response(e),
+    };
+
+    let rpc_client = state.rpc_client(net);
+    let block_hash = match state
+        .latest_blockhash(net, req.send_options.max_retries())
+        .await
+    {
+        Ok(hash) => hash,
+        Err(e) => return error_response(e),
+    };
+
+    let tx = match crate::tss::sign_all(keypairs, amount, to, req.memo.clone(), block_hash) {
+        Ok(transaction) => transaction,
+        Err(e) => return error_response(e),
+    };
+
+    let sig = match state.send_transaction(net, &tx, &req.send_options) {
+        Ok(signature) => signature,
+        Err(e) => return error_response(e),
+    };
+
+    if let Err(e) = confirm_transaction(
+        &rpc_client,
+        &sig,
+        &block_hash,
+        req.send_options.confirm_timeout(),
+    ) {
+        return error_response(e);
+    }
+
+    let response = AggSendAllResponse {
+        transaction_id: sig.to_string(),
+    };
+    success_response(response)
+}
+
+#[handler]
+async fn create_nonce_account(
+    req: Json<CreateNonceAccountRequest>,
+    state: Data<&AppState>,
+) -> impl IntoResponse {
+    let net = match state.config.resolve_network(req.net) {
+        Ok(net) => net,
+        Err(e) => return error_response(e),
+    };
+
+    if let Err(e) = check_safe_mode(&state.config, net) {
+        return error_response(e);
+    }
+
+    if let Err(e) = check_mainnet_confirmed(net, &req.send_options) {
+        return error_response(e);
+    }
+
+    let keypair = match parse_keypair_bs58(&req.keypair) {
+        Ok(kp) => kp,
+        Err(e) => return error_response(e),
+    };
+
+    let authority = match req.authority.as_deref().map(parse_pubkey).transpose() {
+        Ok(authority) => authority.unwrap_or_else(|| keypair.pubkey()),
+        Err(e) => return error_response(e),
+    };
+
+    let rpc_client = state.rpc_client(net);
+    let lamports = match nonce_account_rent(&rpc_client) {
+        Ok(lamports) => lamports,
+        Err(e) => return error_response(e),
+    };
+
+    let nonce_keypair = Keypair::generate(&mut rand07::thread_rng());
+    let mut tx = create_nonce_account_transaction(
+        &keypair.pubkey(),
+        &nonce_keypair.pubkey(),
+        &authority,
+        lamports,
+    );
+
+    let recent_hash = match state
+        .latest_blockhash(net, req.send_options.max_retries())
+        .await
+    {
+        Ok(hash) => hash,
+        Err(e) => return error_response(e),
+    };
+
+    tx.sign(&[&keypair, &nonce_keypair], recent_hash);
+
+    let sig = match state.send_transaction(net, &tx, &req.send_options) {
+        Ok(signature) => signature,
+        Err(e) => return error_response(e),
+    };
+
+    if let Err(e) = confirm_transaction(
+        &rpc_client,
+        &sig,
+        &recent_hash,
+        req.send_options.confirm_timeout(),
+    ) {
+        return error_response(e);
+    }
+
+    let response = CreateNonceAccountResponse {
+        nonce_account: nonce_keypair.pubkey().to_string(),
+        authority: authority.to_string(),
+        transaction_id: sig.to_string(),
+    };
+    success_response(response)
+}
+
+#[handler]
+async fn nonce_info(req: Json<NonceInfoRequest>, state: Data<&AppState>) -> impl IntoResponse {
+    let net = match state.config.resolve_network(req.net) {
+        Ok(net) => net,
+        Err(e) => return error_response(e),
+    };
+
+    let nonce_account = match parse_pubkey(&req.nonce_account) {
+        Ok(pubkey) => pubkey,
+        Err(e) => return error_response(e),
+    };
+
+    let rpc_client = state.rpc_client(net);
+    let data = match get_nonce_data(&rpc_client, &nonce_account) {
+        Ok(data) => data,
+        Err(e) => return error_response(e),
+    };
+
+    let response = NonceInfoResponse {
+        nonce_account: nonce_account.to_string(),
+        blockhash: data.blockhash().to_string(),
+        authority: data.authority.to_string(),
+        lamports_per_signature: data.get_lamports_per_signature(),
+    };
+    success_response(response)
+}
+
+//////////////////////// spl /////////////////////////////
+
+// token_mint = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v"
+// 6A2GHg17A2YUbLp7qma1pbvnS7deav7Tq3tthQHa8zt5
+#[handler]
+async fn spl_token_balance(
+    req: Json<SplTokenBalanceRequest>,
+    state: Data<&AppState>,
+) -> impl IntoResponse {
+    let net = match state.config.resolve_network(req.net) {
+        Ok(net) => net,
+        Err(e) => return error_response(e),
+    };
+
+    let owner = match parse_pubkey(&req.owner) {
+        Ok(addr) => addr,
+        Err(e) => return error_response(e),
+    };
+
+    let token_mint = match parse_pubkey(&req.token_mint) {
+        Ok(mint) => mint,
+        Err(e) => return error_response(e),
+    };
+
+    let rpc_client = state.rpc_client(net);
+
+    // Get mint info to get decimals, its owner tells which token program to use
+    let mint_info = match get_optional_account(&rpc_client, &token_mint) {
+        Ok(Some(account)) => account,
+        Ok(None) => return error_response(Error::TokenMintNotFound),
+        Err(e) => return error_response(e),
+    };
+
+    let mint_data = match unpack_mint(&mint_info.data) {
+        Ok(data) => data,
+        Err(e) => return error_response(e),
+    };
+
+    let token_program = match req
+        .token_program
+        .or_else(|| TokenProgram::from_owner(&mint_info.owner))
+    {
+        Some(program) => program,
+        None => {
+            return error_response(Error::InvalidRequest(format!(
+                "{} is not a token mint, it is owned by {}",
+                token_mint, mint_info.owner
+            )));
+        }
+    };
+
+    // Get the associated token address
+    let token_account = get_associated_token_address(&owner, &token_mint, token_program);
+
+    // A missing account is reported as a zero balance rather than an error
+    let account_info =
+        match rpc_client.get_account_with_commitment(&token_account, rpc_client.commitment()) {
+            Ok(response) => response.value,
+            Err(e) => return error_response(Error::TokenAccountsFailed(e)),
+        };
+
+    let balance = match account_info.map(|account| unpack_token_account(&account.data)) {
+        None => None,
+        Some(Ok(data)) => Some(data.amount),
+        Some(Err(e)) => return error_response(e),
+    };
+
+    let response = SplTokenBalanceResponse {
+        owner: owner.to_string(),
+        token_mint: token_mint.to_string(),
+        token_account: token_account.to_string(),
+        account_exists: balance.is_some(),
+        balance: balance.unwrap_or(0),
+        decimals: mint_data.decimals,
+        token_program,
+    };
+    success_response(response)
+}
+
+#[handler]
+async fn token_metadata(
+    req: Json<TokenMetadataRequest>,
+    state: Data<&AppState>,
+) -> impl IntoResponse {
+    let net = match state.config.resolve_network(req.net) {
+        Ok(net) => net,
+        Err(e) => return error_response(e),
+    };
+
+    let token_mint = match parse_pubkey(&req.token_mint) {
+        Ok(mint) => mint,
+        Err(e) => return error_response(e),
+    };
+
+    let metadata_account = get_metadata_address(&token_mint);
+    let rpc_client = state.rpc_client(net);
+    let account =
+        match rpc_client.get_account_with_commitment(&metadata_account, rpc_client.commitment()) {
+            Ok(response) => response.value,
+            Err(e) => return error_response(Error::AccountInfoFailed(e)),
+        };
+
+    // Plenty of mints have no metadata, that is not an error
+    let metadata = match account.map(|account| parse_metadata(&metadata_account, &account.data)) {
+        Some(Ok(metadata)) => Some(metadata),
+        Some(Err(e)) => return error_response(e),
+        None => None,
+    };
+
+    let response = TokenMetadataResponse {
+        token_mint: token_mint.to_string(),
+        metadata_account: metadata_account.to_string(),
+        name: metadata.as_ref().map(|metadata| metadata.name.clone()),
+        symbol: metadata.as_ref().map(|metadata| metadata.symbol.clone()),
+        uri: metadata.map(|metadata| metadata.uri),
+    };
+    success_response(response)
+}
+
+/// Pure PDA derivation, works without any RPC
+#[handler]
+async fn derive_ata(req: Json<DeriveAtaRequest>) -> impl IntoResponse {
+    let owner = match parse_pubkey(&req.owner) {
+        Ok(addr) => addr,
+        Err(e) => return error_response(e),
+    };
+
+    let token_mint = match parse_pubkey(&req.token_mint) {
+        Ok(addr) => addr,
+        Err(e) => return error_response(e),
+    };
+
+    let token_program = req.token_program.unwrap_or_default();
+    let response = DeriveAtaResponse {
+        associated_token_address: get_associated_token_address(&owner, &token_mint, token_program)
+            .to_string(),
+        token_program,
+    };
+    success_response(response)
+}
+
+/// The memo of a transfer that is also returned as a Solana Pay URL. The URL carries a single
+/// memo and no tip, so anything more is refused before the transaction is built from it
+fn solana_pay_memo<'a>(memos: &'a Memos, tip: Option<&Tip>) -> Result<Option<&'a str>, Error> {
+    if tip.is_some() {
+        return Err(Error::InvalidRequest(
+            "a Solana Pay URL can't carry a tip, leave out solana_pay_url or the tip".to_string(),
+        ));
+    }
+    let mut all = memos.before.iter().chain(&memos.after);
+    match (all.next(), all.next()) {
+        (memo, None) => Ok(memo.map(String::as_str)),
+        _ => Err(Error::InvalidRequest(
+            "a Solana Pay URL carries a single memo".to_string(),
+        )),
+    }
+}
+
+/// Encodes a transfer as a Solana Pay URL for a mobile wallet, nothing is signed or sent.
+/// send_single and spl_send_single return the same URL for what they sent with `solana_pay_url`
+#[handler]
+async fn solana_pay_url(
+    req: Json<SolanaPayUrlRequest>,
+    state: Data<&AppState>,
+) -> impl IntoResponse {
+    let recipient = match parse_pubkey(&req.to) {
+        Ok(addr) => addr,
+        Err(e) => return error_response(e),
+    };
+
+    let spl_token = match req.token_mint.as_deref().map(parse_pubkey).transpose() {
+        Ok(mint) => mint,
+        Err(e) => return error_response(e),
+    };
+
+    let references = match req
+        .references
+        .iter()
+        .flatten()
+        .map(|reference| parse_pubkey(reference))
+        .collect::<Result<Vec<_>, _>>()
+    {
+        Ok(references) => references,
+        Err(e) => return error_response(e),
+    };
+
+    if let Err(e) = check_memo(req.memo.as_deref(), state.config.max_memo_bytes) {
+        return error_response(e);
+    }
+
+    // Without any amount the wallet asks for one
+    let sol_amount_given = req.amount_sol.is_some() || req.amount_lamports.is_some();
+    let amount = match (spl_token, req.amount_base_units) {
+        (None, None) if !sol_amount_given => None,
+        (None, None) => match resolve_lamports(req.amount_sol, req.amount_lamports) {
+            Ok(lamports) => Some((lamports, SOL_DECIMALS)),
+            Err(e) => return error_response(e),
+        },
+        (None, Some(_)) => {
+            return error_response(Error::InvalidRequest(
+                "amount_base_units needs token_mint, use amount_sol or amount_lamports for SOL"
+                    .to_string(),
+            ));
+        }
+        (Some(_), _) if sol_amount_given => {
+            return error_response(Error::InvalidRequest(
+                "give a token amount as amount_base_units".to_string(),
+            ));
+        }
+        (Some(_), None) => None,
+        (Some(_), Some(0)) => return error_response(Error::InvalidAmount(0.0)),
+        (Some(mint), Some(base_units)) => {
+            let decimals = match req.decimals {
+                Some(decimals) => Ok(decimals),
+                None => state.config.resolve_network(req.net).and_then(|net| {
+                    resolve_mint(&state.rpc_client(net), &mint, None, None)
+                        .map(|(_, decimals)| decimals)
+                }),
+            };
+            match decimals {
+                Ok(decimals) => Some((base_units, decimals)),
+                Err(e) => return error_response(e),
+            }
+        }
+    };
+
+    let request = TransferRequest {
+        references: &references,
+        label: req.label.as_deref(),
+        message: req.message.as_deref(),
+        ..TransferRequest::new(recipient, amount, spl_token, req.memo.as_deref())
+    };
+    let response = SolanaPayUrlResponse {
+        url: request.to_url(),
+    };
+    success_response(response)
+}
+
+#[handler]
+async fn spl_all_balances(
+    req: Json<SplAllBalancesRequest>,
+    state: Data<&AppState>,
+) -> impl IntoResponse {
+    let net = match state.config.resolve_network(req.net) {
+        Ok(net) => net,
+        Err(e) => return error_response(e),
+    };
+
+    let owner = match parse_pubkey(&req.owner) {
+        Ok(addr) => addr,
+        Err(e) => return error_response(e),
+    };
+
+    let rpc_client = state.rpc_client(net);
+
+    // Token-2022 accounts live under a different program, so ask for both
+    let mut token_accounts: Vec<(Pubkey, TokenProgram)> = Vec::new();
+    for token_program in [TokenProgram::Token, TokenProgram::Token2022] {
+        let keyed_accounts = match rpc_client
+            .get_token_accounts_by_owner(&owner, TokenAccountsFilter::ProgramId(token_program.id()))
+        {
+            Ok(accounts) => accounts,
+            Err(e) => return error_response(Error::TokenAccountsFailed(e)),
+        };
+        for keyed_account in keyed_accounts {
+            match parse_pubkey(&keyed_account.pubkey) {
+                Ok(address) => token_accounts.push((address, token_program)),
+                Err(e) => return error_response(e),
+            }
+        }
+    }
+
+    // The RPC answers in jsonParsed, so fetch the raw data to unpack it ourselves
+    let addresses: Vec<Pubkey> = token_accounts.iter().map(|(address, _)| *address).collect();
+    let raw_accounts = match get_multiple_accounts_chunked(&rpc_client, &addresses) {
+        Ok(accounts) => accounts,
+        Err(e) => return error_response(Error::TokenAccountsFailed(e)),
+    };
+
+    let mut holdings = Vec::new();
+    for ((address, token_program), account) in token_accounts.into_iter().zip(raw_accounts) {
+        // Closed between the two calls
+        let Some(account) = account else { continue };
+        let token_account_data = match unpack_token_account(&account.data) {
+            Ok(data) => data,
+            Err(e) => return error_response(e),
+        };
+        if token_account_data.amount > 0 || req.include_zero {
+            holdings.push((address, token_program, token_account_data));
+        }
+    }
+
+    // Look every distinct mint up once for its decimals
+    let mut mints: Vec<Pubkey> = holdings.iter().map(|(_, _, data)| data.mint).collect();
+    mints.sort();
+    mints.dedup();
+    let mint_accounts = match get_multiple_accounts_chunked(&rpc_client, &mints) {
+        Ok(accounts) => accounts,
+        Err(e) => return error_response(Error::TokenAccountsFailed(e)),
+    };
+    let mut decimals = HashMap::new();
+    for (mint, account) in mints.iter().zip(mint_accounts) {
+        let Some(account) = account else {
+            return error_response(Error::TokenMintNotFound);
+        };
+        match unpack_mint(&account.data) {
+            Ok(data) => decimals.insert(*mint, data.decimals),
+            Err(e) => return error_response(e),
+        };
+    }
+
+    let balances = holdings
+        .into_iter()
+        .map(|(address, token_program, data)| SplBalanceEntry {
+            token_account: address.to_string(),
+            token_mint: data.mint.to_string(),
+            balance: data.amount,
+            decimals: decimals[&data.mint],
+            token_program,
+        })
+        .collect();
+
+    let response = SplAllBalancesResponse {
+        owner: owner.to_string(),
+        balances,
+    };
+    success_response(response)
+}
+
+#[handler]
+async fn spl_send_single(
+    req: Json<SplSendSingleRequest>,
+    state: Data<&AppState>,
+) -> impl IntoResponse {
+    let net = match state.config.resolve_network(req.net) {
+        Ok(net) => net,
+        Err(e) => return error_response(e),
+    };
+
+    if let Err(e) = check_safe_mode(&state.config, net) {
+        return error_response(e);
+    }
+
+    if let Err(e) = check_mainnet_confirmed(net, &req.send_options) {
+        return error_response(e);
+    }
+
+    if let Err(e) = check_memo(req.memo.as_deref(), state.config.max_memo_bytes) {
+        return error_response(e);
+    }
+
+    let keypair = match parse_keypair_bs58(&req.keypair) {
+        Ok(kp) => kp,
+        Err(e) => return error_response(e),
+    };
+
+    let to = match parse_pubkey(&req.to) {
+        Ok(addr) => addr,
+        Err(e) => return error_response(e),
+    };
+
+    let token_mint = match parse_pubkey(&req.token_mint) {
+        Ok(mint) => mint,
+        Err(e) => return error_response(e),
+    };
+
+    let from_token_account = match req
+        .from_token_account
+        .as_deref()
+        .map(parse_pubkey)
+        .transpose()
+    {
+        Ok(source) => source,
+        Err(e) => return error_response(e),
+    };
+
+    let rpc_client = state.rpc_client(net);
+
+    let (token_program, decimals) =
+        match resolve_mint(&rpc_client, &token_mint, req.token_program, req.decimals) {
+            Ok(mint) => mint,
+            Err(e) => return error_response(e),
+        };
+
+    // Base units skip the float conversion entirely
+    let token_amount = match req.amount_base_units {
+        Some(0) => return error_response(Error::InvalidAmount(0.0)),
+        Some(base_units) => base_units,
+        None => match parse_amount(req.amount)
+            .and_then(|amount| get_token_amount_with_decimals(amount, decimals))
+        {
+            Ok(token_amount) => token_amount,
+            Err(e) => return error_response(e),
+        },
+    };
+
+    //Derive ATAs
+    let to_ata = get_associated_token_address(&to, &token_mint, token_program);
+
+    // Without a source account the transfer would only fail later in simulation
+    match from_token_account {
+        Some(source) => {
+            let account = match get_optional_account(&rpc_client, &source) {
+                Ok(Some(account)) => account,
+                Ok(None) => {
+                    return error_response(Error::TokenAccountNotFound(source.to_string()));
+                }
+                Err(e) => return error_response(e),
+            };
+            if let Err(e) = check_source_account(
+                &source,
+                &account,
+                &token_mint,
+                token_program,
+                &keypair.pubkey(),
+                token_amount,
+            ) {
+                return error_response(e);
+            }
+        }
+        None => {
+            let from_ata =
+                get_associated_token_address(&keypair.pubkey(), &token_mint, token_program);
+            match get_optional_account(&rpc_client, &from_ata) {
+                Ok(Some(_)) => {}
+                Ok(None) => {
+                    return error_response(Error::TokenAccountNotFound(format!(
+                        "{} holds none of mint {} (no account at {})",
+                        keypair.pubkey(),
+                        token_mint,
+                        from_ata
+                    )));
+                }
+                Err(e) => return error_response(e),
+            }
+        }
+    }
+
+    //checking if destination ATA exists
+    let to_ata_exists = match get_optional_account(&rpc_client, &to_ata) {
+        Ok(account) => account.is_some(),
+        Err(e) => return error_response(e),
+    };
+
+    // Creates the destination ATA if needed, then transfers and adds the memo
+    let instructions = match spl_token_transfer_instructions(
+        token_amount,
+        &keypair.pubkey(),
+        from_token_account.as_ref(),
+        &to,
+        &token_mint,
+        &keypair.pubkey(),
+        req.memo.clone(),
+        true,
+        to_ata_exists,
+        token_program,
+        decimals,
+    ) {
+        Ok(instructions) => instructions,
+        Err(e) => return error_response(e),
+    };
+
+    // Create and sign transaction
+    let recent_hash = match state
+        .latest_blockhash(net, req.send_options.max_retries())
+        .await
+    {
+        Ok(hash) => hash,
+        Err(e) => return error_response(e),
+    };
+
+    let mut tx = Transaction::new_with_payer(&instructions, Some(&keypair.pubkey()));
+    tx.sign(&[&keypair], recent_hash);
+
+    // Send transaction
+    let sig = match state.send_transaction(net, &tx, &req.send_options) {
+        Ok(signature) => signature,
+        Err(e) => return error_response(e),
+    };
+
+    // Confirm transaction
+    if let Err(e) = confirm_transaction(
+        &rpc_client,
+        &sig,
+        &recent_hash,
+        req.send_options.confirm_timeout(),
+    ) {
+        return error_response(e);
+    }
+
+    let solana_pay_url = req.solana_pay_url.then(|| {
+        TransferRequest::new(
+            to,
+            Some((token_amount, decimals)),
+            Some(token_mint),
+            req.memo.as_deref(),
+        )
+        .to_url()
+    });
+    let response = SplSendSingleResponse {
+        transaction_id: sig.to_string(),
+        solana_pay_url,
+    };
+    success_response(response)
+}
+
+#[handler]
+async fn spl_create_account(
+    req: Json<SplCreateAccountRequest>,
+    state: Data<&AppState>,
+) -> impl IntoResponse {
+    let net = match state.config.resolve_network(req.net) {
+        Ok(net) => net,
+        Err(e) => return error_response(e),
+    };
+
+    if let Err(e) = check_safe_mode(&state.config, net) {
+        return error_response(e);
+    }
+
+    if let Err(e) = check_mainnet_confirmed(net, &req.send_options) {
+        return error_response(e);
+    }
+
+    let keypair = match parse_keypair_bs58(&req.keypair) {
+        Ok(kp) => kp,
+        Err(e) => return error_response(e),
+    };
+
+    let owner = match parse_pubkey(&req.owner) {
+        Ok(addr) => addr,
+        Err(e) => return error_response(e),
+    };
+
+    let token_mint = match parse_pubkey(&req.token_mint) {
+        Ok(mint) => mint,
+        Err(e) => return error_response(e),
+    };
+
+    let rpc_client = state.rpc_client(net);
+    let token_program = match resolve_token_program(&rpc_client, &token_mint, req.token_program) {
+        Ok(program) => program,
+        Err(e) => return error_response(e),
+    };
+    let token_account = get_associated_token_address(&owner, &token_mint, token_program);
+
+    // Creating an existing ATA fails on-chain, catch it before paying the fee
+    match get_optional_account(&rpc_client, &token_account) {
+        Ok(None) => {}
+        Ok(Some(_)) => {
+            return error_response(Error::TokenAccountAlreadyExists(token_account.to_string()));
+        }
+        Err(e) => return error_response(e),
+    }
+
+    let mut tx = create_associated_token_account_transaction(
+        &keypair.pubkey(),
+        &owner,
+        &token_mint,
+        token_program,
+    );
+
+    let recent_hash = match state
+        .latest_blockhash(net, req.send_options.max_retries())
+        .await
+    {
+        Ok(hash) => hash,
+        Err(e) => return error_response(e),
+    };
+
+    tx.sign(&[&keypair], recent_hash);
+
+    let sig = match state.send_transaction(net, &tx, &req.send_options) {
+        Ok(signature) => signature,
+        Err(e) => return error_response(e),
+    };
+
+    if let Err(e) = confirm_transaction(
+        &rpc_client,
+        &sig,
+        &recent_hash,
+        req.send_options.confirm_timeout(),
+    ) {
+        return error_response(e);
+    }
+
+    let response = SplCreateAccountResponse {
+        token_account: token_account.to_string(),
+        transaction_id: sig.to_string(),
+    };
+    success_response(response)
+}
+
+#[handler]
+async fn spl_approve(req: Json<SplApproveRequest>, state: Data<&AppState>) -> impl IntoResponse {
+    let net = match state.config.resolve_network(req.net) {
+        Ok(net) => net,
+        Err(e) => return error_response(e),
+    };
+
+    if let Err(e) = check_safe_mode(&state.config, net) {
+        return error_response(e);
+    }
+
+    if let Err(e) = check_mainnet_confirmed(net, &req.send_options) {
+        return error_response(e);
+    }
+
+    let amount = match parse_amount(req.amount)
+        .and_then(|amount| get_token_amount_with_decimals(amount, req.decimals))
+    {
+        Ok(amount) => amount,
+        Err(e) => return error_response(e),
+    };
+
+    let keypair = match parse_keypair_bs58(&req.keypair) {
+        Ok(kp) => kp,
+        Err(e) => return error_response(e),
+    };
+
+    let token_mint = match parse_pubkey(&req.token_mint) {
+        Ok(mint) => mint,
+        Err(e) => return error_response(e),
     };
 
-    let to = match parse_pubkey(&req.to) {
+    let delegate = match parse_pubkey(&req.delegate) {
         Ok(addr) => addr,
-        Err(e) => return error_response(e.to_string()),
+        Err(e) => return error_response(e),
+    };
+
+    let rpc_client = state.rpc_client(net);
+    let token_program = match resolve_token_program(&rpc_client, &token_mint, req.token_program) {
+        Ok(program) => program,
+        Err(e) => return error_response(e),
     };
+    let token_account = get_associated_token_address(&keypair.pubkey(), &token_mint, token_program);
+
+    match get_optional_account(&rpc_client, &token_account) {
+        Ok(Some(_)) => {}
+        Ok(None) => {
+            return error_response(Error::TokenAccountNotFound(token_account.to_string()));
+        }
+        Err(e) => return error_response(e),
+    }
 
-    let rpc_client = RpcClient::new(req.net.get_cluster_url().to_string());
-    let mut tx = create_unsigned_transaction(req.amount, &to, req.memo.clone(), &keypair.pubkey());
+    let mut tx = match approve_transaction(
+        &keypair.pubkey(),
+        &token_mint,
+        &delegate,
+        amount,
+        req.decimals,
+        token_program,
+    ) {
+        Ok(tx) => tx,
+        Err(e) => return error_response(e),
+    };
 
-    let recent_hash = match rpc_client.get_latest_blockhash() {
+    let recent_hash = match state
+        .latest_blockhash(net, req.send_options.max_retries())
+        .await
+    {
         Ok(hash) => hash,
-        Err(e) => return error_response(Error::RecentHashFailed(e).to_string()),
+        Err(e) => return error_response(e),
     };
 
     tx.sign(&[&keypair], recent_hash);
 
-    let sig = match rpc_client.send_transaction(&tx) {
+    let sig = match state.send_transaction(net, &tx, &req.send_options) {
         Ok(signature) => signature,
-        Err(e) => return error_response(Error::SendTransactionFailed(e).to_string()),
+        Err(e) => return error_response(e),
     };
 
-    if let Err(e) =
-        rpc_client.confirm_transaction_with_spinner(&sig, &recent_hash, rpc_client.commitment())
-    {
-        return error_response(Error::ConfirmingTransactionFailed(e).to_string());
+    if let Err(e) = confirm_transaction(
+        &rpc_client,
+        &sig,
+        &recent_hash,
+        req.send_options.confirm_timeout(),
+    ) {
+        return error_response(e);
     }
 
-    let response = SendSingleResponse {
+    let response = SplApproveResponse {
+        token_account: token_account.to_string(),
+        delegate: delegate.to_string(),
+        amount_base_units: amount,
         transaction_id: sig.to_string(),
     };
     success_response(response)
 }
 
 #[handler]
-async fn recent_block_hash(req: Json<RecentBlockHashRequest>) -> impl IntoResponse {
-    let rpc_client = RpcClient::new(req.net.get_cluster_url().to_string());
-    let recent_hash = match rpc_client.get_latest_blockhash() {
-        Ok(hash) => hash,
-        Err(e) => return error_response(Error::RecentHashFailed(e).to_string()),
-    };
-
-    let response = RecentBlockHashResponse {
-        recent_block_hash: recent_hash.to_string(),
-    };
-    success_response(response)
-}
-
-#[handler]
-async fn aggregate_keys(req: Json<AggregateKeysRequest>) -> impl IntoResponse {
-    let keys: Vec<Pubkey> = match req
-        .keys
-        .iter()
-        .map(|k| parse_pubkey(k))
-        .collect::<Result<_, _>>()
-    {
-        Ok(keys) => keys,
-        Err(e) => return error_response(e.to_string()),
+async fn spl_revoke(req: Json<SplRevokeRequest>, state: Data<&AppState>) -> impl IntoResponse {
+    let net = match state.config.resolve_network(req.net) {
+        Ok(net) => net,
+        Err(e) => return error_response(e),
     };
 
-    let aggkey = match key_agg(keys, None) {
-        Ok(key) => key,
-        Err(e) => return error_response(e.to_string()),
-    };
+    if let Err(e) = check_safe_mode(&state.config, net) {
+        return error_response(e);
+    }
 
-    let aggpubkey = Pubkey::new(&*aggkey.agg_public_key.to_bytes(true));
-    let response = AggregateKeysResponse {
-        aggregated_public_key: aggpubkey.to_string(),
-    };
-    success_response(response)
-}
+    if let Err(e) = check_mainnet_confirmed(net, &req.send_options) {
+        return error_response(e);
+    }
 
-#[handler]
-async fn agg_send_step_one(req: Json<AggSendStepOneRequest>) -> impl IntoResponse {
     let keypair = match parse_keypair_bs58(&req.keypair) {
         Ok(kp) => kp,
-        Err(e) => return error_response(e.to_string()),
+        Err(e) => return error_response(e),
     };
 
-    let (first_msg, secret) = step_one(keypair);
-    let response = AggSendStepOneResponse {
-        message_1: first_msg.serialize_bs58(),
-        secret_state: secret.serialize_bs58(),
+    let token_mint = match parse_pubkey(&req.token_mint) {
+        Ok(mint) => mint,
+        Err(e) => return error_response(e),
     };
-    success_response(response)
-}
 
-#[handler]
-async fn agg_send_step_two(req: Json<AggSendStepTwoRequest>) -> impl IntoResponse {
-    let keypair = match parse_keypair_bs58(&req.keypair) {
-        Ok(kp) => kp,
-        Err(e) => return error_response(e.to_string()),
+    let rpc_client = state.rpc_client(net);
+    let token_program = match resolve_token_program(&rpc_client, &token_mint, req.token_program) {
+        Ok(program) => program,
+        Err(e) => return error_response(e),
     };
+    let token_account = get_associated_token_address(&keypair.pubkey(), &token_mint, token_program);
 
-    let to = match parse_pubkey(&req.to) {
-        Ok(addr) => addr,
-        Err(e) => return error_response(e.to_string()),
-    };
+    match get_optional_account(&rpc_client, &token_account) {
+        Ok(Some(_)) => {}
+        Ok(None) => {
+            return error_response(Error::TokenAccountNotFound(token_account.to_string()));
+        }
+        Err(e) => return error_response(e),
+    }
 
-    let block_hash = match parse_hash(&req.recent_block_hash) {
-        Ok(hash) => hash,
-        Err(e) => return error_response(e.to_string()),
+    let mut tx = match revoke_transaction(&keypair.pubkey(), &token_mint, token_program) {
+        Ok(tx) => tx,
+        Err(e) => return error_response(e),
     };
 
-    let keys: Vec<Pubkey> = match req
-        .keys
-        .iter()
-        .map(|k| parse_pubkey(k))
-        .collect::<Result<_, _>>()
+    let recent_hash = match state
+        .latest_blockhash(net, req.send_options.max_retries())
+        .await
     {
-        Ok(keys) => keys,
-        Err(e) => return error_response(e.to_string()),
+        Ok(hash) => hash,
+        Err(e) => return error_response(e),
     };
 
-    let first_messages: Vec<AggMessage1> = match req
-        .first_messages
-        .iter()
-        .map(|m| AggMessage1::deserialize_bs58(m))
-        .collect::<Result<_, _>>()
-    {
-        Ok(msgs) => msgs,
-        Err(e) => return error_response(e.to_string()),
-    };
+    tx.sign(&[&keypair], recent_hash);
 
-    let secret_state = match SecretAggStepOne::deserialize_bs58(&req.secret_state) {
-        Ok(state) => state,
-        Err(e) => return error_response(e.to_string()),
+    let sig = match state.send_transaction(net, &tx, &req.send_options) {
+        Ok(signature) => signature,
+        Err(e) => return error_response(e),
     };
 
-    let sig = match step_two(
-        keypair,
-        req.amount,
-        to,
-        req.memo.clone(),
-        block_hash,
-        keys,
-        first_messages,
-        secret_state,
+    if let Err(e) = confirm_transaction(
+        &rpc_client,
+        &sig,
+        &recent_hash,
+        req.send_options.confirm_timeout(),
     ) {
-        Ok(signature) => signature,
-        Err(e) => return error_response(e.to_string()),
-    };
+        return error_response(e);
+    }
 
-    let response = AggSendStepTwoResponse {
-        partial_signature: sig.serialize_bs58(),
+    let response = SplRevokeResponse {
+        token_account: token_account.to_string(),
+        transaction_id: sig.to_string(),
     };
     success_response(response)
 }
 
 #[handler]
-async fn aggregate_signatures(req: Json<AggregateSignaturesRequest>) -> impl IntoResponse {
-    let to = match parse_pubkey(&req.to) {
-        Ok(addr) => addr,
-        Err(e) => return error_response(e.to_string()),
+async fn spl_close_account(
+    req: Json<SplCloseAccountRequest>,
+    state: Data<&AppState>,
+) -> impl IntoResponse {
+    let net = match state.config.resolve_network(req.net) {
+        Ok(net) => net,
+        Err(e) => return error_response(e),
     };
 
-    let block_hash = match parse_hash(&req.recent_block_hash) {
-        Ok(hash) => hash,
-        Err(e) => return error_response(e.to_string()),
+    if let Err(e) = check_safe_mode(&state.config, net) {
+        return error_response(e);
+    }
+
+    if let Err(e) = check_mainnet_confirmed(net, &req.send_options) {
+        return error_response(e);
+    }
+
+    let keypair = match parse_keypair_bs58(&req.keypair) {
+        Ok(kp) => kp,
+        Err(e) => return error_response(e),
     };
 
-    let keys: Vec<Pubkey> = match req
-        .keys
-        .iter()
-        .map(|k| parse_pubkey(k))
-        .collect::<Result<_, _>>()
-    {
-        Ok(keys) => keys,
-        Err(e) => return error_response(e.to_string()),
+    let token_mint = match parse_pubkey(&req.token_mint) {
+        Ok(mint) => mint,
+        Err(e) => return error_response(e),
+    };
+
+    let destination = match req.destination.as_deref().map(parse_pubkey).transpose() {
+        Ok(destination) => destination.unwrap_or_else(|| keypair.pubkey()),
+        Err(e) => return error_response(e),
+    };
+
+    let rpc_client = state.rpc_client(net);
+    let token_program = match resolve_token_program(&rpc_client, &token_mint, req.token_program) {
+        Ok(program) => program,
+        Err(e) => return error_response(e),
+    };
+    let token_account = get_associated_token_address(&keypair.pubkey(), &token_mint, token_program);
+
+    let account =
+        match rpc_client.get_account_with_commitment(&token_account, rpc_client.commitment()) {
+            Ok(response) => match response.value {
+                Some(account) => account,
+                None => {
+                    return error_response(Error::TokenAccountNotFound(token_account.to_string()));
+                }
+            },
+            Err(e) => return error_response(Error::TokenAccountsFailed(e)),
+        };
+
+    // The program refuses to close an account that still holds tokens, fail before paying the fee
+    match unpack_token_account(&account.data) {
+        Ok(data) if data.amount > 0 => {
+            return error_response(Error::TokenAccountNotEmpty {
+                account: token_account.to_string(),
+                amount: data.amount,
+            });
+        }
+        Ok(_) => {}
+        Err(e) => return error_response(e),
+    }
+
+    let mut tx = match close_token_account_transaction(
+        &keypair.pubkey(),
+        &token_mint,
+        &destination,
+        token_program,
+    ) {
+        Ok(tx) => tx,
+        Err(e) => return error_response(e),
     };
 
-    let signatures: Vec<PartialSignature> = match req
-        .signatures
-        .iter()
-        .map(|s| PartialSignature::deserialize_bs58(s))
-        .collect::<Result<_, _>>()
+    let recent_hash = match state
+        .latest_blockhash(net, req.send_options.max_retries())
+        .await
     {
-        Ok(sigs) => sigs,
-        Err(e) => return error_response(e.to_string()),
+        Ok(hash) => hash,
+        Err(e) => return error_response(e),
     };
 
-    let tx = match sign_and_broadcast(
-        req.amount,
-        to,
-        req.memo.clone(),
-        block_hash,
-        keys,
-        signatures,
-    ) {
-        Ok(transaction) => transaction,
-        Err(e) => return error_response(e.to_string()),
-    };
+    tx.sign(&[&keypair], recent_hash);
 
-    let rpc_client = RpcClient::new(req.net.get_cluster_url().to_string());
-    let sig = match rpc_client.send_transaction(&tx) {
+    let sig = match state.send_transaction(net, &tx, &req.send_options) {
         Ok(signature) => signature,
-        Err(e) => return error_response(Error::SendTransactionFailed(e).to_string()),
+        Err(e) => return error_response(e),
     };
 
-    if let Err(e) =
-        rpc_client.confirm_transaction_with_spinner(&sig, &block_hash, rpc_client.commitment())
-    {
-        return error_response(Error::ConfirmingTransactionFailed(e).to_string());
+    if let Err(e) = confirm_transaction(
+        &rpc_client,
+        &sig,
+        &recent_hash,
+        req.send_options.confirm_timeout(),
+    ) {
+        return error_response(e);
     }
 
-    let response = AggregateSignaturesResponse {
+    let response = SplCloseAccountResponse {
+        token_account: token_account.to_string(),
+        destination: destination.to_string(),
+        reclaimed_lamports: account.lamports,
         transaction_id: sig.to_string(),
     };
     success_response(response)
 }
 
-//////////////////////// spl /////////////////////////////
-
-// token_mint = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v"
-// 6A2GHg17A2YUbLp7qma1pbvnS7deav7Tq3tthQHa8zt5
 #[handler]
-async fn spl_token_balance(req: Json<SplTokenBalanceRequest>) -> impl IntoResponse {
-    let owner = match parse_pubkey(&req.owner) {
-        Ok(addr) => addr,
-        Err(e) => return error_response(e.to_string()),
-    };
-
-    let token_mint = match parse_pubkey(&req.token_mint) {
-        Ok(mint) => mint,
-        Err(e) => return error_response(e.to_string()),
+async fn spl_set_authority(
+    req: Json<SplSetAuthorityRequest>,
+    state: Data<&AppState>,
+) -> impl IntoResponse {
+    let net = match state.config.resolve_network(req.net) {
+        Ok(net) => net,
+        Err(e) => return error_response(e),
     };
 
-    let rpc_client = RpcClient::new(req.net.get_cluster_url().to_string());
-
-    // Get the associated token address
-    let token_account = get_associated_token_address(&owner, &token_mint);
+    if let Err(e) = check_safe_mode(&state.config, net) {
+        return error_response(e);
+    }
 
-    // Get token account info
-    let account_info = match rpc_client.get_account(&token_account) {
-        Ok(account) => account,
-        Err(_) => return error_response("Token account not found".to_string()),
-    };
+    if let Err(e) = check_mainnet_confirmed(net, &req.send_options) {
+        return error_response(e);
+    }
 
-    // Parse the token account data
-    let token_account_data = match Account::unpack(&account_info.data) {
-        Ok(data) => data,
-        Err(e) => return error_response(format!("Failed to parse token account: {}", e)),
+    let keypair = match parse_keypair_bs58(&req.keypair) {
+        Ok(kp) => kp,
+        Err(e) => return error_response(e),
     };
 
-    // Get mint info to get decimals
-    let mint_info = match rpc_client.get_account(&token_mint) {
+    let account = match parse_pubkey(&req.token_account_or_mint) {
         Ok(account) => account,
-        Err(_) => return error_response("Token mint not found".to_string()),
+        Err(e) => return error_response(e),
     };
 
-    let mint_data = match Mint::unpack(&mint_info.data) {
-        Ok(data) => data,
-        Err(e) => return error_response(format!("Failed to parse mint account: {}", e)),
+    let new_authority = match req.new_authority.as_deref().map(parse_pubkey).transpose() {
+        Ok(new_authority) => new_authority,
+        Err(e) => return error_response(e),
     };
 
-    let response = SplTokenBalanceResponse {
-        owner: owner.to_string(),
-        token_mint: token_mint.to_string(),
-        balance: token_account_data.amount,
-        decimals: mint_data.decimals,
-    };
-    success_response(response)
-}
+    if req.authority_type == SplAuthorityType::Owner && new_authority.is_none() {
+        return error_response(Error::InvalidRequest(
+            "A token account always has an owner, new_authority is required".to_string(),
+        ));
+    }
 
-#[handler]
-async fn spl_send_single(req: Json<SplSendSingleRequest>) -> impl IntoResponse {
-    let keypair = match parse_keypair_bs58(&req.keypair) {
-        Ok(kp) => kp,
-        Err(e) => return error_response(e.to_string()),
+    let kind = if req.authority_type.is_mint_authority() {
+        "token mint"
+    } else {
+        "token account"
     };
 
-    let to = match parse_pubkey(&req.to) {
-        Ok(addr) => addr,
-        Err(e) => return error_response(e.to_string()),
+    let rpc_client = state.rpc_client(net);
+    let account_info = match get_optional_account(&rpc_client, &account) {
+        Ok(Some(account_info)) => account_info,
+        Ok(None) if req.authority_type.is_mint_authority() => {
+            return error_response(Error::TokenMintNotFound);
+        }
+        Ok(None) => return error_response(Error::TokenAccountNotFound(account.to_string())),
+        Err(e) => return error_response(e),
     };
 
-    let token_mint = match parse_pubkey(&req.token_mint) {
-        Ok(mint) => mint,
-        Err(e) => return error_response(e.to_string()),
+    let Some(token_program) = TokenProgram::from_owner(&account_info.owner) else {
+        return error_response(Error::InvalidRequest(format!(
+            "{} is not a {}, it is owned by {}",
+            account, kind, account_info.owner
+        )));
     };
 
-    let rpc_client = RpcClient::new(req.net.get_cluster_url().to_string());
-
-    // Convert amount to proper token units
-    let token_amount = (req.amount * 10_f64.powi(req.decimals as i32)) as u64;
-
-    //Derive ATAs
-    let from_ata =
-        spl_associated_token_account::get_associated_token_address(&keypair.pubkey(), &token_mint);
-    let to_ata = spl_associated_token_account::get_associated_token_address(&to, &token_mint);
-
-    //checking if destination ATA exists
-    let to_ata_exists = match rpc_client.get_account(&to_ata) {
-        Ok(_) => true,
-        Err(_) => false,
+    // Checked here so a wrong keypair or account kind fails before paying the fee
+    let current = match current_authority(&account_info.data, req.authority_type) {
+        Ok(current) => current,
+        Err(_) => {
+            return error_response(Error::InvalidRequest(format!(
+                "{} is not a {}",
+                account, kind
+            )));
+        }
     };
-
-    let mut instructions = vec![];
-
-    // Create destination ATA if it doesn't exist
-    if !to_ata_exists {
-        let create_ata_instruction =
-            spl_associated_token_account::instruction::create_associated_token_account(
-                &keypair.pubkey(), // Payer
-                &to,               // Owner
-                &token_mint,       // Mint
-                &spl_token::id(),  // Token program
-            );
-        instructions.push(create_ata_instruction);
+    let authority_type = req.authority_type.as_str();
+    if current != Some(keypair.pubkey()) {
+        return error_response(Error::InvalidRequest(match current {
+            Some(current) => format!(
+                "{} is not the {} authority of {}, {} is",
+                keypair.pubkey(),
+                authority_type,
+                account,
+                current
+            ),
+            None => format!(
+                "The {} authority of {} has been removed",
+                authority_type, account
+            ),
+        }));
     }
 
-    // Create transfer instruction
-    let transfer_instruction = match spl_token::instruction::transfer(
-        &spl_token::id(),
-        &from_ata,
-        &to_ata,
+    let mut tx = match set_authority_transaction(
+        &account,
         &keypair.pubkey(),
-        &[],
-        token_amount,
+        req.authority_type,
+        new_authority.as_ref(),
+        token_program,
     ) {
-        Ok(instr) => instr,
-        Err(e) => return error_response(e.to_string()),
+        Ok(tx) => tx,
+        Err(e) => return error_response(e),
     };
 
-    instructions.push(transfer_instruction);
-
-    // Add memo if provided
-    if let Some(memo) = req.memo.clone() {
-        instructions.push(spl_memo::build_memo(memo.as_bytes(), &[]));
-    }
-
-    // Create and sign transaction
-    let recent_hash = match rpc_client.get_latest_blockhash() {
+    let recent_hash = match state
+        .latest_blockhash(net, req.send_options.max_retries())
+        .await
+    {
         Ok(hash) => hash,
-        Err(e) => return error_response(Error::RecentHashFailed(e).to_string()),
+        Err(e) => return error_response(e),
     };
 
-    let mut tx = Transaction::new_with_payer(&instructions, Some(&keypair.pubkey()));
     tx.sign(&[&keypair], recent_hash);
 
-    // Send transaction
-    let sig = match rpc_client.send_transaction(&tx) {
+    let sig = match state.send_transaction(net, &tx, &req.send_options) {
         Ok(signature) => signature,
-        Err(e) => {
-            if let Some(rpc_err) = e.get_transaction_error() {
-                eprintln!("Transaction error details: {:?}", rpc_err);
-            }
-            return error_response(Error::SendTransactionFailed(e).to_string());
-        }
+        Err(e) => return error_response(e),
     };
 
-    // Confirm transaction
-    if let Err(e) =
-        rpc_client.confirm_transaction_with_spinner(&sig, &recent_hash, rpc_client.commitment())
-    {
-        return error_response(Error::ConfirmingTransactionFailed(e).to_string());
+    if let Err(e) = confirm_transaction(
+        &rpc_client,
+        &sig,
+        &recent_hash,
+        req.send_options.confirm_timeout(),
+    ) {
+        return error_response(e);
     }
 
-    let response = SplSendSingleResponse {
+    let response = SplSetAuthorityResponse {
+        token_account_or_mint: account.to_string(),
+        authority_type: req.authority_type,
+        new_authority: new_authority.map(|authority| authority.to_string()),
         transaction_id: sig.to_string(),
     };
     success_response(response)
 }
 
 #[handler]
-async fn spl_agg_send_step_two(req: Json<SplAggSendStepTwoRequest>) -> impl IntoResponse {
+async fn spl_agg_send_step_two(
+    req: Json<SplAggSendStepTwoRequest>,
+    state: Data<&AppState>,
+) -> impl IntoResponse {
+    let net = match state.config.resolve_network(req.net) {
+        Ok(net) => net,
+        Err(e) => return error_response(e),
+    };
+
+    if let Err(e) = check_serialization_version(req.serialization_version) {
+        return error_response(e);
+    }
+
+    let amount = match parse_amount(req.amount) {
+        Ok(amount) => amount,
+        Err(e) => return error_response(e),
+    };
+
+    if let Err(e) = check_memo(req.memo.as_deref(), state.config.max_memo_bytes) {
+        return error_response(e);
+    }
+
     let keypair = match parse_keypair_bs58(&req.keypair) {
         Ok(kp) => kp,
-        Err(e) => return error_response(e.to_string()),
+        Err(e) => return error_response(e),
     };
 
     let to = match parse_pubkey(&req.to) {
         Ok(addr) => addr,
-        Err(e) => return error_response(e.to_string()),
+        Err(e) => return error_response(e),
     };
 
     let token_mint = match parse_pubkey(&req.token_mint) {
         Ok(mint) => mint,
-        Err(e) => return error_response(e.to_string()),
+        Err(e) => return error_response(e),
     };
 
     let block_hash = match parse_hash(&req.recent_block_hash) {
         Ok(hash) => hash,
-        Err(e) => return error_response(e.to_string()),
+        Err(e) => return error_response(e),
     };
 
     let keys: Vec<Pubkey> = match req
@@ -570,27 +3514,30 @@ async fn spl_agg_send_step_two(req: Json<SplAggSendStepTwoRequest>) -> impl Into
         .collect::<Result<_, _>>()
     {
         Ok(keys) => keys,
-        Err(e) => return error_response(e.to_string()),
+        Err(e) => return error_response(e),
     };
 
     let first_messages: Vec<AggMessage1> = match req
         .first_messages
         .iter()
-        .map(|m| AggMessage1::deserialize_bs58(m))
+        .map(|m| AggMessage1::deserialize_bs58(m).with_field("first_messages"))
         .collect::<Result<_, _>>()
     {
         Ok(msgs) => msgs,
-        Err(e) => return error_response(e.to_string()),
+        Err(e) => return error_response(e),
     };
 
-    let secret_state = match SecretAggStepOne::deserialize_bs58(&req.secret_state) {
-        Ok(state) => state,
-        Err(e) => return error_response(e.to_string()),
-    };
+    let secret_state =
+        match SecretAggStepOne::deserialize_bs58(&req.secret_state).with_field("secret_state") {
+            Ok(state) => state,
+            Err(e) => return error_response(e),
+        };
 
+    let rpc_client = state.rpc_client(net);
     let sig = match spl_step_two(
+        &rpc_client,
         keypair,
-        req.amount,
+        amount,
         to,
         token_mint,
         req.decimals,
@@ -599,32 +3546,63 @@ async fn spl_agg_send_step_two(req: Json<SplAggSendStepTwoRequest>) -> impl Into
         keys,
         first_messages,
         secret_state,
+        req.token_program,
     ) {
         Ok(signature) => signature,
-        Err(e) => return error_response(e.to_string()),
+        Err(e) => return error_response(e),
     };
 
     let response = SplAggSendStepTwoResponse {
         partial_signature: sig.serialize_bs58(),
+        serialization_version: SERIALIZATION_VERSION,
     };
     success_response(response)
 }
 
 #[handler]
-async fn spl_aggregate_signatures(req: Json<SplAggregateSignaturesRequest>) -> impl IntoResponse {
+async fn spl_aggregate_signatures(
+    req: Json<SplAggregateSignaturesRequest>,
+    state: Data<&AppState>,
+) -> impl IntoResponse {
+    let net = match state.config.resolve_network(req.net) {
+        Ok(net) => net,
+        Err(e) => return error_response(e),
+    };
+
+    if let Err(e) = check_serialization_version(req.serialization_version) {
+        return error_response(e);
+    }
+
+    if let Err(e) = check_safe_mode(&state.config, net) {
+        return error_response(e);
+    }
+
+    if let Err(e) = check_mainnet_confirmed(net, &req.send_options) {
+        return error_response(e);
+    }
+
+    let amount = match parse_amount(req.amount) {
+        Ok(amount) => amount,
+        Err(e) => return error_response(e),
+    };
+
+    if let Err(e) = check_memo(req.memo.as_deref(), state.config.max_memo_bytes) {
+        return error_response(e);
+    }
+
     let to = match parse_pubkey(&req.to) {
         Ok(addr) => addr,
-        Err(e) => return error_response(e.to_string()),
+        Err(e) => return error_response(e),
     };
 
     let token_mint = match parse_pubkey(&req.token_mint) {
         Ok(mint) => mint,
-        Err(e) => return error_response(e.to_string()),
+        Err(e) => return error_response(e),
     };
 
     let block_hash = match parse_hash(&req.recent_block_hash) {
         Ok(hash) => hash,
-        Err(e) => return error_response(e.to_string()),
+        Err(e) => return error_response(e),
     };
 
     let keys: Vec<Pubkey> = match req
@@ -634,21 +3612,23 @@ async fn spl_aggregate_signatures(req: Json<SplAggregateSignaturesRequest>) -> i
         .collect::<Result<_, _>>()
     {
         Ok(keys) => keys,
-        Err(e) => return error_response(e.to_string()),
+        Err(e) => return error_response(e),
     };
 
     let signatures: Vec<PartialSignature> = match req
         .signatures
         .iter()
-        .map(|s| PartialSignature::deserialize_bs58(s))
+        .map(|s| PartialSignature::deserialize_bs58(s).with_field("signatures"))
         .collect::<Result<_, _>>()
     {
         Ok(sigs) => sigs,
-        Err(e) => return error_response(e.to_string()),
+        Err(e) => return error_response(e),
     };
 
+    let rpc_client = state.rpc_client(net);
     let tx = match spl_sign_and_broadcast(
-        req.amount,
+        &rpc_client,
+        amount,
         to,
         token_mint,
         req.decimals,
@@ -656,25 +3636,31 @@ async fn spl_aggregate_signatures(req: Json<SplAggregateSignaturesRequest>) -> i
         block_hash,
         keys,
         signatures,
+        req.token_program,
     ) {
         Ok(transaction) => transaction,
-        Err(e) => return error_response(e.to_string()),
+        Err(e) => return error_response(e),
     };
 
-    let rpc_client = RpcClient::new(req.net.get_cluster_url().to_string());
-    let sig = match rpc_client.send_transaction(&tx) {
+    let sig = match state.send_transaction(net, &tx, &req.send_options) {
         Ok(signature) => signature,
-        Err(e) => return error_response(Error::SendTransactionFailed(e).to_string()),
+        Err(e) => return error_response(e),
     };
 
-    if let Err(e) =
-        rpc_client.confirm_transaction_with_spinner(&sig, &block_hash, rpc_client.commitment())
-    {
-        return error_response(Error::ConfirmingTransactionFailed(e).to_string());
+    if let Err(e) = confirm_transaction(
+        &rpc_client,
+        &sig,
+        &block_hash,
+        req.send_options.confirm_timeout(),
+    ) {
+        return error_response(e);
     }
 
+    let (slot, block_time) = get_landed_slot(&rpc_client, &sig);
     let response = SplAggregateSignaturesResponse {
         transaction_id: sig.to_string(),
+        slot,
+        block_time,
     };
     success_response(response)
 }
@@ -683,167 +3669,376 @@ async fn spl_aggregate_signatures(req: Json<SplAggregateSignaturesRequest>) -> i
 //
 
 #[handler]
-async fn stake_account(req: Json<StakeAccountRequest>) -> impl IntoResponse {
+async fn stake_account(
+    req: Json<StakeAccountRequest>,
+    state: Data<&AppState>,
+) -> impl IntoResponse {
+    let net = match state.config.resolve_network(req.net) {
+        Ok(net) => net,
+        Err(e) => return error_response(e),
+    };
+
+    if let Err(e) = check_safe_mode(&state.config, net) {
+        return error_response(e);
+    }
+
+    if let Err(e) = check_mainnet_confirmed(net, &req.send_options) {
+        return error_response(e);
+    }
+
+    let amount = match resolve_lamports(req.amount_sol, req.amount_lamports) {
+        Ok(amount) => amount,
+        Err(e) => return error_response(e),
+    };
+
     let keypair = match parse_keypair_bs58(&req.keypair) {
         Ok(kp) => kp,
-        Err(e) => return error_response(e.to_string()),
+        Err(e) => return error_response(e),
     };
 
     let vote_account = match parse_pubkey(&req.validator_vote_accont) {
         Ok(vc) => vc,
-        Err(e) => return error_response(e.to_string()),
+        Err(e) => return error_response(e),
+    };
+
+    let stake_account = match stake_account_address(&keypair.pubkey(), &req.seed) {
+        Ok(addr) => addr,
+        Err(e) => return error_response(e),
     };
 
-    let rpc_client = RpcClient::new(req.net.get_cluster_url().to_string());
+    let rpc_client = state.rpc_client(net);
+    if let Err(e) = check_minimum_delegation(&rpc_client, amount) {
+        return error_response(e);
+    }
+
     let mut tx = match create_stake_account_transaction(
-        req.stake_amount,
+        &rpc_client,
+        amount,
         &req.seed,
         &keypair.pubkey(),
         &vote_account,
     ) {
         Ok(tx) => tx,
-        Err(e) => return error_response(e.to_string()),
+        Err(e) => return error_response(e),
     };
 
-    let recent_hash = match rpc_client.get_latest_blockhash() {
+    let recent_hash = match state
+        .latest_blockhash(net, req.send_options.max_retries())
+        .await
+    {
         Ok(hash) => hash,
-        Err(e) => return error_response(Error::RecentHashFailed(e).to_string()),
+        Err(e) => return error_response(e),
     };
 
     tx.sign(&[&keypair], recent_hash);
 
-    let sig = match rpc_client.send_transaction(&tx) {
+    let sig = match state.send_transaction(net, &tx, &req.send_options) {
         Ok(signature) => signature,
-        Err(e) => return error_response(Error::SendTransactionFailed(e).to_string()),
+        Err(e) => return error_response(e),
+    };
+
+    if let Err(e) = confirm_transaction(
+        &rpc_client,
+        &sig,
+        &recent_hash,
+        req.send_options.confirm_timeout(),
+    ) {
+        return error_response(e);
+    }
+
+    let response = StakeAccountResponse {
+        stake_account_address: stake_account.to_string(),
+        transaction_id: sig.to_string(),
+    };
+    success_response(response)
+}
+
+/// Preview of the address /api/stake creates, nothing is read from the cluster
+#[handler]
+async fn derive_stake_account(req: Json<DeriveStakeAccountRequest>) -> impl IntoResponse {
+    let payer = match parse_pubkey(&req.payer) {
+        Ok(addr) => addr,
+        Err(e) => return error_response(e),
     };
 
-    if let Err(e) =
-        rpc_client.confirm_transaction_with_spinner(&sig, &recent_hash, rpc_client.commitment())
-    {
-        return error_response(Error::ConfirmingTransactionFailed(e).to_string());
-    }
-
-    let stake_account = match Pubkey::create_with_seed(
-        &keypair.pubkey(),
-        &req.seed,
-        &solana_sdk::stake::program::id(),
-    ) {
+    let stake_account = match stake_account_address(&payer, &req.seed) {
         Ok(addr) => addr,
-        Err(_) => {
-            return error_response("Invalid stake account seed".to_string());
-        }
+        Err(e) => return error_response(e),
     };
 
-    let response = StakeAccountResponse {
+    let response = DeriveStakeAccountResponse {
         stake_account_address: stake_account.to_string(),
-        transaction_id: sig.to_string(),
     };
     success_response(response)
 }
 
 #[handler]
-async fn deactivate_stake(req: Json<DeactivateStakeRequest>) -> impl IntoResponse {
+async fn deactivate_stake(
+    req: Json<DeactivateStakeRequest>,
+    state: Data<&AppState>,
+) -> impl IntoResponse {
+    let net = match state.config.resolve_network(req.net) {
+        Ok(net) => net,
+        Err(e) => return error_response(e),
+    };
+
+    if let Err(e) = check_safe_mode(&state.config, net) {
+        return error_response(e);
+    }
+
+    if let Err(e) = check_mainnet_confirmed(net, &req.send_options) {
+        return error_response(e);
+    }
+
     let keypair = match parse_keypair_bs58(&req.keypair) {
         Ok(kp) => kp,
-        Err(e) => return error_response(e.to_string()),
+        Err(e) => return error_response(e),
     };
 
     let stake_accountt = match parse_pubkey(&req.stake_account) {
         Ok(addr) => addr,
-        Err(e) => return error_response(e.to_string()),
+        Err(e) => return error_response(e),
     };
 
-    let rpc_client = RpcClient::new(req.net.get_cluster_url().to_string());
+    let auto_withdraw_destination = match req.auto_withdraw_destination.as_deref().map(parse_pubkey)
+    {
+        None => None,
+        Some(Ok(destination)) => Some(destination),
+        Some(Err(e)) => return error_response(e),
+    };
+
+    let rpc_client = state.rpc_client(net);
+
+    // The scheduled withdrawal is signed with the same keypair, refuse it before deactivating
+    if auto_withdraw_destination.is_some() {
+        let withdrawer = match get_stake_account(&rpc_client, &stake_accountt) {
+            Ok((_, stake_state)) => stake_state.authorized().map(|a| a.withdrawer),
+            Err(e) => return error_response(e),
+        };
+        if withdrawer != Some(keypair.pubkey()) {
+            return error_response(Error::InvalidRequest(format!(
+                "auto_withdraw_destination needs the withdraw authority of {} as keypair",
+                stake_accountt
+            )));
+        }
+    }
+
     let mut tx = create_deactivate_stake_transaction(&stake_accountt, &keypair.pubkey());
 
-    let recent_hash = match rpc_client.get_latest_blockhash() {
+    let recent_hash = match state
+        .latest_blockhash(net, req.send_options.max_retries())
+        .await
+    {
         Ok(hash) => hash,
-        Err(e) => return error_response(Error::RecentHashFailed(e).to_string()),
+        Err(e) => return error_response(e),
     };
 
     tx.sign(&[&keypair], recent_hash);
 
-    let sig = match rpc_client.send_transaction(&tx) {
+    let sig = match state.send_transaction(net, &tx, &req.send_options) {
         Ok(signature) => signature,
-        Err(e) => return error_response(Error::SendTransactionFailed(e).to_string()),
+        Err(e) => return error_response(e),
     };
 
-    if let Err(e) =
-        rpc_client.confirm_transaction_with_spinner(&sig, &recent_hash, rpc_client.commitment())
-    {
-        return error_response(Error::ConfirmingTransactionFailed(e).to_string());
+    if let Err(e) = confirm_transaction(
+        &rpc_client,
+        &sig,
+        &recent_hash,
+        req.send_options.confirm_timeout(),
+    ) {
+        return error_response(e);
     }
 
+    // Deactivation went through, a full schedule only means withdrawing by hand
+    let auto_withdraw_scheduled = match auto_withdraw_destination {
+        Some(destination) => {
+            let withdrawal = ScheduledWithdrawal::new(net, stake_accountt, destination, keypair);
+            match state.auto_withdraw.schedule(withdrawal) {
+                Ok(()) => true,
+                Err(e) => {
+                    tracing::warn!("Auto withdraw not scheduled: {}", e);
+                    false
+                }
+            }
+        }
+        None => false,
+    };
+
     let response = DeactivateStakeResponse {
         transaction_id: sig.to_string(),
+        auto_withdraw_scheduled,
     };
     success_response(response)
 }
 
 #[handler]
-async fn withdraw_stake(req: Json<WithdrawStakeRequest>) -> impl IntoResponse {
+async fn withdraw_stake(
+    req: Json<WithdrawStakeRequest>,
+    state: Data<&AppState>,
+) -> impl IntoResponse {
+    let net = match state.config.resolve_network(req.net) {
+        Ok(net) => net,
+        Err(e) => return error_response(e),
+    };
+
+    if let Err(e) = check_safe_mode(&state.config, net) {
+        return error_response(e);
+    }
+
+    if let Err(e) = check_mainnet_confirmed(net, &req.send_options) {
+        return error_response(e);
+    }
+
+    // No amount, or 0 lamports, withdraws everything that is withdrawable right now
+    let amount = match (req.amount_sol, req.amount_lamports) {
+        (None, None | Some(0)) => None,
+        (amount_sol, amount_lamports) => match resolve_lamports(amount_sol, amount_lamports) {
+            Ok(amount) => Some(amount),
+            Err(e) => return error_response(e),
+        },
+    };
+
     let keypair = match parse_keypair_bs58(&req.keypair) {
         Ok(kp) => kp,
-        Err(e) => return error_response(e.to_string()),
+        Err(e) => return error_response(e),
     };
 
     let stake_accountt = match parse_pubkey(&req.stake_account) {
         Ok(addr) => addr,
-        Err(e) => return error_response(e.to_string()),
+        Err(e) => return error_response(e),
     };
 
     let destination = match parse_pubkey(&req.destination) {
         Ok(addr) => addr,
-        Err(e) => return error_response(e.to_string()),
+        Err(e) => return error_response(e),
     };
 
-    let rpc_client = RpcClient::new(req.net.get_cluster_url().to_string());
-    let mut tx = create_withdraw_stake_transaction(
-        &stake_accountt,
-        &destination,
-        &keypair.pubkey(),
-        req.amount,
-    );
+    if req.close && amount.is_some() {
+        return error_response(Error::InvalidRequest(
+            "close withdraws the whole balance, leave out the amount".to_string(),
+        ));
+    }
+
+    let rpc_client = state.rpc_client(net);
+    let amount = match amount {
+        None if req.close => match get_closable(&rpc_client, &stake_accountt) {
+            Ok(lamports) => lamports,
+            Err(e) => return error_response(e),
+        },
+        Some(amount) => {
+            if req.check_state.unwrap_or(true) {
+                if let Err(e) = check_withdrawable(&rpc_client, &stake_accountt, amount) {
+                    return error_response(e);
+                }
+            }
+            amount
+        }
+        None => match get_withdrawable(&rpc_client, &stake_accountt) {
+            Ok((_, 0)) => {
+                return error_response(Error::WithdrawalFailed(
+                    "nothing above the rent-exempt reserve, set close to withdraw it and close the account"
+                        .to_string(),
+                ));
+            }
+            Ok((_, withdrawable)) => withdrawable,
+            Err(e) => return error_response(e),
+        },
+    };
+
+    let mut tx =
+        create_withdraw_stake_transaction(&stake_accountt, &destination, &keypair.pubkey(), amount);
 
-    let recent_hash = match rpc_client.get_latest_blockhash() {
+    let recent_hash = match state
+        .latest_blockhash(net, req.send_options.max_retries())
+        .await
+    {
         Ok(hash) => hash,
-        Err(e) => return error_response(Error::RecentHashFailed(e).to_string()),
+        Err(e) => return error_response(e),
     };
 
     tx.sign(&[&keypair], recent_hash);
 
-    let sig = match rpc_client.send_transaction(&tx) {
+    let sig = match state.send_transaction(net, &tx, &req.send_options) {
         Ok(signature) => signature,
-        Err(e) => return error_response(Error::SendTransactionFailed(e).to_string()),
+        Err(e) => return error_response(e),
     };
 
-    if let Err(e) =
-        rpc_client.confirm_transaction_with_spinner(&sig, &recent_hash, rpc_client.commitment())
-    {
-        return error_response(Error::ConfirmingTransactionFailed(e).to_string());
+    if let Err(e) = confirm_transaction(
+        &rpc_client,
+        &sig,
+        &recent_hash,
+        req.send_options.confirm_timeout(),
+    ) {
+        return error_response(e);
     }
 
     let response = WithdrawStakeResponse {
         transaction_id: sig.to_string(),
+        amount_lamports: amount,
     };
     success_response(response)
 }
 
 #[handler]
-async fn agg_stake_step_two(req: Json<AggStakeStepTwoRequest>) -> impl IntoResponse {
+async fn agg_stake_step_one(req: Json<AggStakeStepOneRequest>) -> impl IntoResponse {
+    match agg_step_one(&req.keypair) {
+        Ok(response) => success_response(response),
+        Err(e) => error_response(e),
+    }
+}
+
+#[handler]
+async fn agg_deactivate_stake_step_one(
+    req: Json<AggDeactivateStakeStepOneRequest>,
+) -> impl IntoResponse {
+    match agg_step_one(&req.keypair) {
+        Ok(response) => success_response(response),
+        Err(e) => error_response(e),
+    }
+}
+
+#[handler]
+async fn agg_withdraw_stake_step_one(
+    req: Json<AggWithdrawStakeStepOneRequest>,
+) -> impl IntoResponse {
+    match agg_step_one(&req.keypair) {
+        Ok(response) => success_response(response),
+        Err(e) => error_response(e),
+    }
+}
+
+#[handler]
+async fn agg_stake_step_two(
+    req: Json<AggStakeStepTwoRequest>,
+    state: Data<&AppState>,
+) -> impl IntoResponse {
+    let net = match state.config.resolve_network(req.net) {
+        Ok(net) => net,
+        Err(e) => return error_response(e),
+    };
+
+    if let Err(e) = check_serialization_version(req.serialization_version) {
+        return error_response(e);
+    }
+
+    let amount = match resolve_lamports(req.amount_sol, req.amount_lamports) {
+        Ok(amount) => amount,
+        Err(e) => return error_response(e),
+    };
+
     let keypair = match parse_keypair_bs58(&req.keypair) {
         Ok(kp) => kp,
-        Err(e) => return error_response(e.to_string()),
+        Err(e) => return error_response(e),
     };
 
     let vote_account = match parse_pubkey(&req.validator_vote_accont) {
         Ok(vc) => vc,
-        Err(e) => return error_response(e.to_string()),
+        Err(e) => return error_response(e),
     };
 
     let block_hash = match parse_hash(&req.recent_block_hash) {
         Ok(hash) => hash,
-        Err(e) => return error_response(e.to_string()),
+        Err(e) => return error_response(e),
     };
 
     let keys: Vec<Pubkey> = match req
@@ -853,27 +4048,30 @@ async fn agg_stake_step_two(req: Json<AggStakeStepTwoRequest>) -> impl IntoRespo
         .collect::<Result<_, _>>()
     {
         Ok(keys) => keys,
-        Err(e) => return error_response(e.to_string()),
+        Err(e) => return error_response(e),
     };
 
     let first_messages: Vec<AggMessage1> = match req
         .first_messages
         .iter()
-        .map(|m| AggMessage1::deserialize_bs58(m))
+        .map(|m| AggMessage1::deserialize_bs58(m).with_field("first_messages"))
         .collect::<Result<_, _>>()
     {
         Ok(msgs) => msgs,
-        Err(e) => return error_response(e.to_string()),
+        Err(e) => return error_response(e),
     };
 
-    let secret_state = match SecretAggStepOne::deserialize_bs58(&req.secret_state) {
-        Ok(state) => state,
-        Err(e) => return error_response(e.to_string()),
-    };
+    let secret_state =
+        match SecretAggStepOne::deserialize_bs58(&req.secret_state).with_field("secret_state") {
+            Ok(state) => state,
+            Err(e) => return error_response(e),
+        };
 
+    let rpc_client = state.rpc_client(net);
     let sig = match stake_step_two(
+        &rpc_client,
         keypair,
-        req.stake_amount,
+        amount,
         req.seed.clone(),
         vote_account,
         block_hash,
@@ -882,11 +4080,12 @@ async fn agg_stake_step_two(req: Json<AggStakeStepTwoRequest>) -> impl IntoRespo
         secret_state,
     ) {
         Ok(signature) => signature,
-        Err(e) => return error_response(e.to_string()),
+        Err(e) => return error_response(e),
     };
 
     let response = AggStakeStepTwoResponse {
         partial_signature: sig.serialize_bs58(),
+        serialization_version: SERIALIZATION_VERSION,
     };
     success_response(response)
 }
@@ -895,19 +4094,23 @@ async fn agg_stake_step_two(req: Json<AggStakeStepTwoRequest>) -> impl IntoRespo
 async fn agg_deactivate_stake_step_two(
     req: Json<AggDeactivateStakeStepTwoRequest>,
 ) -> impl IntoResponse {
+    if let Err(e) = check_serialization_version(req.serialization_version) {
+        return error_response(e);
+    }
+
     let keypair = match parse_keypair_bs58(&req.keypair) {
         Ok(kp) => kp,
-        Err(e) => return error_response(e.to_string()),
+        Err(e) => return error_response(e),
     };
 
     let stake_accountt = match parse_pubkey(&req.stake_account) {
         Ok(addr) => addr,
-        Err(e) => return error_response(e.to_string()),
+        Err(e) => return error_response(e),
     };
 
     let block_hash = match parse_hash(&req.recent_block_hash) {
         Ok(hash) => hash,
-        Err(e) => return error_response(e.to_string()),
+        Err(e) => return error_response(e),
     };
 
     let keys: Vec<Pubkey> = match req
@@ -917,23 +4120,24 @@ async fn agg_deactivate_stake_step_two(
         .collect::<Result<_, _>>()
     {
         Ok(keys) => keys,
-        Err(e) => return error_response(e.to_string()),
+        Err(e) => return error_response(e),
     };
 
     let first_messages: Vec<AggMessage1> = match req
         .first_messages
         .iter()
-        .map(|m| AggMessage1::deserialize_bs58(m))
+        .map(|m| AggMessage1::deserialize_bs58(m).with_field("first_messages"))
         .collect::<Result<_, _>>()
     {
         Ok(msgs) => msgs,
-        Err(e) => return error_response(e.to_string()),
+        Err(e) => return error_response(e),
     };
 
-    let secret_state = match SecretAggStepOne::deserialize_bs58(&req.secret_state) {
-        Ok(state) => state,
-        Err(e) => return error_response(e.to_string()),
-    };
+    let secret_state =
+        match SecretAggStepOne::deserialize_bs58(&req.secret_state).with_field("secret_state") {
+            Ok(state) => state,
+            Err(e) => return error_response(e),
+        };
 
     let sig = match deactivate_stake_step_two(
         keypair,
@@ -944,11 +4148,12 @@ async fn agg_deactivate_stake_step_two(
         secret_state,
     ) {
         Ok(signature) => signature,
-        Err(e) => return error_response(e.to_string()),
+        Err(e) => return error_response(e),
     };
 
     let response = AggDeactivateStakeStepTwoResponse {
         partial_signature: sig.serialize_bs58(),
+        serialization_version: SERIALIZATION_VERSION,
     };
     success_response(response)
 }
@@ -957,24 +4162,33 @@ async fn agg_deactivate_stake_step_two(
 async fn agg_withdraw_stake_step_two(
     req: Json<AggWithdrawStakeStepTwoRequest>,
 ) -> impl IntoResponse {
+    if let Err(e) = check_serialization_version(req.serialization_version) {
+        return error_response(e);
+    }
+
+    let amount = match resolve_lamports(req.amount_sol, req.amount_lamports) {
+        Ok(amount) => amount,
+        Err(e) => return error_response(e),
+    };
+
     let keypair = match parse_keypair_bs58(&req.keypair) {
         Ok(kp) => kp,
-        Err(e) => return error_response(e.to_string()),
+        Err(e) => return error_response(e),
     };
 
     let stake_accountt = match parse_pubkey(&req.stake_account) {
         Ok(addr) => addr,
-        Err(e) => return error_response(e.to_string()),
+        Err(e) => return error_response(e),
     };
 
     let destination = match parse_pubkey(&req.destination) {
         Ok(addr) => addr,
-        Err(e) => return error_response(e.to_string()),
+        Err(e) => return error_response(e),
     };
 
     let block_hash = match parse_hash(&req.recent_block_hash) {
         Ok(hash) => hash,
-        Err(e) => return error_response(e.to_string()),
+        Err(e) => return error_response(e),
     };
 
     let keys: Vec<Pubkey> = match req
@@ -984,40 +4198,42 @@ async fn agg_withdraw_stake_step_two(
         .collect::<Result<_, _>>()
     {
         Ok(keys) => keys,
-        Err(e) => return error_response(e.to_string()),
+        Err(e) => return error_response(e),
     };
 
     let first_messages: Vec<AggMessage1> = match req
         .first_messages
         .iter()
-        .map(|m| AggMessage1::deserialize_bs58(m))
+        .map(|m| AggMessage1::deserialize_bs58(m).with_field("first_messages"))
         .collect::<Result<_, _>>()
     {
         Ok(msgs) => msgs,
-        Err(e) => return error_response(e.to_string()),
+        Err(e) => return error_response(e),
     };
 
-    let secret_state = match SecretAggStepOne::deserialize_bs58(&req.secret_state) {
-        Ok(state) => state,
-        Err(e) => return error_response(e.to_string()),
-    };
+    let secret_state =
+        match SecretAggStepOne::deserialize_bs58(&req.secret_state).with_field("secret_state") {
+            Ok(state) => state,
+            Err(e) => return error_response(e),
+        };
 
     let sig = match withdraw_stake_step_two(
         keypair,
         stake_accountt,
         destination,
-        req.amount,
+        amount,
         block_hash,
         keys,
         first_messages,
         secret_state,
     ) {
         Ok(signature) => signature,
-        Err(e) => return error_response(e.to_string()),
+        Err(e) => return error_response(e),
     };
 
     let response = AggWithdrawStakeStepTwoResponse {
         partial_signature: sig.serialize_bs58(),
+        serialization_version: SERIALIZATION_VERSION,
     };
     success_response(response)
 }
@@ -1025,15 +4241,42 @@ async fn agg_withdraw_stake_step_two(
 #[handler]
 async fn aggregate_stake_signatures(
     req: Json<AggregateStakeSignaturesRequest>,
+    state: Data<&AppState>,
 ) -> impl IntoResponse {
+    let net = match state.config.resolve_network(req.net) {
+        Ok(net) => net,
+        Err(e) => return error_response(e),
+    };
+
+    if let Err(e) = check_serialization_version(req.serialization_version) {
+        return error_response(e);
+    }
+
+    if let Err(e) = check_safe_mode(&state.config, net) {
+        return error_response(e);
+    }
+
+    if let Err(e) = check_mainnet_confirmed(net, &req.send_options) {
+        return error_response(e);
+    }
+
+    let amount = match resolve_lamports(req.amount_sol, req.amount_lamports) {
+        Ok(amount) => amount,
+        Err(e) => return error_response(e),
+    };
+
     let vote_account = match parse_pubkey(&req.validator_vote_accont) {
         Ok(vc) => vc,
-        Err(e) => return error_response(e.to_string()),
+        Err(e) => return error_response(e),
     };
 
+    if let Err(e) = check_stake_seed(&req.seed) {
+        return error_response(e);
+    }
+
     let block_hash = match parse_hash(&req.recent_block_hash) {
         Ok(hash) => hash,
-        Err(e) => return error_response(e.to_string()),
+        Err(e) => return error_response(e),
     };
 
     let keys: Vec<Pubkey> = match req
@@ -1043,21 +4286,27 @@ async fn aggregate_stake_signatures(
         .collect::<Result<_, _>>()
     {
         Ok(keys) => keys,
-        Err(e) => return error_response(e.to_string()),
+        Err(e) => return error_response(e),
     };
 
     let signatures: Vec<PartialSignature> = match req
         .signatures
         .iter()
-        .map(|s| PartialSignature::deserialize_bs58(s))
+        .map(|s| PartialSignature::deserialize_bs58(s).with_field("signatures"))
         .collect::<Result<_, _>>()
     {
         Ok(sigs) => sigs,
-        Err(e) => return error_response(e.to_string()),
+        Err(e) => return error_response(e),
     };
 
+    let rpc_client = state.rpc_client(net);
+    if let Err(e) = check_minimum_delegation(&rpc_client, amount) {
+        return error_response(e);
+    }
+
     let tx = match aggregate_stake_signatures_and_broadcast(
-        req.stake_amount,
+        &rpc_client,
+        amount,
         req.seed.clone(),
         vote_account,
         block_hash,
@@ -1065,23 +4314,28 @@ async fn aggregate_stake_signatures(
         signatures,
     ) {
         Ok(tx) => tx,
-        Err(e) => return error_response(e.to_string()),
+        Err(e) => return error_response(e),
     };
 
-    let rpc_client = RpcClient::new(req.net.get_cluster_url().to_string());
-    let sig = match rpc_client.send_transaction(&tx) {
+    let sig = match state.send_transaction(net, &tx, &req.send_options) {
         Ok(signature) => signature,
-        Err(e) => return error_response(Error::SendTransactionFailed(e).to_string()),
+        Err(e) => return error_response(e),
     };
 
-    if let Err(e) =
-        rpc_client.confirm_transaction_with_spinner(&sig, &block_hash, rpc_client.commitment())
-    {
-        return error_response(Error::ConfirmingTransactionFailed(e).to_string());
+    if let Err(e) = confirm_transaction(
+        &rpc_client,
+        &sig,
+        &block_hash,
+        req.send_options.confirm_timeout(),
+    ) {
+        return error_response(e);
     }
 
+    let (slot, block_time) = get_landed_slot(&rpc_client, &sig);
     let response = AggregateStakeSignaturesResponse {
         transaction_id: sig.to_string(),
+        slot,
+        block_time,
     };
     success_response(response)
 }
@@ -1089,15 +4343,33 @@ async fn aggregate_stake_signatures(
 #[handler]
 async fn aggregate_deactivate_stake_signatures(
     req: Json<AggregateDeactivateStakeSignaturesRequest>,
+    state: Data<&AppState>,
 ) -> impl IntoResponse {
+    let net = match state.config.resolve_network(req.net) {
+        Ok(net) => net,
+        Err(e) => return error_response(e),
+    };
+
+    if let Err(e) = check_serialization_version(req.serialization_version) {
+        return error_response(e);
+    }
+
+    if let Err(e) = check_safe_mode(&state.config, net) {
+        return error_response(e);
+    }
+
+    if let Err(e) = check_mainnet_confirmed(net, &req.send_options) {
+        return error_response(e);
+    }
+
     let stake_accountt = match parse_pubkey(&req.stake_account) {
         Ok(addr) => addr,
-        Err(e) => return error_response(e.to_string()),
+        Err(e) => return error_response(e),
     };
 
     let block_hash = match parse_hash(&req.recent_block_hash) {
         Ok(hash) => hash,
-        Err(e) => return error_response(e.to_string()),
+        Err(e) => return error_response(e),
     };
 
     let keys: Vec<Pubkey> = match req
@@ -1107,17 +4379,17 @@ async fn aggregate_deactivate_stake_signatures(
         .collect::<Result<_, _>>()
     {
         Ok(keys) => keys,
-        Err(e) => return error_response(e.to_string()),
+        Err(e) => return error_response(e),
     };
 
     let signatures: Vec<PartialSignature> = match req
         .signatures
         .iter()
-        .map(|s| PartialSignature::deserialize_bs58(s))
+        .map(|s| PartialSignature::deserialize_bs58(s).with_field("signatures"))
         .collect::<Result<_, _>>()
     {
         Ok(sigs) => sigs,
-        Err(e) => return error_response(e.to_string()),
+        Err(e) => return error_response(e),
     };
 
     let tx = match aggregate_deactivate_stake_signatures_and_broadcast(
@@ -1127,23 +4399,29 @@ async fn aggregate_deactivate_stake_signatures(
         signatures,
     ) {
         Ok(tx) => tx,
-        Err(e) => return error_response(e.to_string()),
+        Err(e) => return error_response(e),
     };
 
-    let rpc_client = RpcClient::new(req.net.get_cluster_url().to_string());
-    let sig = match rpc_client.send_transaction(&tx) {
+    let rpc_client = state.rpc_client(net);
+    let sig = match state.send_transaction(net, &tx, &req.send_options) {
         Ok(signature) => signature,
-        Err(e) => return error_response(Error::SendTransactionFailed(e).to_string()),
+        Err(e) => return error_response(e),
     };
 
-    if let Err(e) =
-        rpc_client.confirm_transaction_with_spinner(&sig, &block_hash, rpc_client.commitment())
-    {
-        return error_response(Error::ConfirmingTransactionFailed(e).to_string());
+    if let Err(e) = confirm_transaction(
+        &rpc_client,
+        &sig,
+        &block_hash,
+        req.send_options.confirm_timeout(),
+    ) {
+        return error_response(e);
     }
 
+    let (slot, block_time) = get_landed_slot(&rpc_client, &sig);
     let response = AggregateDeactivateStakeSignaturesResponse {
         transaction_id: sig.to_string(),
+        slot,
+        block_time,
     };
     success_response(response)
 }
@@ -1151,20 +4429,43 @@ async fn aggregate_deactivate_stake_signatures(
 #[handler]
 async fn aggregate_withdraw_stake_signatures(
     req: Json<AggregateWithdrawStakeSignaturesRequest>,
+    state: Data<&AppState>,
 ) -> impl IntoResponse {
+    let net = match state.config.resolve_network(req.net) {
+        Ok(net) => net,
+        Err(e) => return error_response(e),
+    };
+
+    if let Err(e) = check_serialization_version(req.serialization_version) {
+        return error_response(e);
+    }
+
+    if let Err(e) = check_safe_mode(&state.config, net) {
+        return error_response(e);
+    }
+
+    if let Err(e) = check_mainnet_confirmed(net, &req.send_options) {
+        return error_response(e);
+    }
+
+    let amount = match resolve_lamports(req.amount_sol, req.amount_lamports) {
+        Ok(amount) => amount,
+        Err(e) => return error_response(e),
+    };
+
     let stake_accountt = match parse_pubkey(&req.stake_account) {
         Ok(addr) => addr,
-        Err(e) => return error_response(e.to_string()),
+        Err(e) => return error_response(e),
     };
 
     let destination = match parse_pubkey(&req.destination) {
         Ok(addr) => addr,
-        Err(e) => return error_response(e.to_string()),
+        Err(e) => return error_response(e),
     };
 
     let block_hash = match parse_hash(&req.recent_block_hash) {
         Ok(hash) => hash,
-        Err(e) => return error_response(e.to_string()),
+        Err(e) => return error_response(e),
     };
 
     let keys: Vec<Pubkey> = match req
@@ -1174,78 +4475,480 @@ async fn aggregate_withdraw_stake_signatures(
         .collect::<Result<_, _>>()
     {
         Ok(keys) => keys,
-        Err(e) => return error_response(e.to_string()),
+        Err(e) => return error_response(e),
     };
 
     let signatures: Vec<PartialSignature> = match req
         .signatures
         .iter()
-        .map(|s| PartialSignature::deserialize_bs58(s))
+        .map(|s| PartialSignature::deserialize_bs58(s).with_field("signatures"))
         .collect::<Result<_, _>>()
     {
         Ok(sigs) => sigs,
-        Err(e) => return error_response(e.to_string()),
+        Err(e) => return error_response(e),
     };
 
+    let rpc_client = state.rpc_client(net);
+    if req.check_state.unwrap_or(true) {
+        if let Err(e) = check_withdrawable(&rpc_client, &stake_accountt, amount) {
+            return error_response(e);
+        }
+    }
+
     let tx = match aggregate_withdraw_stake_signatures_and_broadcast(
         stake_accountt,
         destination,
-        req.amount,
+        amount,
         block_hash,
         keys,
         signatures,
     ) {
         Ok(tx) => tx,
-        Err(e) => return error_response(e.to_string()),
+        Err(e) => return error_response(e),
     };
 
-    let rpc_client = RpcClient::new(req.net.get_cluster_url().to_string());
-    let sig = match rpc_client.send_transaction(&tx) {
+    let sig = match state.send_transaction(net, &tx, &req.send_options) {
         Ok(signature) => signature,
-        Err(e) => return error_response(Error::SendTransactionFailed(e).to_string()),
+        Err(e) => return error_response(e),
     };
 
-    if let Err(e) =
-        rpc_client.confirm_transaction_with_spinner(&sig, &block_hash, rpc_client.commitment())
-    {
-        return error_response(Error::ConfirmingTransactionFailed(e).to_string());
+    if let Err(e) = confirm_transaction(
+        &rpc_client,
+        &sig,
+        &block_hash,
+        req.send_options.confirm_timeout(),
+    ) {
+        return error_response(e);
     }
 
+    let (slot, block_time) = get_landed_slot(&rpc_client, &sig);
     let response = AggregateWithdrawStakeSignaturesResponse {
         transaction_id: sig.to_string(),
+        slot,
+        block_time,
+    };
+    success_response(response)
+}
+
+#[handler]
+async fn validators(req: Json<ValidatorsRequest>, state: Data<&AppState>) -> impl IntoResponse {
+    let net = match state.config.resolve_network(req.net) {
+        Ok(net) => net,
+        Err(e) => return error_response(e),
+    };
+
+    let rpc_client = state.rpc_client(net);
+    let vote_accounts = match rpc_client.get_vote_accounts() {
+        Ok(accounts) => accounts,
+        Err(e) => return error_response(Error::VoteAccountsFailed(e)),
+    };
+
+    let to_infos = |accounts: Vec<RpcVoteAccountInfo>| -> (Vec<ValidatorInfo>, usize) {
+        let (page, total) = select_validators(accounts, &req);
+        let infos = page
+            .into_iter()
+            .map(|a| ValidatorInfo {
+                vote_pubkey: a.vote_pubkey,
+                node_pubkey: a.node_pubkey,
+                activated_stake: a.activated_stake,
+                commission: a.commission,
+                last_vote: a.last_vote,
+            })
+            .collect();
+        (infos, total)
+    };
+
+    let (current, total_current) = to_infos(vote_accounts.current);
+    let (delinquent, total_delinquent) = if req.include_delinquent.unwrap_or(true) {
+        to_infos(vote_accounts.delinquent)
+    } else {
+        (Vec::new(), 0)
+    };
+
+    let response = ValidatorsResponse {
+        current,
+        delinquent,
+        total_current,
+        total_delinquent,
+    };
+    success_response(response)
+}
+
+#[handler]
+async fn account_info(req: Json<AccountInfoRequest>, state: Data<&AppState>) -> impl IntoResponse {
+    let net = match state.config.resolve_network(req.net) {
+        Ok(net) => net,
+        Err(e) => return error_response(e),
+    };
+
+    let address = match parse_pubkey(&req.address) {
+        Ok(addr) => addr,
+        Err(e) => return error_response(e),
+    };
+
+    let rpc_client = state.rpc_client(net);
+    let account = match rpc_client.get_account_with_commitment(&address, rpc_client.commitment()) {
+        Ok(response) => response.value,
+        Err(e) => return error_response(Error::AccountInfoFailed(e)),
+    };
+
+    // A missing account is an answer, not an error, so clients can decide to create it
+    let Some(account) = account else {
+        let response = AccountInfoResponse {
+            address: address.to_string(),
+            exists: false,
+            lamports: 0,
+            owner: None,
+            executable: false,
+            rent_epoch: 0,
+            data_len: 0,
+            rent_exempt: false,
+        };
+        return success_response(response);
+    };
+
+    let rent_minimum = match rpc_client.get_minimum_balance_for_rent_exemption(account.data.len()) {
+        Ok(lamports) => lamports,
+        Err(e) => return error_response(Error::RentExemptionFailed(e)),
+    };
+
+    let response = AccountInfoResponse {
+        address: address.to_string(),
+        exists: true,
+        lamports: account.lamports,
+        owner: Some(account.owner.to_string()),
+        executable: account.executable,
+        rent_epoch: account.rent_epoch,
+        data_len: account.data.len(),
+        rent_exempt: account.lamports >= rent_minimum,
+    };
+    success_response(response)
+}
+
+#[handler]
+async fn rent_exemption(
+    req: Json<RentExemptionRequest>,
+    state: Data<&AppState>,
+) -> impl IntoResponse {
+    let net = match state.config.resolve_network(req.net) {
+        Ok(net) => net,
+        Err(e) => return error_response(e),
+    };
+
+    let data_size = match (req.data_size, req.account_type) {
+        (Some(size), None) => size,
+        (None, Some(RentAccountType::Stake)) => std::mem::size_of::<StakeStateV2>(),
+        (None, Some(RentAccountType::Token)) => Account::LEN,
+        (None, Some(RentAccountType::Mint)) => Mint::LEN,
+        _ => {
+            return error_response(Error::InvalidRequest(
+                "set exactly one of data_size and account_type".to_string(),
+            ));
+        }
+    };
+
+    let rpc_client = state.rpc_client(net);
+    let lamports = match rpc_client.get_minimum_balance_for_rent_exemption(data_size) {
+        Ok(lamports) => lamports,
+        Err(e) => return error_response(Error::RentExemptionFailed(e)),
+    };
+
+    let response = RentExemptionResponse {
+        data_size,
+        lamports,
+        sol: native_token::lamports_to_sol(lamports),
+    };
+    success_response(response)
+}
+
+#[handler]
+async fn rent_reserve(req: Json<RentReserveRequest>, state: Data<&AppState>) -> impl IntoResponse {
+    let net = match state.config.resolve_network(req.net) {
+        Ok(net) => net,
+        Err(e) => return error_response(e),
+    };
+
+    let address = match parse_pubkey(&req.address) {
+        Ok(address) => address,
+        Err(e) => return error_response(e),
+    };
+
+    let rpc_client = state.rpc_client(net);
+    let lamports = match basic_rent_minimum(&rpc_client) {
+        Ok(lamports) => lamports,
+        Err(e) => return error_response(e),
+    };
+    let balance = match rpc_client.get_balance(&address) {
+        Ok(balance) => balance,
+        Err(e) => return error_response(Error::BalanceCheckFailed(e.to_string())),
+    };
+
+    let response = RentReserveResponse {
+        address: address.to_string(),
+        lamports,
+        sol: native_token::lamports_to_sol(lamports),
+        balance_lamports: balance,
+        spendable_lamports: balance.saturating_sub(lamports),
+    };
+    success_response(response)
+}
+
+#[handler]
+async fn epoch_info(req: Json<EpochInfoRequest>, state: Data<&AppState>) -> impl IntoResponse {
+    let net = match state.config.resolve_network(req.net) {
+        Ok(net) => net,
+        Err(e) => return error_response(e),
+    };
+
+    let rpc_client = state.rpc_client(net);
+    let epoch_info = match rpc_client.get_epoch_info() {
+        Ok(info) => info,
+        Err(e) => return error_response(Error::EpochInfoFailed(e)),
+    };
+    let inflation = match rpc_client.get_inflation_rate() {
+        Ok(rate) => rate,
+        Err(e) => return error_response(Error::InflationRateFailed(e)),
+    };
+
+    let response = EpochInfoResponse {
+        epoch: epoch_info.epoch,
+        slot_index: epoch_info.slot_index,
+        slots_in_epoch: epoch_info.slots_in_epoch,
+        absolute_slot: epoch_info.absolute_slot,
+        block_height: epoch_info.block_height,
+        epoch_progress: epoch_info.slot_index as f64 / epoch_info.slots_in_epoch as f64 * 100.0,
+        inflation: InflationRateInfo {
+            total: inflation.total,
+            validator: inflation.validator,
+            foundation: inflation.foundation,
+        },
+    };
+    success_response(response)
+}
+
+#[handler]
+async fn supply(req: Json<SupplyRequest>, state: Data<&AppState>) -> impl IntoResponse {
+    let net = match state.config.resolve_network(req.net) {
+        Ok(net) => net,
+        Err(e) => return error_response(e),
+    };
+
+    let rpc_client = state.rpc_client(net);
+    let supply = match rpc_client.supply() {
+        Ok(response) => response.value,
+        Err(e) => return error_response(Error::SupplyFailed(e)),
+    };
+
+    let response = SupplyResponse {
+        total: supply.total,
+        total_sol: native_token::lamports_to_sol(supply.total),
+        circulating: supply.circulating,
+        circulating_sol: native_token::lamports_to_sol(supply.circulating),
+        non_circulating: supply.non_circulating,
+        non_circulating_sol: native_token::lamports_to_sol(supply.non_circulating),
+        non_circulating_accounts: supply.non_circulating_accounts.len(),
+    };
+    success_response(response)
+}
+
+// Rewards are looked up one epoch per RPC call, so keep the history short
+const MAX_REWARD_EPOCHS: u64 = 10;
+
+#[handler]
+async fn stake_info(req: Json<StakeInfoRequest>, state: Data<&AppState>) -> impl IntoResponse {
+    let net = match state.config.resolve_network(req.net) {
+        Ok(net) => net,
+        Err(e) => return error_response(e),
+    };
+
+    let stake_accountt = match parse_pubkey(&req.stake_account) {
+        Ok(addr) => addr,
+        Err(e) => return error_response(e),
+    };
+
+    let rpc_client = state.rpc_client(net);
+    let (lamports, state) = match get_stake_account(&rpc_client, &stake_accountt) {
+        Ok(account) => account,
+        Err(e) => return error_response(e),
+    };
+
+    let epoch_info = match rpc_client.get_epoch_info() {
+        Ok(info) => info,
+        Err(e) => return error_response(Error::EpochInfoFailed(e)),
+    };
+
+    let mut rewards = Vec::new();
+    let reward_epochs = req.reward_epochs.unwrap_or(0).min(MAX_REWARD_EPOCHS);
+    for epoch in (epoch_info.epoch.saturating_sub(reward_epochs)..epoch_info.epoch).rev() {
+        let reward = match rpc_client.get_inflation_reward(&[stake_accountt], Some(epoch)) {
+            Ok(mut rewards) => rewards.pop().flatten(),
+            Err(e) => return error_response(Error::InflationRewardFailed(e)),
+        };
+        if let Some(reward) = reward {
+            rewards.push(StakeRewardInfo {
+                epoch: reward.epoch,
+                amount: reward.amount,
+                post_balance: reward.post_balance,
+                effective_slot: reward.effective_slot,
+                commission: reward.commission,
+            });
+        }
+    }
+
+    let (state_name, meta, delegation) = match &state {
+        StakeStateV2::Uninitialized => ("uninitialized", None, None),
+        StakeStateV2::Initialized(meta) => ("initialized", Some(meta), None),
+        StakeStateV2::Stake(meta, stake, _) => ("delegated", Some(meta), Some(stake.delegation)),
+        StakeStateV2::RewardsPool => ("rewards_pool", None, None),
+    };
+
+    let response = StakeInfoResponse {
+        stake_account: stake_accountt.to_string(),
+        lamports,
+        state: state_name.to_string(),
+        staker: meta.map(|m| m.authorized.staker.to_string()),
+        withdrawer: meta.map(|m| m.authorized.withdrawer.to_string()),
+        rent_exempt_reserve: meta.map(|m| m.rent_exempt_reserve),
+        delegation: delegation.map(|d| StakeDelegationInfo {
+            voter: d.voter_pubkey.to_string(),
+            stake: d.stake,
+            activation_epoch: d.activation_epoch,
+            deactivation_epoch: (d.deactivation_epoch != u64::MAX).then_some(d.deactivation_epoch),
+        }),
+        current_epoch: epoch_info.epoch,
+        rewards,
     };
     success_response(response)
 }
 
 //staking end her
 
+/// Resolves on Ctrl+C or SIGTERM, after which the server stops accepting connections
+/// and gives in-flight requests the grace period to finish
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::warn!("Failed listening for Ctrl+C: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                sigterm.recv().await;
+            }
+            Err(e) => {
+                tracing::warn!("Failed listening for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+    tracing::info!("Shutting down, waiting for in-flight requests to finish");
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
+        )
+        // Closing spans report their duration, which is what makes slow RPC calls visible
+        .with_span_events(FmtSpan::CLOSE)
+        .init();
+
+    let config = Config::from_env()?;
+    let bind_address = config.bind_address();
+    let shutdown_grace_period = config.shutdown_grace_period;
+
+    // No configured origins keeps CORS fully permissive for local development
+    let cors = Cors::new().allow_origins(config.cors_allowed_origins.iter());
+
+    let api_service = openapi::api_service();
     let app = Route::new()
+        .at("/health", get(health))
+        .at("/api/version", get(version))
+        .at("/ready", get(ready))
+        .at("/metrics", get(prometheus_metrics))
+        .at("/ws/confirm", get(ws_confirm))
+        .at("/docs", api_service.swagger_ui())
+        .at("/docs/openapi.json", api_service.spec_endpoint())
         .at("/api/generate", get(generate_keypair))
+        .at("/api/generate_vanity", post(generate_vanity))
         .at("/api/balance", post(balance))
+        .at("/api/balances", post(balances))
+        .at("/api/confirm_many", post(confirm_many))
+        .at("/api/account_info", post(account_info))
         .at("/api/airdrop", post(airdrop))
         .at("/api/send_single", post(send_single))
+        .at("/api/send_raw_instructions", post(send_raw_instructions))
+        .at("/api/estimate_compute_units", post(estimate_compute_units))
+        .at("/api/validate_address", post(validate_address))
+        .at("/api/keypair_pubkey", post(keypair_pubkey))
+        .at("/api/keypair_from_mnemonic", post(keypair_from_mnemonic))
+        .at("/api/decode_transaction", post(decode_transaction))
         .at("/api/recent_block_hash", post(recent_block_hash))
         .at("/api/aggregate_keys", post(aggregate_keys))
         .at("/api/agg_send_step_one", post(agg_send_step_one))
         .at("/api/agg_send_step_two", post(agg_send_step_two))
+        .at("/api/signing_package/create", post(signing_package_create))
+        .at(
+            "/api/signing_package/step_two",
+            post(signing_package_step_two),
+        )
         .at("/api/aggregate_signatures", post(aggregate_signatures))
+        .at(
+            "/api/verify_partial_signatures",
+            post(verify_partial_signatures),
+        )
+        .at("/api/verify_signature", post(verify_signature))
+        .at("/api/agg_session/create", post(agg_session_create))
+        .at("/api/agg_session/:id/submit", post(agg_session_submit))
+        .at("/api/agg_session/:id/status", get(agg_session_status))
+        .at("/api/create_nonce_account", post(create_nonce_account))
+        .at("/api/nonce_info", post(nonce_info))
         .at("/api/spl_token_balance", post(spl_token_balance))
+        .at("/api/spl_all_balances", post(spl_all_balances))
+        .at("/api/token_metadata", post(token_metadata))
+        .at("/api/derive_ata", post(derive_ata))
+        .at("/api/solana_pay_url", post(solana_pay_url))
         .at("/api/spl_send_single", post(spl_send_single))
+        .at("/api/spl_create_account", post(spl_create_account))
+        .at("/api/spl_approve", post(spl_approve))
+        .at("/api/spl_revoke", post(spl_revoke))
+        .at("/api/spl_close_account", post(spl_close_account))
+        .at("/api/spl_set_authority", post(spl_set_authority))
         .at("/api/spl_agg_send_step_two", post(spl_agg_send_step_two))
         .at(
             "/api/spl_aggregate_signatures",
             post(spl_aggregate_signatures),
         )
         .at("/api/stake", post(stake_account))
+        .at("/api/derive_stake_account", post(derive_stake_account))
         .at("/api/deactivate_stake", post(deactivate_stake))
         .at("/api/withdraw_stake", post(withdraw_stake))
+        .at("/api/agg_stake_step_one", post(agg_stake_step_one))
         .at("/api/agg_stake_step_two", post(agg_stake_step_two))
+        .at(
+            "/api/agg_deactivate_stake_step_one",
+            post(agg_deactivate_stake_step_one),
+        )
         .at(
             "/api/agg_deactivate_stake_step_two",
             post(agg_deactivate_stake_step_two),
         )
+        .at(
+            "/api/agg_withdraw_stake_step_one",
+            post(agg_withdraw_stake_step_one),
+        )
         .at(
             "/api/agg_withdraw_stake_step_two",
             post(agg_withdraw_stake_step_two),
@@ -1261,11 +4964,247 @@ async fn main() -> anyhow::Result<()> {
         .at(
             "/api/aggregate_withdraw_stake_signatures",
             post(aggregate_withdraw_stake_signatures),
-        );
+        )
+        .at("/api/validators", post(validators))
+        .at("/api/epoch_info", post(epoch_info))
+        .at("/api/supply", post(supply))
+        .at("/api/rent_exemption", post(rent_exemption))
+        .at("/api/rent_reserve", post(rent_reserve))
+        .at("/api/stake_info", post(stake_info));
+    // Holds every key in one process, never compiled into production builds
+    #[cfg(feature = "testing-helpers")]
+    let app = app.at("/api/agg_send_all", post(agg_send_all));
+    let api_key = config.api_key.clone();
+    let rate_limit = config.rate_limit_per_sec;
+    let metrics = Arc::new(Metrics::default());
+    let state = AppState::new(config.clone(), metrics.clone());
+    tokio::spawn(auto_withdraw::run(
+        state.auto_withdraw.clone(),
+        state.rpc_clients(),
+    ));
+    let app = app
+        // Malformed bodies get the same error shape as every other failure
+        .catch_error(|e: ParseJsonError| async move {
+            error_response(Error::InvalidJson(e.to_string()))
+        })
+        .with(BodyLimit::new(config.max_body_bytes))
+        .with_if(
+            api_key.is_some(),
+            ApiKeyAuth::new(api_key.unwrap_or_default()),
+        )
+        .with_if(
+            rate_limit.is_some(),
+            RateLimit::new(rate_limit.unwrap_or(1.0), config.rate_limit_burst),
+        )
+        .with(RequestMetrics::new(metrics.clone()))
+        .with(cors)
+        .with(Tracing)
+        .with(AssignRequestId)
+        .with(ResponseEnvelope)
+        .data(state);
+
+    tracing::info!("Listening on http://{}", bind_address);
 
-    Server::new(TcpListener::bind("127.0.0.1:8000"))
-        .run(app)
+    Server::new(TcpListener::bind(bind_address))
+        .run_with_graceful_shutdown(app, shutdown_signal(), Some(shutdown_grace_period))
         .await?;
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use solana_sdk::{
+        hash::Hash,
+        message::Message,
+        pubkey::Pubkey,
+        signature::{Keypair, Signer},
+        system_instruction,
+        transaction::Transaction,
+    };
+
+    use crate::{
+        MAX_SOL_AMOUNT, Memos, check_amount_percent, check_mainnet_confirmed, encode_keypair,
+        error::Error,
+        models::{KeyFormat, MemoPosition, Network, SendOptions},
+        parse_agg_keys, percent_of, resolve_lamports, send_with_blockhash_retry, sol_to_lamports,
+        transfer_instructions,
+    };
+
+    #[test]
+    fn test_sol_amount_bounds() {
+        assert_eq!(sol_to_lamports(1.5).unwrap(), 1_500_000_000);
+        assert_eq!(
+            sol_to_lamports(MAX_SOL_AMOUNT).unwrap(),
+            10_000_000_000_000_000_000
+        );
+        for amount in [
+            f64::MAX,
+            1e30,
+            MAX_SOL_AMOUNT * 2.0,
+            f64::INFINITY,
+            f64::NAN,
+            -1.0,
+        ] {
+            assert!(matches!(
+                sol_to_lamports(amount),
+                Err(Error::InvalidAmount(_))
+            ));
+        }
+        assert!(matches!(
+            resolve_lamports(Some(1e30), None),
+            Err(Error::InvalidAmount(_))
+        ));
+    }
+
+    #[test]
+    fn test_memo_order() {
+        let from = Pubkey::new_unique();
+        let to = Pubkey::new_unique();
+        let memos = Memos::new(
+            Some("a".to_string()),
+            Some(vec!["b".to_string(), "c".to_string()]),
+            MemoPosition::Before,
+        );
+        assert_eq!(memos.total_bytes(), 3);
+
+        let instructions = transfer_instructions(1, &to, &memos, &from, None);
+        let data: Vec<&[u8]> = instructions.iter().map(|ix| ix.data.as_slice()).collect();
+        assert_eq!(&data[..3], &[b"a", b"b", b"c"]);
+        assert_eq!(instructions[3].program_id, solana_sdk::system_program::id());
+
+        // The single memo keeps its old place after the transfer
+        let instructions =
+            transfer_instructions(1, &to, &Memos::from(Some("a".to_string())), &from, None);
+        assert_eq!(instructions.len(), 2);
+        assert_eq!(instructions[1].data, b"a");
+    }
+
+    #[test]
+    fn test_keypair_formats() {
+        let keypair = Keypair::new();
+        let bytes = keypair.to_bytes();
+
+        let json: Vec<u8> =
+            serde_json::from_str(&encode_keypair(&keypair, KeyFormat::JsonArray)).unwrap();
+        assert_eq!(json, bytes);
+        assert_eq!(
+            hex::decode(encode_keypair(&keypair, KeyFormat::Hex)).unwrap(),
+            bytes
+        );
+        assert_eq!(
+            encode_keypair(&keypair, KeyFormat::Base58),
+            keypair.to_base58_string()
+        );
+    }
+
+    #[test]
+    fn test_amount_percent() {
+        assert!(check_amount_percent(100.0, None, None).is_ok());
+        assert!(check_amount_percent(0.5, None, None).is_ok());
+        for percent in [0.0, -1.0, 100.1, f64::NAN, f64::INFINITY] {
+            assert!(check_amount_percent(percent, None, None).is_err());
+        }
+        assert!(check_amount_percent(50.0, Some(1.0), None).is_err());
+        assert!(check_amount_percent(50.0, None, Some(1)).is_err());
+
+        assert_eq!(percent_of(1_000, 100.0), 1_000);
+        assert_eq!(percent_of(1_001, 50.0), 500);
+        assert_eq!(percent_of(u64::MAX, 100.0), u64::MAX);
+        assert_eq!(percent_of(0, 100.0), 0);
+    }
+
+    #[test]
+    fn test_mainnet_confirmation() {
+        let unconfirmed = SendOptions::default();
+        assert!(matches!(
+            check_mainnet_confirmed(Network::Mainnet, &unconfirmed),
+            Err(Error::MainnetNotConfirmed)
+        ));
+        assert!(check_mainnet_confirmed(Network::Devnet, &unconfirmed).is_ok());
+
+        let confirmed = SendOptions {
+            confirm_mainnet: true,
+            ..SendOptions::default()
+        };
+        assert!(check_mainnet_confirmed(Network::Mainnet, &confirmed).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_stale_blockhash_signed_again() {
+        let keypair = Keypair::new();
+        let message = Message::new(
+            &[system_instruction::transfer(
+                &keypair.pubkey(),
+                &Pubkey::new_unique(),
+                1,
+            )],
+            Some(&keypair.pubkey()),
+        );
+        let stale = Hash::new_unique();
+        let fresh = Hash::new_unique();
+
+        let mut tx = Transaction::new_unsigned(message.clone());
+        let mut sent = Vec::new();
+        let (sig, used) = send_with_blockhash_retry(
+            &mut tx,
+            &[&keypair],
+            stale,
+            || async { Ok(fresh) },
+            |tx| {
+                sent.push(tx.message.recent_blockhash);
+                if tx.message.recent_blockhash == stale {
+                    return Err(Error::BlockhashExpired(stale.to_string()));
+                }
+                Ok(tx.signatures[0])
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(sent, vec![stale, fresh]);
+        assert_eq!(used, fresh);
+        assert_eq!(sig, tx.signatures[0]);
+        assert!(tx.verify().is_ok());
+
+        // Only once, and other errors aren't retried at all
+        let mut tx = Transaction::new_unsigned(message.clone());
+        let mut attempts = 0;
+        let result = send_with_blockhash_retry(
+            &mut tx,
+            &[&keypair],
+            stale,
+            || async { Ok(fresh) },
+            |_| {
+                attempts += 1;
+                Err(Error::BlockhashExpired(String::new()))
+            },
+        )
+        .await;
+        assert!(matches!(result, Err(Error::BlockhashExpired(_))));
+        assert_eq!(attempts, 2);
+
+        let mut tx = Transaction::new_unsigned(message);
+        let result = send_with_blockhash_retry(
+            &mut tx,
+            &[&keypair],
+            stale,
+            || async { Err(Error::Internal("not a blockhash error".to_string())) },
+            |_| Err(Error::InvalidRequest("rejected".to_string())),
+        )
+        .await;
+        assert!(matches!(result, Err(Error::InvalidRequest(_))));
+    }
+
+    #[test]
+    fn test_agg_keys_empty() {
+        assert!(matches!(
+            parse_agg_keys(&[], None),
+            Err(Error::InvalidRequest(_))
+        ));
+
+        let key = Pubkey::new_unique();
+        let (keys, signer) = parse_agg_keys(&[key.to_string()], None).unwrap();
+        assert_eq!(keys, vec![key]);
+        assert_eq!(signer, key);
+    }
+}
@@ -0,0 +1,322 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt::Write,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use solana_client::{
+    client_error::Result as ClientResult,
+    http_sender::HttpSender,
+    rpc_request::RpcRequest,
+    rpc_sender::{RpcSender, RpcTransportStats},
+};
+
+use crate::models::Network;
+
+/// Upper bounds in seconds, from a cached read up to a slow send
+const RPC_LATENCY_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Unknown paths all end up in one label, so scanning the API can't grow the output
+const MAX_PATH_LABELS: usize = 200;
+const OTHER_PATH: &str = "other";
+
+/// Set on error responses, lets the metrics middleware count errors by code
+#[derive(Debug, Clone, Copy)]
+pub struct ErrorCode(pub &'static str);
+
+#[derive(Debug, Default, Clone)]
+struct Histogram {
+    /// Observations at or below each of `RPC_LATENCY_BUCKETS`, not cumulative
+    buckets: [u64; RPC_LATENCY_BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, seconds: f64) {
+        if let Some(i) = RPC_LATENCY_BUCKETS.iter().position(|le| seconds <= *le) {
+            self.buckets[i] += 1;
+        }
+        self.sum += seconds;
+        self.count += 1;
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct RequestLabels {
+    path: String,
+    method: String,
+    status: u16,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct RpcLabels {
+    net: &'static str,
+    method: String,
+}
+
+/// Counters exported in the Prometheus text format on /metrics. Kept in ordered maps
+/// so the output is stable between scrapes
+#[derive(Debug, Default)]
+pub struct Metrics {
+    requests: Mutex<BTreeMap<RequestLabels, u64>>,
+    errors: Mutex<BTreeMap<&'static str, u64>>,
+    rpc_latency: Mutex<BTreeMap<RpcLabels, Histogram>>,
+    rpc_errors: Mutex<BTreeMap<RpcLabels, u64>>,
+}
+
+impl Metrics {
+    pub fn record_request(&self, path: &str, method: &str, status: u16) {
+        let mut requests = self.requests.lock().unwrap();
+        let path = normalize_path(path);
+        let known = requests.keys().any(|labels| labels.path == path);
+        let distinct_paths = || {
+            requests
+                .keys()
+                .map(|labels| labels.path.as_str())
+                .collect::<HashSet<_>>()
+                .len()
+        };
+        let path = if known || distinct_paths() < MAX_PATH_LABELS {
+            path
+        } else {
+            OTHER_PATH.to_string()
+        };
+        *requests
+            .entry(RequestLabels {
+                path,
+                method: method.to_string(),
+                status,
+            })
+            .or_default() += 1;
+    }
+
+    pub fn record_error(&self, code: &'static str) {
+        *self.errors.lock().unwrap().entry(code).or_default() += 1;
+    }
+
+    pub fn record_rpc(&self, net: Network, method: String, elapsed: Duration, ok: bool) {
+        let labels = RpcLabels {
+            net: net.as_str(),
+            method,
+        };
+        if !ok {
+            *self
+                .rpc_errors
+                .lock()
+                .unwrap()
+                .entry(labels.clone())
+                .or_default() += 1;
+        }
+        self.rpc_latency
+            .lock()
+            .unwrap()
+            .entry(labels)
+            .or_default()
+            .observe(elapsed.as_secs_f64());
+    }
+
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+
+        out.push_str("# HELP http_requests_total Requests by path, method and status.\n");
+        out.push_str("# TYPE http_requests_total counter\n");
+        for (labels, count) in self.requests.lock().unwrap().iter() {
+            let _ = writeln!(
+                out,
+                "http_requests_total{{path=\"{}\",method=\"{}\",status=\"{}\"}} {}",
+                escape(&labels.path),
+                escape(&labels.method),
+                labels.status,
+                count
+            );
+        }
+
+        out.push_str("# HELP api_errors_total Error responses by error code.\n");
+        out.push_str("# TYPE api_errors_total counter\n");
+        for (code, count) in self.errors.lock().unwrap().iter() {
+            let _ = writeln!(out, "api_errors_total{{code=\"{}\"}} {}", code, count);
+        }
+
+        out.push_str("# HELP rpc_request_duration_seconds Solana RPC call latency.\n");
+        out.push_str("# TYPE rpc_request_duration_seconds histogram\n");
+        for (labels, histogram) in self.rpc_latency.lock().unwrap().iter() {
+            let labels = format!(
+                "net=\"{}\",method=\"{}\"",
+                labels.net,
+                escape(&labels.method)
+            );
+            let mut cumulative = 0;
+            for (le, count) in RPC_LATENCY_BUCKETS.iter().zip(histogram.buckets) {
+                cumulative += count;
+                let _ = writeln!(
+                    out,
+                    "rpc_request_duration_seconds_bucket{{{},le=\"{}\"}} {}",
+                    labels, le, cumulative
+                );
+            }
+            let _ = writeln!(
+                out,
+                "rpc_request_duration_seconds_bucket{{{},le=\"+Inf\"}} {}",
+                labels, histogram.count
+            );
+            let _ = writeln!(
+                out,
+                "rpc_request_duration_seconds_sum{{{}}} {}",
+                labels, histogram.sum
+            );
+            let _ = writeln!(
+                out,
+                "rpc_request_duration_seconds_count{{{}}} {}",
+                labels, histogram.count
+            );
+        }
+
+        out.push_str("# HELP rpc_errors_total Failed Solana RPC calls.\n");
+        out.push_str("# TYPE rpc_errors_total counter\n");
+        for (labels, count) in self.rpc_errors.lock().unwrap().iter() {
+            let _ = writeln!(
+                out,
+                "rpc_errors_total{{net=\"{}\",method=\"{}\"}} {}",
+                labels.net,
+                escape(&labels.method),
+                count
+            );
+        }
+        out
+    }
+}
+
+/// Session ids would give every session its own label
+fn normalize_path(path: &str) -> String {
+    match path.strip_prefix("/api/agg_session/") {
+        Some(rest) => match rest.split_once('/') {
+            Some((_, action)) => format!("/api/agg_session/:id/{}", action),
+            None => path.to_string(),
+        },
+        None => path.to_string(),
+    }
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// `HttpSender` that times every call, so all RPC traffic is covered
+/// without touching the call sites
+pub struct MeteredSender {
+    inner: HttpSender,
+    net: Network,
+    metrics: Arc<Metrics>,
+}
+
+impl MeteredSender {
+    /// `net` only labels the calls, they go to `url`
+    pub fn new(net: Network, url: String, timeout: Duration, metrics: Arc<Metrics>) -> Self {
+        Self {
+            inner: HttpSender::new_with_timeout(url, timeout),
+            net,
+            metrics,
+        }
+    }
+}
+
+#[async_trait]
+impl RpcSender for MeteredSender {
+    async fn send(
+        &self,
+        request: RpcRequest,
+        params: serde_json::Value,
+    ) -> ClientResult<serde_json::Value> {
+        let start = Instant::now();
+        let result = self.inner.send(request, params).await;
+        self.metrics.record_rpc(
+            self.net,
+            request.to_string(),
+            start.elapsed(),
+            result.is_ok(),
+        );
+        result
+    }
+
+    fn get_transport_stats(&self) -> RpcTransportStats {
+        self.inner.get_transport_stats()
+    }
+
+    fn url(&self) -> String {
+        self.inner.url()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::{
+        metrics::{MAX_PATH_LABELS, Metrics},
+        models::Network,
+    };
+
+    #[test]
+    fn test_render_counts_and_cumulative_buckets() {
+        let metrics = Metrics::default();
+        metrics.record_request("/api/balance", "POST", 200);
+        metrics.record_request("/api/balance", "POST", 200);
+        metrics.record_request("/api/agg_session/abc123/status", "GET", 404);
+        metrics.record_error("AGG_SESSION_NOT_FOUND");
+        metrics.record_rpc(
+            Network::Devnet,
+            "getBalance".to_string(),
+            Duration::from_millis(30),
+            true,
+        );
+        metrics.record_rpc(
+            Network::Devnet,
+            "getBalance".to_string(),
+            Duration::from_secs(20),
+            false,
+        );
+
+        let out = metrics.render();
+        assert!(out.contains(
+            "http_requests_total{path=\"/api/balance\",method=\"POST\",status=\"200\"} 2"
+        ));
+        assert!(out.contains("path=\"/api/agg_session/:id/status\""));
+        assert!(out.contains("api_errors_total{code=\"AGG_SESSION_NOT_FOUND\"} 1"));
+        assert!(out.contains(
+            "rpc_request_duration_seconds_bucket{net=\"devnet\",method=\"getBalance\",le=\"0.025\"} 0"
+        ));
+        assert!(out.contains(
+            "rpc_request_duration_seconds_bucket{net=\"devnet\",method=\"getBalance\",le=\"0.05\"} 1"
+        ));
+        // Slower than the last bucket only shows up in +Inf
+        assert!(out.contains(
+            "rpc_request_duration_seconds_bucket{net=\"devnet\",method=\"getBalance\",le=\"10\"} 1"
+        ));
+        assert!(out.contains(
+            "rpc_request_duration_seconds_bucket{net=\"devnet\",method=\"getBalance\",le=\"+Inf\"} 2"
+        ));
+        assert!(out.contains("rpc_errors_total{net=\"devnet\",method=\"getBalance\"} 1"));
+    }
+
+    #[test]
+    fn test_path_labels_are_capped() {
+        let metrics = Metrics::default();
+        for i in 0..MAX_PATH_LABELS + 10 {
+            metrics.record_request(&format!("/api/unknown_{}", i), "POST", 404);
+        }
+        let out = metrics.render();
+        assert_eq!(
+            out.matches("http_requests_total{").count(),
+            MAX_PATH_LABELS + 1
+        );
+        assert!(out.contains("path=\"other\",method=\"POST\",status=\"404\"} 10"));
+    }
+}
@@ -0,0 +1,443 @@
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use poem::{
+    Endpoint, IntoResponse, Middleware, Request, Response, Result,
+    error::ReadBodyError,
+    http::{
+        HeaderValue,
+        header::{ACCEPT, CONTENT_LENGTH, RETRY_AFTER},
+    },
+};
+use tracing::Instrument;
+use uuid::Uuid;
+
+use crate::{
+    error::Error,
+    error_response,
+    metrics::{ErrorCode, Metrics},
+};
+
+pub const API_KEY_HEADER: &str = "X-API-Key";
+pub const REQUEST_ID_HEADER: &str = "X-Request-Id";
+
+/// Accepting this media type opts in to the `{success, data}` / `{success, error}` envelope
+pub const ENVELOPE_MEDIA_TYPE: &str = "application/vnd.tss-api.envelope+json";
+
+/// Incoming ids end up in logs and responses, longer or non printable ones are replaced
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// Probes, metrics and docs stay reachable without a key
+const PUBLIC_PATHS: &[&str] = &["/health", "/ready", "/metrics", "/docs"];
+
+fn is_public(path: &str) -> bool {
+    PUBLIC_PATHS
+        .iter()
+        .any(|public| path == *public || path.starts_with(&format!("{}/", public)))
+}
+
+/// Rejects requests without the configured `X-API-Key` with 401
+pub struct ApiKeyAuth {
+    key: String,
+}
+
+impl ApiKeyAuth {
+    pub fn new(key: String) -> Self {
+        Self { key }
+    }
+}
+
+impl<E: Endpoint> Middleware<E> for ApiKeyAuth {
+    type Output = ApiKeyAuthEndpoint<E>;
+
+    fn transform(&self, ep: E) -> Self::Output {
+        ApiKeyAuthEndpoint {
+            inner: ep,
+            key: self.key.clone(),
+        }
+    }
+}
+
+pub struct ApiKeyAuthEndpoint<E> {
+    inner: E,
+    key: String,
+}
+
+impl<E: Endpoint> Endpoint for ApiKeyAuthEndpoint<E> {
+    type Output = Response;
+
+    async fn call(&self, req: Request) -> Result<Self::Output> {
+        let authorized = req
+            .header(API_KEY_HEADER)
+            .is_some_and(|key| constant_time_eq(key.as_bytes(), self.key.as_bytes()));
+        if !authorized && !is_public(req.uri().path()) {
+            return Ok(error_response(Error::Unauthorized));
+        }
+        self.inner.call(req).await.map(IntoResponse::into_response)
+    }
+}
+
+/// Doesn't leak how much of the key matched through timing
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Above this many tracked clients, buckets that have refilled completely are dropped
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn full(burst: f64, now: Instant) -> Self {
+        Self {
+            tokens: burst,
+            updated: now,
+        }
+    }
+
+    fn refill(&mut self, rate: f64, burst: f64, now: Instant) {
+        let elapsed = now.duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate).min(burst);
+        self.updated = now;
+    }
+
+    /// Takes a token, or returns how long until one is available
+    fn take(&mut self, rate: f64, burst: f64, now: Instant) -> Result<(), Duration> {
+        self.refill(rate, burst, now);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - self.tokens) / rate))
+        }
+    }
+}
+
+/// Token bucket per client IP, answers 429 with Retry-After once a client runs dry.
+/// Requests are counted against the socket address, so behind a reverse proxy
+/// every client shares the proxy's bucket
+pub struct RateLimit {
+    rate: f64,
+    burst: f64,
+    buckets: Arc<Mutex<HashMap<IpAddr, Bucket>>>,
+}
+
+impl RateLimit {
+    pub fn new(requests_per_sec: f64, burst: u32) -> Self {
+        Self {
+            rate: requests_per_sec,
+            burst: f64::from(burst.max(1)),
+            buckets: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+}
+
+impl<E: Endpoint> Middleware<E> for RateLimit {
+    type Output = RateLimitEndpoint<E>;
+
+    fn transform(&self, ep: E) -> Self::Output {
+        RateLimitEndpoint {
+            inner: ep,
+            rate: self.rate,
+            burst: self.burst,
+            buckets: self.buckets.clone(),
+        }
+    }
+}
+
+pub struct RateLimitEndpoint<E> {
+    inner: E,
+    rate: f64,
+    burst: f64,
+    buckets: Arc<Mutex<HashMap<IpAddr, Bucket>>>,
+}
+
+impl<E> RateLimitEndpoint<E> {
+    fn take(&self, ip: IpAddr) -> Result<(), Duration> {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() >= MAX_TRACKED_CLIENTS {
+            buckets.retain(|_, bucket| {
+                bucket.refill(self.rate, self.burst, now);
+                bucket.tokens < self.burst
+            });
+        }
+        buckets
+            .entry(ip)
+            .or_insert_with(|| Bucket::full(self.burst, now))
+            .take(self.rate, self.burst, now)
+    }
+}
+
+impl<E: Endpoint> Endpoint for RateLimitEndpoint<E> {
+    type Output = Response;
+
+    async fn call(&self, req: Request) -> Result<Self::Output> {
+        if let Some(ip) = req.remote_addr().as_socket_addr().map(|addr| addr.ip()) {
+            if let Err(wait) = self.take(ip) {
+                let retry_after = wait.as_secs_f64().ceil() as u64;
+                let mut resp = error_response(Error::RateLimited(retry_after));
+                resp.headers_mut()
+                    .insert(RETRY_AFTER, HeaderValue::from(retry_after));
+                return Ok(resp);
+            }
+        }
+        self.inner.call(req).await.map(IntoResponse::into_response)
+    }
+}
+
+/// Answers 413 for bodies over `max_bytes`. The declared Content-Length is checked
+/// first, chunked bodies are buffered up to the limit
+pub struct BodyLimit {
+    max_bytes: usize,
+}
+
+impl BodyLimit {
+    pub fn new(max_bytes: usize) -> Self {
+        Self { max_bytes }
+    }
+}
+
+impl<E: Endpoint> Middleware<E> for BodyLimit {
+    type Output = BodyLimitEndpoint<E>;
+
+    fn transform(&self, ep: E) -> Self::Output {
+        BodyLimitEndpoint {
+            inner: ep,
+            max_bytes: self.max_bytes,
+        }
+    }
+}
+
+pub struct BodyLimitEndpoint<E> {
+    inner: E,
+    max_bytes: usize,
+}
+
+impl<E: Endpoint> Endpoint for BodyLimitEndpoint<E> {
+    type Output = Response;
+
+    async fn call(&self, mut req: Request) -> Result<Self::Output> {
+        let declared = req
+            .header(CONTENT_LENGTH)
+            .and_then(|len| len.parse::<usize>().ok());
+        if declared.is_some_and(|len| len > self.max_bytes) {
+            return Ok(error_response(Error::PayloadTooLarge(self.max_bytes)));
+        }
+        if declared.is_none() {
+            match req.take_body().into_bytes_limit(self.max_bytes).await {
+                Ok(body) => req.set_body(body),
+                Err(ReadBodyError::PayloadTooLarge) => {
+                    return Ok(error_response(Error::PayloadTooLarge(self.max_bytes)));
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+        self.inner.call(req).await.map(IntoResponse::into_response)
+    }
+}
+
+/// Counts every response by path and status, and error responses by their code
+pub struct RequestMetrics {
+    metrics: Arc<Metrics>,
+}
+
+impl RequestMetrics {
+    pub fn new(metrics: Arc<Metrics>) -> Self {
+        Self { metrics }
+    }
+}
+
+impl<E: Endpoint> Middleware<E> for RequestMetrics {
+    type Output = RequestMetricsEndpoint<E>;
+
+    fn transform(&self, ep: E) -> Self::Output {
+        RequestMetricsEndpoint {
+            inner: ep,
+            metrics: self.metrics.clone(),
+        }
+    }
+}
+
+pub struct RequestMetricsEndpoint<E> {
+    inner: E,
+    metrics: Arc<Metrics>,
+}
+
+impl<E: Endpoint> Endpoint for RequestMetricsEndpoint<E> {
+    type Output = Response;
+
+    async fn call(&self, req: Request) -> Result<Self::Output> {
+        let path = req.uri().path().to_string();
+        let method = req.method().to_string();
+        // Errors such as an unknown route are turned into their response here to get a status
+        let resp = match self.inner.call(req).await {
+            Ok(resp) => resp.into_response(),
+            Err(e) => e.into_response(),
+        };
+        self.metrics
+            .record_request(&path, &method, resp.status().as_u16());
+        if let Some(ErrorCode(code)) = resp.extensions().get::<ErrorCode>() {
+            self.metrics.record_error(code);
+        }
+        Ok(resp)
+    }
+}
+
+tokio::task_local! {
+    static CURRENT_REQUEST_ID: String;
+}
+
+/// Correlation id of a request, in its extensions
+#[derive(Debug, Clone)]
+pub struct RequestId(pub String);
+
+/// Id of the request being handled, None outside of `AssignRequestId`
+pub fn current_request_id() -> Option<String> {
+    CURRENT_REQUEST_ID.try_with(Clone::clone).ok()
+}
+
+fn is_valid_request_id(id: &str) -> bool {
+    !id.is_empty() && id.len() <= MAX_REQUEST_ID_LEN && id.bytes().all(|b| b.is_ascii_graphic())
+}
+
+/// Gives every request a correlation id, the caller's `X-Request-Id` or a new UUID. It is
+/// echoed in the response header, added to error bodies and to every log line of the request
+pub struct AssignRequestId;
+
+impl<E: Endpoint> Middleware<E> for AssignRequestId {
+    type Output = AssignRequestIdEndpoint<E>;
+
+    fn transform(&self, ep: E) -> Self::Output {
+        AssignRequestIdEndpoint { inner: ep }
+    }
+}
+
+pub struct AssignRequestIdEndpoint<E> {
+    inner: E,
+}
+
+impl<E: Endpoint> Endpoint for AssignRequestIdEndpoint<E> {
+    type Output = Response;
+
+    async fn call(&self, mut req: Request) -> Result<Self::Output> {
+        let id = req
+            .header(REQUEST_ID_HEADER)
+            .filter(|id| is_valid_request_id(id))
+            .map(str::to_string)
+            .unwrap_or_else(|| Uuid::new_v4().to_string());
+        req.extensions_mut().insert(RequestId(id.clone()));
+
+        let span = tracing::info_span!("request", request_id = %id);
+        let result = CURRENT_REQUEST_ID
+            .scope(id.clone(), self.inner.call(req).instrument(span))
+            .await;
+        let mut resp = match result {
+            Ok(resp) => resp.into_response(),
+            Err(e) => e.into_response(),
+        };
+        if let Ok(value) = HeaderValue::from_str(&id) {
+            resp.headers_mut().insert(REQUEST_ID_HEADER, value);
+        }
+        Ok(resp)
+    }
+}
+
+tokio::task_local! {
+    static ENVELOPE: bool;
+}
+
+/// Whether the request being handled asked for the response envelope
+pub fn wants_envelope() -> bool {
+    ENVELOPE.try_with(|envelope| *envelope).unwrap_or(false)
+}
+
+fn accepts_envelope(accept: &str) -> bool {
+    accept
+        .split(',')
+        .filter_map(|media_type| media_type.split(';').next())
+        .any(|media_type| media_type.trim().eq_ignore_ascii_case(ENVELOPE_MEDIA_TYPE))
+}
+
+/// Lets `success_response` and `error_response` wrap their body for clients that send
+/// `Accept: application/vnd.tss-api.envelope+json`, everyone else keeps the bare bodies
+pub struct ResponseEnvelope;
+
+impl<E: Endpoint> Middleware<E> for ResponseEnvelope {
+    type Output = ResponseEnvelopeEndpoint<E>;
+
+    fn transform(&self, ep: E) -> Self::Output {
+        ResponseEnvelopeEndpoint { inner: ep }
+    }
+}
+
+pub struct ResponseEnvelopeEndpoint<E> {
+    inner: E,
+}
+
+impl<E: Endpoint> Endpoint for ResponseEnvelopeEndpoint<E> {
+    type Output = E::Output;
+
+    async fn call(&self, req: Request) -> Result<Self::Output> {
+        let envelope = req.header(ACCEPT).is_some_and(accepts_envelope);
+        ENVELOPE.scope(envelope, self.inner.call(req)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::middleware::{
+        Bucket, ENVELOPE, MAX_REQUEST_ID_LEN, accepts_envelope, is_valid_request_id, wants_envelope,
+    };
+
+    #[test]
+    fn test_bucket_allows_burst_then_refills() {
+        let (rate, burst) = (2.0, 3.0);
+        let start = Instant::now();
+        let mut bucket = Bucket::full(burst, start);
+
+        for _ in 0..3 {
+            assert!(bucket.take(rate, burst, start).is_ok());
+        }
+        assert_eq!(
+            bucket.take(rate, burst, start),
+            Err(Duration::from_millis(500))
+        );
+
+        let later = start + Duration::from_millis(500);
+        assert!(bucket.take(rate, burst, later).is_ok());
+        assert!(bucket.take(rate, burst, later).is_err());
+    }
+
+    #[test]
+    fn test_incoming_request_ids_are_validated() {
+        assert!(is_valid_request_id("3f2b8c1e-support-ticket-42"));
+        assert!(!is_valid_request_id(""));
+        assert!(!is_valid_request_id("two words"));
+        assert!(!is_valid_request_id("line\nbreak"));
+        assert!(!is_valid_request_id(&"a".repeat(MAX_REQUEST_ID_LEN + 1)));
+    }
+
+    #[test]
+    fn test_envelope_is_opt_in() {
+        assert!(accepts_envelope("application/vnd.tss-api.envelope+json"));
+        assert!(accepts_envelope(
+            "text/html, Application/Vnd.Tss-Api.Envelope+Json; q=0.9"
+        ));
+        assert!(!accepts_envelope("application/json"));
+        assert!(!accepts_envelope("*/*"));
+
+        assert!(!wants_envelope());
+        assert!(ENVELOPE.sync_scope(true, wants_envelope));
+    }
+}
@@ -1,169 +1,1020 @@
-use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr, time::Duration};
 
-#[derive(Debug, Serialize, Deserialize, Clone, Copy, Eq, PartialEq)]
+use poem_openapi::{Enum, Object};
+use serde::{Deserialize, Deserializer, Serialize, de};
+use solana_client::rpc_config::RpcSendTransactionConfig;
+use solana_sdk::{
+    commitment_config::CommitmentLevel, native_token::LAMPORTS_PER_SOL, pubkey::Pubkey,
+};
+
+use crate::{
+    error::Error,
+    rpc_utils::{DEFAULT_CONFIRM_TIMEOUT, DEFAULT_MAX_RETRIES},
+};
+
+/// Deserialized through `FromStr`, so any case and the usual aliases are accepted
+#[derive(Debug, Serialize, Clone, Copy, Eq, PartialEq, Hash, Enum)]
 #[serde(rename_all = "lowercase")]
+#[oai(rename_all = "lowercase")]
 pub enum Network {
     Mainnet,
     Testnet,
     Devnet,
 }
 
+impl FromStr for Network {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "mainnet" | "mainnet-beta" | "main" => Ok(Self::Mainnet),
+            "testnet" | "test" => Ok(Self::Testnet),
+            "devnet" | "dev" => Ok(Self::Devnet),
+            _ => Err(Error::WrongNetwork(s.to_string())),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Network {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(de::Error::custom)
+    }
+}
+
 impl Network {
-    pub fn get_cluster_url(&self) -> &'static str {
+    /// Same as the serialized form
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Mainnet => "mainnet",
+            Self::Testnet => "testnet",
+            Self::Devnet => "devnet",
+        }
+    }
+
+    pub fn get_cluster_url(&self) -> String {
         match self {
             Self::Mainnet => "https://api.mainnet-beta.solana.com",
             Self::Testnet => "https://api.testnet.solana.com",
             Self::Devnet => "https://api.devnet.solana.com",
         }
+        .to_string()
+    }
+
+    /// Largest single airdrop the public faucet hands out, bigger ones are split
+    pub fn airdrop_cap_lamports(&self) -> u64 {
+        match self {
+            Self::Mainnet | Self::Testnet => LAMPORTS_PER_SOL,
+            Self::Devnet => 2 * LAMPORTS_PER_SOL,
+        }
     }
+
+    pub fn get_websocket_url(&self) -> String {
+        match self {
+            Self::Mainnet => "wss://api.mainnet-beta.solana.com",
+            Self::Testnet => "wss://api.testnet.solana.com",
+            Self::Devnet => "wss://api.devnet.solana.com",
+        }
+        .to_string()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Object)]
+pub struct HealthResponse {
+    pub status: String,
+    pub version: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Object)]
+pub struct VersionResponse {
+    pub version: String,
+    pub serialization_version: u8, // Of message_1, secret_state and partial_signature
+}
+
+#[derive(Debug, Serialize, Deserialize, Object)]
+pub struct ReadyResponse {
+    pub status: String,
+    pub checked_network: Option<Network>, // None when the RPC check is disabled
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq, Enum)]
+#[serde(rename_all = "snake_case")]
+#[oai(rename_all = "snake_case")]
+pub enum KeyFormat {
+    #[default]
+    Base58, // The 64 byte keypair as one base58 string, what every other endpoint takes
+    JsonArray, // The 64 bytes as a JSON array, the Solana CLI keypair file format
+    Hex,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GenerateKeypairQuery {
+    pub format: Option<KeyFormat>, // Encoding of secret_share, defaults to base58
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Object)]
 pub struct GenerateKeypairResponse {
+    pub secret_share: String, // In the requested format
+    pub public_share: String,
+    pub public_share_hex: String,
+}
+
+impl fmt::Debug for GenerateKeypairResponse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GenerateKeypairResponse")
+            .field("secret_share", &"<redacted>")
+            .field("public_share", &self.public_share)
+            .field("public_share_hex", &self.public_share_hex)
+            .finish()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Object)]
+pub struct GenerateVanityRequest {
+    pub prefix: String,               // Base58, so no 0, O, I or l
+    pub case_sensitive: Option<bool>, // Defaults to true
+    pub max_attempts: Option<u64>,    // Defaults to 1,000,000, at most 50,000,000
+}
+
+#[derive(Debug, Serialize, Deserialize, Object)]
+pub struct GenerateVanityResponse {
     pub secret_share: String,
     pub public_share: String,
+    pub attempts: u64,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Object)]
 pub struct BalanceRequest {
     pub address: String,
-    pub net: Network,
+    pub net: Option<Network>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Object)]
 pub struct BalanceResponse {
     pub address: String,
-    pub balance: u64,
+    pub balance: u64,     // In lamports
+    pub balance_sol: f64, // For display only, use `balance` for arithmetic
+}
+
+#[derive(Debug, Serialize, Deserialize, Object)]
+pub struct BalancesRequest {
+    pub addresses: Vec<String>,
+    pub net: Option<Network>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Object)]
+pub struct AddressBalance {
+    pub address: String,
+    pub balance: u64, // In lamports, 0 when the account doesn't exist
+    pub balance_sol: f64,
+    pub exists: bool,
+    pub error: Option<String>, // Set when the address couldn't be parsed
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Object)]
+pub struct BalancesResponse {
+    pub balances: Vec<AddressBalance>, // Same order as the requested addresses
+}
+
+#[derive(Debug, Serialize, Deserialize, Object)]
 pub struct AirdropRequest {
     pub to: String,
-    pub amount: f64,
-    pub net: Network,
+    #[serde(alias = "amount")]
+    pub amount_sol: Option<f64>, // Either amount_sol or amount_lamports, "amount" is still accepted
+    pub amount_lamports: Option<u64>,
+    pub net: Option<Network>,
+    pub wait_for_confirmation: Option<bool>, // Defaults to true, false returns right after the request
+    pub retries: Option<u32>, // Retries of each airdrop call that got rate limited, defaults to 0
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Object)]
 pub struct AirdropResponse {
-    pub transaction_id: String,
+    pub transaction_id: String, // The first airdrop when the amount was split
+    pub transaction_ids: Vec<String>, // Amounts above the faucet's cap take several airdrops
+    pub to: String,
+    pub lamports: u64, // Funded amount, below requested_lamports when a later airdrop failed
+    pub requested_lamports: u64,
+    pub confirmed: bool,
+    pub error: Option<String>, // Why the amount was only partly funded
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Enum)]
+#[serde(rename_all = "lowercase")]
+#[oai(rename_all = "lowercase")]
+pub enum Commitment {
+    Processed,
+    Confirmed,
+    Finalized,
+}
+
+impl From<Commitment> for CommitmentLevel {
+    fn from(commitment: Commitment) -> Self {
+        match commitment {
+            Commitment::Processed => Self::Processed,
+            Commitment::Confirmed => Self::Confirmed,
+            Commitment::Finalized => Self::Finalized,
+        }
+    }
+}
+
+/// Shared by every endpoint that broadcasts a transaction
+#[derive(Debug, Default, Clone, Serialize, Deserialize, Object)]
+pub struct SendOptions {
+    pub max_retries: Option<u32>, // Retries on transient RPC errors, defaults to 3
+    pub skip_preflight: Option<bool>,
+    pub preflight_commitment: Option<Commitment>, // Defaults to the client's commitment
+    pub rpc_max_retries: Option<usize>,           // How many times the RPC node itself rebroadcasts
+    pub confirm_timeout_secs: Option<u64>, // Give up waiting for confirmation, defaults to 60
+    #[serde(default)]
+    #[oai(default)]
+    pub confirm_mainnet: bool, // Required to be true for anything sent to mainnet
+}
+
+impl SendOptions {
+    pub fn max_retries(&self) -> u32 {
+        self.max_retries.unwrap_or(DEFAULT_MAX_RETRIES)
+    }
+
+    pub fn confirm_timeout(&self) -> Duration {
+        self.confirm_timeout_secs
+            .map_or(DEFAULT_CONFIRM_TIMEOUT, Duration::from_secs)
+    }
+
+    pub fn rpc_send_config(&self, default_commitment: CommitmentLevel) -> RpcSendTransactionConfig {
+        RpcSendTransactionConfig {
+            skip_preflight: self.skip_preflight.unwrap_or_default(),
+            preflight_commitment: Some(
+                self.preflight_commitment
+                    .map_or(default_commitment, CommitmentLevel::from),
+            ),
+            max_retries: self.rpc_max_retries,
+            ..RpcSendTransactionConfig::default()
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq, Enum)]
+#[serde(rename_all = "lowercase")]
+#[oai(rename_all = "lowercase")]
+pub enum MemoPosition {
+    Before, // Memos come first, before the transfer
+    #[default]
+    After, // Memos follow the transfer, ahead of any tip
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Object)]
 pub struct SendSingleRequest {
     pub keypair: String,
-    pub amount: f64,
+    #[serde(alias = "amount")]
+    pub amount_sol: Option<f64>, // Either amount_sol or amount_lamports, "amount" is still accepted
+    pub amount_lamports: Option<u64>,
+    pub amount_percent: Option<f64>, // Instead of an amount, share of the balance left after keeping rent + fee
     pub to: String,
-    pub net: Network,
+    pub net: Option<Network>,
     pub memo: Option<String>,
+    pub memos: Option<Vec<String>>, // One memo instruction each, in order, after memo if both are set
+    pub memo_position: Option<MemoPosition>, // Defaults to after the transfer
+    pub tip_account: Option<String>, // Receives tip_lamports in the same transaction, e.g. a Jito tip account
+    pub tip_lamports: Option<u64>,
+    #[serde(default)]
+    #[oai(default)]
+    pub check_balance: bool, // Verify the payer covers amount + fee before signing
+    pub fee_payer: Option<String>, // Pays the fee instead of keypair, has to sign as well
+    pub fee_payer_keypair: Option<String>, // Base58 encoded keypair of fee_payer
+    pub idempotency_key: Option<String>, // Also accepted as the Idempotency-Key header
+    pub recent_block_hash: Option<String>, // Skips fetching one, e.g. from /api/recent_block_hash
+    #[serde(default)]
+    #[oai(default)]
+    pub check_block_hash: bool, // Ask the node whether recent_block_hash is still valid first
+    #[serde(default)]
+    #[oai(default)]
+    pub solana_pay_url: bool, // Also return the transfer as a Solana Pay URL, allows one memo and no tip
+    #[serde(flatten)]
+    #[oai(flatten)]
+    pub send_options: SendOptions,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Object)]
 pub struct SendSingleResponse {
     pub transaction_id: String,
+    pub amount_lamports: Option<u64>, // What was sent, null when replayed through idempotency_key
+    pub solana_pay_url: Option<String>, // With solana_pay_url set, the same transfer as a solana: URL
+    pub slot: Option<u64>, // Slot the transaction landed in, null if the node hasn't indexed it yet
+    pub block_time: Option<i64>, // Unix timestamp of that slot
+}
+
+#[derive(Debug, Serialize, Deserialize, Object)]
+pub struct EstimateComputeUnitsResponse {
+    pub units_consumed: u64,
+    pub suggested_limit: u64, // units_consumed + 10%, capped at the 1.4M per transaction maximum
+}
+
+#[derive(Debug, Serialize, Deserialize, Object)]
+pub struct RawAccountMeta {
+    pub pubkey: String,
+    pub is_signer: bool, // Only the keypair can sign, other signers make the request fail
+    pub is_writable: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Object)]
+pub struct RawInstruction {
+    pub program_id: String,
+    pub accounts: Vec<RawAccountMeta>,
+    pub data_base64: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Object)]
+pub struct SendRawInstructionsRequest {
+    pub keypair: String, // Pays the fee and signs every instruction
+    pub net: Option<Network>,
+    pub instructions: Vec<RawInstruction>,
+    #[serde(flatten)]
+    #[oai(flatten)]
+    pub send_options: SendOptions,
+}
+
+#[derive(Debug, Serialize, Deserialize, Object)]
+pub struct SendRawInstructionsResponse {
+    pub transaction_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Object)]
+pub struct ValidateAddressRequest {
+    pub address: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Object)]
+pub struct ValidateAddressResponse {
+    pub valid: bool,
+    pub is_on_curve: bool, // false for PDAs (program derived addresses)
+}
+
+#[derive(Serialize, Deserialize, Object)]
+pub struct KeypairPubkeyRequest {
+    pub keypair: String, // Base58 encoded keypair
+}
+
+// The whole point of the endpoint is handling a secret, keep it out of any debug output
+impl fmt::Debug for KeypairPubkeyRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeypairPubkeyRequest")
+            .field("keypair", &"<redacted>")
+            .finish()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Object)]
+pub struct KeypairPubkeyResponse {
+    pub public_key: String,
+}
+
+#[derive(Serialize, Deserialize, Object)]
+pub struct KeypairFromMnemonicRequest {
+    pub mnemonic: String, // BIP39 English seed phrase
+    pub passphrase: Option<String>,
+    pub derivation_path: Option<String>, // Defaults to m/44'/501'/0'/0', the path wallets use
+}
+
+impl fmt::Debug for KeypairFromMnemonicRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeypairFromMnemonicRequest")
+            .field("mnemonic", &"<redacted>")
+            .field(
+                "passphrase",
+                &self.passphrase.as_ref().map(|_| "<redacted>"),
+            )
+            .field("derivation_path", &self.derivation_path)
+            .finish()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Object)]
+pub struct KeypairFromMnemonicResponse {
+    pub secret_share: String, // Base58 encoded keypair
+    pub public_share: String,
+    pub derivation_path: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, Enum)]
+#[serde(rename_all = "lowercase")]
+#[oai(rename_all = "lowercase")]
+pub enum TransactionEncoding {
+    #[default]
+    Base64,
+    Base58,
+}
+
+#[derive(Debug, Serialize, Deserialize, Object)]
+pub struct DecodeTransactionRequest {
+    pub transaction: String, // bincode serialized Transaction
+    #[serde(default)]
+    #[oai(default)]
+    pub encoding: TransactionEncoding,
+}
+
+#[derive(Debug, Serialize, Deserialize, Object)]
+pub struct DecodedAccount {
+    pub pubkey: String,
+    pub is_signer: bool,
+    pub is_writable: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Object)]
+pub struct DecodedSignature {
+    pub signer: String,
+    pub signature: Option<String>, // None while the signer hasn't signed yet
+    pub valid: bool,               // Signature verifies against the message
+}
+
+#[derive(Debug, Serialize, Deserialize, Object)]
+pub struct DecodedInstruction {
+    pub program_id: String,
+    pub accounts: Vec<String>,
+    pub data: String, // Base58 encoded
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Object)]
+pub struct DecodeTransactionResponse {
+    pub fee_payer: Option<String>,
+    pub recent_block_hash: String,
+    pub message_hash: String, // Same for every party signing the exact same transaction
+    pub accounts: Vec<DecodedAccount>,
+    pub signatures: Vec<DecodedSignature>,
+    pub missing_signers: Vec<String>,
+    pub instructions: Vec<DecodedInstruction>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Object)]
 pub struct RecentBlockHashRequest {
-    pub net: Network,
+    pub net: Option<Network>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Object)]
 pub struct RecentBlockHashResponse {
     pub recent_block_hash: String,
+    pub last_valid_block_height: u64, // The hash is rejected once the chain passes this height
+    pub current_block_height: u64,
+    pub valid: bool,       // Whether the node still accepts the hash
+    pub fee_lamports: u64, // Base fee of a one signature transaction, e.g. an aggregated transfer
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Object)]
 pub struct AggregateKeysRequest {
     pub keys: Vec<String>,
+    /// Party whose MuSig coefficient is computed, must be one of `keys`.
+    /// The aggregated key is the same for every party, defaults to the first key
+    pub signer: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Object)]
 pub struct AggregateKeysResponse {
     pub aggregated_public_key: String,
+    /// Hex of the 32-byte compressed Ed25519 encoding: little-endian `y`
+    /// with the sign of `x` in the top bit, the same bytes as the base58 key
+    pub raw_bytes_hex: String,
+    pub compressed: bool, // Always true, Solana keys are compressed points
+    pub signer: String,   // Base58 signer the coefficient belongs to
+    pub signer_coefficient: String, // Hex little-endian MuSig coefficient a_i
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Object)]
 pub struct AggSendStepOneRequest {
     pub keypair: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Object)]
 pub struct AggSendStepOneResponse {
     pub message_1: String,
     pub secret_state: String,
+    pub serialization_version: u8, // Pass it back to step two
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Object)]
 pub struct AggSendStepTwoRequest {
     pub keypair: String,
-    pub amount: f64,
+    #[serde(alias = "amount")]
+    pub amount_sol: Option<f64>, // Either amount_sol or amount_lamports, "amount" is still accepted
+    pub amount_lamports: Option<u64>,
     pub to: String,
     pub memo: Option<String>,
-    pub recent_block_hash: String,
+    pub tip_account: Option<String>, // Receives tip_lamports in the same transaction, e.g. a Jito tip account
+    pub tip_lamports: Option<u64>,
+    pub recent_block_hash: Option<String>, // Omit to read the stored nonce (needs nonce_account + net)
     pub keys: Vec<String>,
     pub first_messages: Vec<String>,
     pub secret_state: String,
+    pub serialization_version: Option<u8>, // From the step one response, checked when given
+    pub nonce_account: Option<String>, // Durable nonce account, authority must be the aggregated key
+    pub nonce_authority: Option<String>,
+    pub net: Option<Network>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Object)]
 pub struct AggSendStepTwoResponse {
     pub partial_signature: String,
+    pub serialization_version: u8, // Pass it back to aggregate_signatures
+}
+
+#[derive(Debug, Serialize, Deserialize, Object)]
+pub struct SigningPackageCreateRequest {
+    #[serde(alias = "amount")]
+    pub amount_sol: Option<f64>, // Either amount_sol or amount_lamports
+    pub amount_lamports: Option<u64>,
+    pub to: String,
+    pub memo: Option<String>,
+    pub tip_account: Option<String>,
+    pub tip_lamports: Option<u64>,
+    pub recent_block_hash: Option<String>, // Omit to read the stored nonce (needs nonce_account + net)
+    pub keys: Vec<String>,
+    pub first_messages: Vec<String>,
+    pub nonce_account: Option<String>,
+    pub nonce_authority: Option<String>,
+    pub net: Option<Network>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Object)]
+pub struct SigningPackageCreateResponse {
+    pub package: String, // Versioned base58 container, hand it to each offline signer
+}
+
+#[derive(Debug, Serialize, Deserialize, Object)]
+pub struct SigningPackageStepTwoRequest {
+    pub package: String,
+    pub keypair: String,
+    pub secret_state: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Object)]
 pub struct AggregateSignaturesRequest {
     pub signatures: Vec<String>,
-    pub amount: f64,
+    #[serde(alias = "amount")]
+    pub amount_sol: Option<f64>, // Either amount_sol or amount_lamports, "amount" is still accepted
+    pub amount_lamports: Option<u64>,
     pub to: String,
     pub memo: Option<String>,
-    pub recent_block_hash: String,
-    pub net: Network,
+    pub tip_account: Option<String>, // Receives tip_lamports in the same transaction, e.g. a Jito tip account
+    pub tip_lamports: Option<u64>,
+    pub recent_block_hash: Option<String>, // Omit to read the stored nonce of nonce_account
+    pub net: Option<Network>,
     pub keys: Vec<String>,
+    pub nonce_account: Option<String>,
+    pub nonce_authority: Option<String>,
+    pub first_messages: Option<Vec<String>>, // Same order as signatures, checks each one before combining
+    pub serialization_version: Option<u8>,   // From the step two responses, checked when given
+    #[serde(flatten)]
+    #[oai(flatten)]
+    pub send_options: SendOptions,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Object)]
 pub struct AggregateSignaturesResponse {
     pub transaction_id: String,
+    pub slot: Option<u64>, // Slot the transaction landed in, null if the node hasn't indexed it yet
+    pub block_time: Option<i64>, // Unix timestamp of that slot
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Object)]
+pub struct AggSessionCreateRequest {
+    pub keys: Vec<String>,
+    #[serde(alias = "amount")]
+    pub amount_sol: Option<f64>, // Either amount_sol or amount_lamports, "amount" is still accepted
+    pub amount_lamports: Option<u64>,
+    pub to: String,
+    pub memo: Option<String>,
+    pub tip_account: Option<String>, // Receives tip_lamports in the same transaction, e.g. a Jito tip account
+    pub tip_lamports: Option<u64>,
+    pub recent_block_hash: Option<String>, // Omit to read the stored nonce of nonce_account
+    pub net: Option<Network>,
+    pub nonce_account: Option<String>,
+    pub nonce_authority: Option<String>,
+    pub auto_broadcast: Option<bool>, // Aggregate and broadcast once every key has submitted, default true
+    #[serde(flatten)]
+    #[oai(flatten)]
+    pub send_options: SendOptions,
+}
+
+#[derive(Debug, Serialize, Deserialize, Object)]
+pub struct AggSessionCreateResponse {
+    pub session_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Object)]
+pub struct AggSessionSubmitRequest {
+    pub signer: String, // Public key of the submitting signer, one of the session keys
+    pub signature: String, // Partial signature from agg_send_step_two
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Enum)]
+#[serde(rename_all = "lowercase")]
+#[oai(rename_all = "lowercase")]
+pub enum AggSessionState {
+    Collecting,
+    Broadcasting,
+    Broadcast,
+}
+
+#[derive(Debug, Serialize, Deserialize, Object)]
+pub struct AggSessionStatusResponse {
+    pub session_id: String,
+    pub state: AggSessionState,
+    pub submitted: Vec<String>,
+    pub missing: Vec<String>,
+    pub signatures: Option<Vec<String>>, // In key order once complete, for /api/aggregate_signatures
+    pub transaction_id: Option<String>,
+    pub error: Option<String>, // Why the last aggregation failed, resubmitting retries it
+}
+
+#[derive(Debug, Serialize, Deserialize, Object)]
+pub struct VerifyPartialSignaturesRequest {
+    pub signatures: Vec<String>,
+    pub first_messages: Vec<String>, // signatures[i] must come from the sender of first_messages[i]
+    #[serde(alias = "amount")]
+    pub amount_sol: Option<f64>, // Either amount_sol or amount_lamports, "amount" is still accepted
+    pub amount_lamports: Option<u64>,
+    pub to: String,
+    pub memo: Option<String>,
+    pub tip_account: Option<String>, // Receives tip_lamports in the same transaction, e.g. a Jito tip account
+    pub tip_lamports: Option<u64>,
+    pub recent_block_hash: Option<String>, // Omit to read the stored nonce (needs nonce_account + net)
+    pub keys: Vec<String>,
+    pub nonce_account: Option<String>,
+    pub nonce_authority: Option<String>,
+    pub net: Option<Network>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Object)]
+pub struct PartialSignatureCheck {
+    pub signer: String,
+    pub valid: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Object)]
+pub struct VerifyPartialSignaturesResponse {
+    pub valid: bool, // True when every partial signature verifies
+    pub results: Vec<PartialSignatureCheck>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Object)]
+pub struct VerifySignatureRequest {
+    pub message_base64: String, // The signed bytes, for a transaction its serialized message
+    pub signature: String,      // Base58, e.g. a transaction id
+    pub public_key: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Object)]
+pub struct VerifySignatureResponse {
+    pub valid: bool,
+}
+
+#[cfg(feature = "testing-helpers")]
+#[derive(Debug, Serialize, Deserialize, Object)]
+pub struct AggSendAllRequest {
+    pub keypairs: Vec<String>, // Every participant's base58 keypair
+    #[serde(alias = "amount")]
+    pub amount_sol: Option<f64>, // Either amount_sol or amount_lamports, "amount" is still accepted
+    pub amount_lamports: Option<u64>,
+    pub to: String,
+    pub memo: Option<String>,
+    pub net: Option<Network>,
+    #[serde(flatten)]
+    #[oai(flatten)]
+    pub send_options: SendOptions,
+}
+
+#[cfg(feature = "testing-helpers")]
+#[derive(Debug, Serialize, Deserialize, Object)]
+pub struct AggSendAllResponse {
+    pub transaction_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Object)]
+pub struct CreateNonceAccountRequest {
+    pub keypair: String,           // Base58 encoded keypair, pays for the account
+    pub authority: Option<String>, // Defaults to the payer, use the aggregated key for TSS
+    pub net: Option<Network>,
+    #[serde(flatten)]
+    #[oai(flatten)]
+    pub send_options: SendOptions,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Object)]
+pub struct CreateNonceAccountResponse {
+    pub nonce_account: String,
+    pub authority: String,
+    pub transaction_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Object)]
+pub struct NonceInfoRequest {
+    pub nonce_account: String,
+    pub net: Option<Network>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Object)]
+pub struct NonceInfoResponse {
+    pub nonce_account: String,
+    pub blockhash: String, // Stored nonce, pass it as recent_block_hash in the aggregate steps
+    pub authority: String, // Has to sign the advance_nonce_account instruction
+    pub lamports_per_signature: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Object)]
 pub struct ErrorResponse {
-    pub error: String,
+    pub code: String,  // Machine-readable, e.g. "SEND_FAILED"
+    pub error: String, // Human-readable message
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub errors: Option<Vec<FieldErrorResponse>>, // Every bad field, only set for INVALID_FIELDS
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>, // Same as the X-Request-Id response header
+}
+
+/// Wraps every body for clients that opted in, `data` on success and `error` otherwise
+#[derive(Debug, Serialize)]
+pub struct Envelope<T> {
+    pub success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<T>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<ErrorResponse>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Object)]
+pub struct FieldErrorResponse {
+    pub field: String, // e.g. "to" or "keys[2]"
+    pub code: String,
+    pub message: String,
 }
 
 // SPL Token Transfer Models
-#[derive(Debug, Serialize, Deserialize)]
+/// SPL token program that owns a mint, Token-2022 mints can carry extensions
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Enum)]
+#[serde(rename_all = "lowercase")]
+#[oai(rename_all = "lowercase")]
+pub enum TokenProgram {
+    #[default]
+    Token,
+    Token2022,
+}
+
+impl TokenProgram {
+    pub fn id(&self) -> Pubkey {
+        match self {
+            TokenProgram::Token => spl_token::id(),
+            TokenProgram::Token2022 => spl_token_2022::id(),
+        }
+    }
+
+    pub fn from_owner(owner: &Pubkey) -> Option<Self> {
+        [TokenProgram::Token, TokenProgram::Token2022]
+            .into_iter()
+            .find(|program| program.id() == *owner)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Object)]
 pub struct SplTokenBalanceRequest {
     pub owner: String,
     pub token_mint: String,
-    pub net: Network,
+    pub net: Option<Network>,
+    pub token_program: Option<TokenProgram>, // Read from the mint when omitted
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Object)]
 pub struct SplTokenBalanceResponse {
     pub owner: String,
     pub token_mint: String,
+    pub token_account: String, // Associated token account that was inspected
+    pub account_exists: bool,  // When false the balance is reported as 0
+    pub balance: u64,
+    pub decimals: u8,
+    pub token_program: TokenProgram,
+}
+
+#[derive(Debug, Serialize, Deserialize, Object)]
+pub struct TokenMetadataRequest {
+    pub token_mint: String,
+    pub net: Option<Network>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Object)]
+pub struct TokenMetadataResponse {
+    pub token_mint: String,
+    pub metadata_account: String, // Metaplex metadata PDA of the mint
+    pub name: Option<String>,     // All null when the mint has no metadata account
+    pub symbol: Option<String>,
+    pub uri: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Object)]
+pub struct DeriveAtaRequest {
+    pub owner: String,
+    pub token_mint: String,
+    pub token_program: Option<TokenProgram>, // Defaults to token, there is no mint lookup
+}
+
+#[derive(Debug, Serialize, Deserialize, Object)]
+pub struct DeriveAtaResponse {
+    pub associated_token_address: String,
+    pub token_program: TokenProgram,
+}
+
+#[derive(Debug, Serialize, Deserialize, Object)]
+pub struct SolanaPayUrlRequest {
+    pub to: String,
+    pub amount_sol: Option<f64>, // Either amount_sol or amount_lamports for SOL, omit both to let the wallet ask
+    pub amount_lamports: Option<u64>,
+    pub token_mint: Option<String>, // Requests this SPL token instead of SOL
+    pub amount_base_units: Option<u64>, // Token amount in the mint's smallest unit
+    pub decimals: Option<u8>,       // Read from the mint when omitted, set it to skip the lookup
+    pub memo: Option<String>,
+    pub label: Option<String>, // Shown by the wallet, e.g. a merchant name
+    pub message: Option<String>,
+    pub references: Option<Vec<String>>, // Added to the transaction as read-only keys to find it later
+    pub net: Option<Network>,            // Only needed to read decimals from the mint
+}
+
+#[derive(Debug, Serialize, Deserialize, Object)]
+pub struct SolanaPayUrlResponse {
+    pub url: String, // solana: transfer request, render it as a QR code or open it as a deep link
+}
+
+#[derive(Debug, Serialize, Deserialize, Object)]
+pub struct SplAllBalancesRequest {
+    pub owner: String,
+    pub net: Option<Network>,
+    #[serde(default)]
+    #[oai(default)]
+    pub include_zero: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Object)]
+pub struct SplBalanceEntry {
+    pub token_account: String,
+    pub token_mint: String,
     pub balance: u64,
     pub decimals: u8,
+    pub token_program: TokenProgram,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Object)]
+pub struct SplAllBalancesResponse {
+    pub owner: String,
+    pub balances: Vec<SplBalanceEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Object)]
 pub struct SplSendSingleRequest {
     pub keypair: String,
-    pub amount: f64,
+    #[serde(default)]
+    #[oai(default)]
+    pub amount: f64, // In whole tokens, ignored when amount_base_units is set
+    pub amount_base_units: Option<u64>, // Exact amount in the mint's smallest unit
     pub to: String,
     pub token_mint: String,
-    pub decimals: u8,
-    pub net: Network,
+    pub decimals: Option<u8>, // Read from the mint when omitted, set it to skip the lookup
+    pub net: Option<Network>,
     pub memo: Option<String>,
+    pub token_program: Option<TokenProgram>, // Read from the mint when omitted
+    pub from_token_account: Option<String>, // Instead of the signer's ATA, owned by or delegated to it
+    #[serde(default)]
+    #[oai(default)]
+    pub solana_pay_url: bool, // Also return the transfer as a Solana Pay URL
+    #[serde(flatten)]
+    #[oai(flatten)]
+    pub send_options: SendOptions,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Object)]
 pub struct SplSendSingleResponse {
     pub transaction_id: String,
+    pub solana_pay_url: Option<String>, // With solana_pay_url set, the same transfer as a solana: URL
+}
+
+#[derive(Debug, Serialize, Deserialize, Object)]
+pub struct SplCreateAccountRequest {
+    pub keypair: String, // Base58 encoded keypair, pays for the account
+    pub owner: String,
+    pub token_mint: String,
+    pub net: Option<Network>,
+    pub token_program: Option<TokenProgram>, // Read from the mint when omitted
+    #[serde(flatten)]
+    #[oai(flatten)]
+    pub send_options: SendOptions,
+}
+
+#[derive(Debug, Serialize, Deserialize, Object)]
+pub struct SplCreateAccountResponse {
+    pub token_account: String,
+    pub transaction_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Object)]
+pub struct SplApproveRequest {
+    pub keypair: String, // Base58 encoded keypair of the token account owner, pays the fee
+    pub token_mint: String,
+    pub delegate: String,
+    pub amount: f64, // In whole tokens, the most the delegate may transfer
+    pub decimals: u8,
+    pub net: Option<Network>,
+    pub token_program: Option<TokenProgram>, // Read from the mint when omitted
+    #[serde(flatten)]
+    #[oai(flatten)]
+    pub send_options: SendOptions,
+}
+
+#[derive(Debug, Serialize, Deserialize, Object)]
+pub struct SplApproveResponse {
+    pub token_account: String,
+    pub delegate: String,
+    pub amount_base_units: u64,
+    pub transaction_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Object)]
+pub struct SplRevokeRequest {
+    pub keypair: String, // Base58 encoded keypair of the token account owner, pays the fee
+    pub token_mint: String,
+    pub net: Option<Network>,
+    pub token_program: Option<TokenProgram>, // Read from the mint when omitted
+    #[serde(flatten)]
+    #[oai(flatten)]
+    pub send_options: SendOptions,
+}
+
+#[derive(Debug, Serialize, Deserialize, Object)]
+pub struct SplRevokeResponse {
+    pub token_account: String,
+    pub transaction_id: String,
+}
+
+/// Owner and close authorities belong to a token account, mint and freeze to a mint
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Enum)]
+#[serde(rename_all = "lowercase")]
+#[oai(rename_all = "lowercase")]
+pub enum SplAuthorityType {
+    Owner,
+    Close,
+    Mint,
+    Freeze,
+}
+
+impl SplAuthorityType {
+    /// Same as the serialized form
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Owner => "owner",
+            Self::Close => "close",
+            Self::Mint => "mint",
+            Self::Freeze => "freeze",
+        }
+    }
+
+    pub fn is_mint_authority(&self) -> bool {
+        matches!(self, Self::Mint | Self::Freeze)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Object)]
+pub struct SplSetAuthorityRequest {
+    pub keypair: String, // Base58 encoded keypair of the current authority, pays the fee
+    pub token_account_or_mint: String,
+    pub authority_type: SplAuthorityType,
+    pub new_authority: Option<String>, // Omit to remove the authority for good, not allowed for owner
+    pub net: Option<Network>,
+    #[serde(flatten)]
+    #[oai(flatten)]
+    pub send_options: SendOptions,
+}
+
+#[derive(Debug, Serialize, Deserialize, Object)]
+pub struct SplSetAuthorityResponse {
+    pub token_account_or_mint: String,
+    pub authority_type: SplAuthorityType,
+    pub new_authority: Option<String>,
+    pub transaction_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Object)]
+pub struct SplCloseAccountRequest {
+    pub keypair: String, // Base58 encoded keypair of the token account owner, pays the fee
+    pub token_mint: String,
+    pub destination: Option<String>, // Receives the reclaimed rent, defaults to the owner
+    pub net: Option<Network>,
+    pub token_program: Option<TokenProgram>, // Read from the mint when omitted
+    #[serde(flatten)]
+    #[oai(flatten)]
+    pub send_options: SendOptions,
+}
+
+#[derive(Debug, Serialize, Deserialize, Object)]
+pub struct SplCloseAccountResponse {
+    pub token_account: String,
+    pub destination: String,
+    pub reclaimed_lamports: u64,
+    pub transaction_id: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Object)]
 pub struct SplAggSendStepTwoRequest {
     pub keypair: String,
     pub amount: f64,
@@ -175,201 +1026,507 @@ pub struct SplAggSendStepTwoRequest {
     pub keys: Vec<String>,
     pub first_messages: Vec<String>,
     pub secret_state: String,
+    pub serialization_version: Option<u8>, // From the step one response, checked when given
+    pub net: Option<Network>,              // Must be the cluster spl_aggregate_signatures sends to
+    #[serde(default)]
+    #[oai(default)]
+    pub token_program: TokenProgram, // Every signer and the aggregator must use the same one
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Object)]
 pub struct SplAggSendStepTwoResponse {
     pub partial_signature: String,
+    pub serialization_version: u8, // Pass it back to spl_aggregate_signatures
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Object)]
 pub struct SplAggregateSignaturesRequest {
     pub signatures: Vec<String>,
+    pub serialization_version: Option<u8>, // From the step two responses, checked when given
     pub amount: f64,
     pub to: String,
     pub token_mint: String,
     pub decimals: u8,
     pub memo: Option<String>,
     pub recent_block_hash: String,
-    pub net: Network,
+    pub net: Option<Network>,
     pub keys: Vec<String>,
+    #[serde(default)]
+    #[oai(default)]
+    pub token_program: TokenProgram, // Must match the one used in spl_agg_send_step_two
+    #[serde(flatten)]
+    #[oai(flatten)]
+    pub send_options: SendOptions,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Object)]
 pub struct SplAggregateSignaturesResponse {
     pub transaction_id: String,
+    pub slot: Option<u64>, // Slot the transaction landed in, null if the node hasn't indexed it yet
+    pub block_time: Option<i64>, // Unix timestamp of that slot
 }
 
 //-----------------------stake Account Creation
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Object)]
 pub struct StakeAccountRequest {
-    pub net: Network,
-    pub keypair: String,   // Base58 encoded keypair
-    pub stake_amount: u64, // Amount to stake in lamports
-    pub seed: String,      // Seed for deriving the stake account
+    pub net: Option<Network>,
+    pub keypair: String, // Base58 encoded keypair
+    #[serde(alias = "stake_amount")]
+    pub amount_lamports: Option<u64>, // Either amount_lamports or amount_sol, "stake_amount" is still accepted
+    pub amount_sol: Option<f64>,
+    pub seed: String, // Seed for deriving the stake account
     pub validator_vote_accont: String,
+    #[serde(flatten)]
+    #[oai(flatten)]
+    pub send_options: SendOptions,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Object)]
 pub struct StakeAccountResponse {
     pub stake_account_address: String,
     pub transaction_id: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Object)]
+pub struct DeriveStakeAccountRequest {
+    pub payer: String, // Public key of the keypair (or aggregated key) that creates the account
+    pub seed: String,  // At most 32 bytes
+}
+
+#[derive(Debug, Serialize, Deserialize, Object)]
+pub struct DeriveStakeAccountResponse {
+    pub stake_account_address: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Object)]
 pub struct DeactivateStakeRequest {
-    pub net: Network,
-    pub keypair: String,       // Base58 encoded keypair
-    pub stake_account: String, // Stake account pubkey
+    pub net: Option<Network>,
+    pub keypair: String,                           // Base58 encoded keypair
+    pub stake_account: String,                     // Stake account pubkey
+    pub auto_withdraw_destination: Option<String>, // Withdraw everything here once the cooldown ends, keypair must be the withdraw authority
+    #[serde(flatten)]
+    #[oai(flatten)]
+    pub send_options: SendOptions,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Object)]
 pub struct DeactivateStakeResponse {
     pub transaction_id: String,
+    pub auto_withdraw_scheduled: bool,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Object)]
 pub struct WithdrawStakeRequest {
-    pub net: Network,
+    pub net: Option<Network>,
     pub keypair: String,       // Base58 encoded keypair
     pub stake_account: String, // Stake account pubkey
     pub destination: String,   // Destination pubkey for withdrawn funds
-    pub amount: u64,           // Amount to withdraw in lamports
+    #[serde(alias = "amount")]
+    pub amount_lamports: Option<u64>, // Either amount_lamports or amount_sol, omit both (or 0) to withdraw all that's available
+    pub amount_sol: Option<f64>,
+    pub check_state: Option<bool>, // Default true, refuse up front while the stake is still active
+    #[serde(default)]
+    #[oai(default)]
+    pub close: bool, // Withdraw the whole balance, rent included, needs fully deactivated stake
+    #[serde(flatten)]
+    #[oai(flatten)]
+    pub send_options: SendOptions,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Object)]
 pub struct WithdrawStakeResponse {
     pub transaction_id: String,
+    pub amount_lamports: u64, // What was withdrawn, useful when no amount was given
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct AggStakeStepOneRequest {
-    pub keypair: String, // Base58 encoded keypair
-}
-
-#[derive(Debug, Serialize)]
-pub struct AggStakeStepOneResponse {
-    pub message_1: String,    // Base58 encoded AggMessage1
-    pub secret_state: String, // Base58 encoded SecretAggStepOne
-}
+// Step one does not depend on the transaction, it is the same as /api/agg_send_step_one
+pub type AggStakeStepOneRequest = AggSendStepOneRequest;
+pub type AggStakeStepOneResponse = AggSendStepOneResponse;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Object)]
 pub struct AggStakeStepTwoRequest {
-    pub net: Network,
-    pub keypair: String,   // Base58 encoded keypair
-    pub stake_amount: u64, // Amount to stake in lamports
-    pub seed: String,      // Seed for stake account
+    pub net: Option<Network>,
+    pub keypair: String, // Base58 encoded keypair
+    #[serde(alias = "stake_amount")]
+    pub amount_lamports: Option<u64>, // Either amount_lamports or amount_sol, "stake_amount" is still accepted
+    pub amount_sol: Option<f64>,
+    pub seed: String, // Seed for stake account
     pub validator_vote_accont: String,
-    pub keys: Vec<String>,           // List of pubkeys for aggregation
-    pub first_messages: Vec<String>, // Base58 encoded AggMessage1
-    pub secret_state: String,        // Base58 encoded SecretAggStepOne from step one
-    pub recent_block_hash: String,   // Base58 encoded recent blockhash
+    pub keys: Vec<String>,                 // List of pubkeys for aggregation
+    pub first_messages: Vec<String>,       // Base58 encoded AggMessage1
+    pub secret_state: String,              // Base58 encoded SecretAggStepOne from step one
+    pub serialization_version: Option<u8>, // From the step one response, checked when given
+    pub recent_block_hash: String,         // Base58 encoded recent blockhash
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Object)]
 pub struct AggStakeStepTwoResponse {
     pub partial_signature: String, // Base58 encoded PartialSignature
+    pub serialization_version: u8, // Pass it back to aggregate_stake_signatures
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct AggDeactivateStakeStepOneRequest {
-    pub keypair: String, // Base58 encoded keypair
-}
-
-#[derive(Debug, Serialize)]
-pub struct AggDeactivateStakeStepOneResponse {
-    pub message_1: String,    // Base58 encoded AggMessage1
-    pub secret_state: String, // Base58 encoded SecretAggStepOne
-}
+pub type AggDeactivateStakeStepOneRequest = AggSendStepOneRequest;
+pub type AggDeactivateStakeStepOneResponse = AggSendStepOneResponse;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Object)]
 pub struct AggDeactivateStakeStepTwoRequest {
-    pub net: Network,
-    pub keypair: String,             // Base58 encoded keypair
-    pub stake_account: String,       // Stake account pubkey
-    pub keys: Vec<String>,           // List of pubkeys for aggregation
-    pub first_messages: Vec<String>, // Base58 encoded AggMessage1
-    pub secret_state: String,        // Base58 encoded SecretAggStepOne from step one
-    pub recent_block_hash: String,   // Base58 encoded recent blockhash
+    pub net: Option<Network>,
+    pub keypair: String,                   // Base58 encoded keypair
+    pub stake_account: String,             // Stake account pubkey
+    pub keys: Vec<String>,                 // List of pubkeys for aggregation
+    pub first_messages: Vec<String>,       // Base58 encoded AggMessage1
+    pub secret_state: String,              // Base58 encoded SecretAggStepOne from step one
+    pub serialization_version: Option<u8>, // From the step one response, checked when given
+    pub recent_block_hash: String,         // Base58 encoded recent blockhash
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Object)]
 pub struct AggDeactivateStakeStepTwoResponse {
     pub partial_signature: String, // Base58 encoded PartialSignature
+    pub serialization_version: u8, // Pass it back to aggregate_deactivate_stake_signatures
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct AggWithdrawStakeStepOneRequest {
-    pub keypair: String, // Base58 encoded keypair
-}
-
-#[derive(Debug, Serialize)]
-pub struct AggWithdrawStakeStepOneResponse {
-    pub message_1: String,    // Base58 encoded AggMessage1
-    pub secret_state: String, // Base58 encoded SecretAggStepOne
-}
+pub type AggWithdrawStakeStepOneRequest = AggSendStepOneRequest;
+pub type AggWithdrawStakeStepOneResponse = AggSendStepOneResponse;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Object)]
 pub struct AggWithdrawStakeStepTwoRequest {
-    pub net: Network,
-    pub keypair: String,             // Base58 encoded keypair
-    pub stake_account: String,       // Stake account pubkey
-    pub destination: String,         // Destination pubkey for withdrawn funds
-    pub amount: u64,                 // Amount to withdraw in lamports
-    pub keys: Vec<String>,           // List of pubkeys for aggregation
-    pub first_messages: Vec<String>, // Base58 encoded AggMessage1
-    pub secret_state: String,        // Base58 encoded SecretAggStepOne from step one
-    pub recent_block_hash: String,   // Base58 encoded recent blockhash
+    pub net: Option<Network>,
+    pub keypair: String,       // Base58 encoded keypair
+    pub stake_account: String, // Stake account pubkey
+    pub destination: String,   // Destination pubkey for withdrawn funds
+    #[serde(alias = "amount")]
+    pub amount_lamports: Option<u64>, // Either amount_lamports or amount_sol, "amount" is still accepted
+    pub amount_sol: Option<f64>,
+    pub keys: Vec<String>,                 // List of pubkeys for aggregation
+    pub first_messages: Vec<String>,       // Base58 encoded AggMessage1
+    pub secret_state: String,              // Base58 encoded SecretAggStepOne from step one
+    pub serialization_version: Option<u8>, // From the step one response, checked when given
+    pub recent_block_hash: String,         // Base58 encoded recent blockhash
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Object)]
 pub struct AggWithdrawStakeStepTwoResponse {
     pub partial_signature: String, // Base58 encoded PartialSignature
+    pub serialization_version: u8, // Pass it back to aggregate_withdraw_stake_signatures
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Object)]
 pub struct AggregateStakeSignaturesRequest {
-    pub net: Network,
-    pub stake_amount: u64, // Amount to stake in lamports
-    pub seed: String,      // Seed for stake account
+    pub net: Option<Network>,
+    #[serde(alias = "stake_amount")]
+    pub amount_lamports: Option<u64>, // Either amount_lamports or amount_sol, "stake_amount" is still accepted
+    pub amount_sol: Option<f64>,
+    pub seed: String, // Seed for stake account
     pub validator_vote_accont: String,
-    pub keys: Vec<String>,         // List of pubkeys
-    pub signatures: Vec<String>,   // Base58 encoded PartialSignatures
-    pub recent_block_hash: String, // Base58 encoded recent blockhash
+    pub keys: Vec<String>,                 // List of pubkeys
+    pub signatures: Vec<String>,           // Base58 encoded PartialSignatures
+    pub serialization_version: Option<u8>, // From the step two responses, checked when given
+    pub recent_block_hash: String,         // Base58 encoded recent blockhash
+    #[serde(flatten)]
+    #[oai(flatten)]
+    pub send_options: SendOptions,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Object)]
 pub struct AggregateStakeSignaturesResponse {
     pub transaction_id: String,
+    pub slot: Option<u64>, // Slot the transaction landed in, null if the node hasn't indexed it yet
+    pub block_time: Option<i64>, // Unix timestamp of that slot
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Object)]
 pub struct AggregateDeactivateStakeSignaturesRequest {
-    pub net: Network,
-    pub stake_account: String,     // Stake account pubkey
-    pub keys: Vec<String>,         // List of pubkeys
-    pub signatures: Vec<String>,   // Base58 encoded PartialSignatures
-    pub recent_block_hash: String, // Base58 encoded recent blockhash
+    pub net: Option<Network>,
+    pub stake_account: String,             // Stake account pubkey
+    pub keys: Vec<String>,                 // List of pubkeys
+    pub signatures: Vec<String>,           // Base58 encoded PartialSignatures
+    pub serialization_version: Option<u8>, // From the step two responses, checked when given
+    pub recent_block_hash: String,         // Base58 encoded recent blockhash
+    #[serde(flatten)]
+    #[oai(flatten)]
+    pub send_options: SendOptions,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Object)]
 pub struct AggregateDeactivateStakeSignaturesResponse {
     pub transaction_id: String,
+    pub slot: Option<u64>, // Slot the transaction landed in, null if the node hasn't indexed it yet
+    pub block_time: Option<i64>, // Unix timestamp of that slot
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Object)]
 pub struct AggregateWithdrawStakeSignaturesRequest {
-    pub net: Network,
-    pub stake_account: String,     // Stake account pubkey
-    pub destination: String,       // Destination pubkey
-    pub amount: u64,               // Amount to withdraw in lamports
-    pub keys: Vec<String>,         // List of pubkeys
-    pub signatures: Vec<String>,   // Base58 encoded PartialSignatures
+    pub net: Option<Network>,
+    pub stake_account: String, // Stake account pubkey
+    pub destination: String,   // Destination pubkey
+    #[serde(alias = "amount")]
+    pub amount_lamports: Option<u64>, // Either amount_lamports or amount_sol, "amount" is still accepted
+    pub amount_sol: Option<f64>,
+    pub keys: Vec<String>,                 // List of pubkeys
+    pub signatures: Vec<String>,           // Base58 encoded PartialSignatures
+    pub serialization_version: Option<u8>, // From the step two responses, checked when given
+    pub check_state: Option<bool>, // Default true, refuse up front while the stake is still active
     pub recent_block_hash: String, // Base58 encoded recent blockhash
+    #[serde(flatten)]
+    #[oai(flatten)]
+    pub send_options: SendOptions,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Object)]
 pub struct AggregateWithdrawStakeSignaturesResponse {
     pub transaction_id: String,
+    pub slot: Option<u64>, // Slot the transaction landed in, null if the node hasn't indexed it yet
+    pub block_time: Option<i64>, // Unix timestamp of that slot
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq, Enum)]
+#[serde(rename_all = "lowercase")]
+#[oai(rename_all = "lowercase")]
+pub enum ValidatorSort {
+    #[default]
+    Stake, // Activated stake, descending
+    Commission, // Ascending, the larger stake first on equal commission
+}
+
+#[derive(Debug, Serialize, Deserialize, Object)]
+pub struct ValidatorsRequest {
+    pub net: Option<Network>,
+    pub limit: Option<usize>, // Applied to current and delinquent lists separately
+    pub offset: Option<usize>, // Skipped after filtering and sorting, also per list
+    pub min_stake: Option<u64>, // Activated stake in lamports
+    pub max_commission: Option<u8>,
+    pub include_delinquent: Option<bool>, // Defaults to true
+    pub sort_by: Option<ValidatorSort>,   // Defaults to stake
+}
+
+#[derive(Debug, Serialize, Object)]
+pub struct ValidatorInfo {
+    pub vote_pubkey: String,
+    pub node_pubkey: String,
+    pub activated_stake: u64, // In lamports
+    pub commission: u8,       // Percentage
+    pub last_vote: u64,       // Slot
+}
+
+#[derive(Debug, Serialize, Object)]
+pub struct ValidatorsResponse {
+    pub current: Vec<ValidatorInfo>,    // Sorted by sort_by
+    pub delinquent: Vec<ValidatorInfo>, // Sorted by sort_by, empty unless include_delinquent
+    pub total_current: usize,           // Matching the filters, before limit and offset
+    pub total_delinquent: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize, Object)]
+pub struct AccountInfoRequest {
+    pub address: String,
+    pub net: Option<Network>,
+}
+
+#[derive(Debug, Serialize, Object)]
+pub struct AccountInfoResponse {
+    pub address: String,
+    pub exists: bool, // The other fields are zero/None when false
+    pub lamports: u64,
+    pub owner: Option<String>,
+    pub executable: bool,
+    pub rent_epoch: u64,
+    pub data_len: usize,
+    pub rent_exempt: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Enum)]
+#[serde(rename_all = "lowercase")]
+#[oai(rename_all = "lowercase")]
+pub enum RentAccountType {
+    Stake,
+    Token,
+    Mint,
+}
+
+#[derive(Debug, Serialize, Deserialize, Object)]
+pub struct RentExemptionRequest {
+    pub data_size: Option<usize>, // In bytes, set either this or account_type
+    pub account_type: Option<RentAccountType>,
+    pub net: Option<Network>,
+}
+
+#[derive(Debug, Serialize, Object)]
+pub struct RentExemptionResponse {
+    pub data_size: usize,
+    pub lamports: u64,
+    pub sol: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Object)]
+pub struct RentReserveRequest {
+    pub address: String,
+    pub net: Option<Network>,
+}
+
+#[derive(Debug, Serialize, Object)]
+pub struct RentReserveResponse {
+    pub address: String,
+    pub lamports: u64, // Rent-exempt minimum of an account without data, always leave this much
+    pub sol: f64,
+    pub balance_lamports: u64,
+    pub spendable_lamports: u64, // Balance above the minimum, before fees
+}
+
+#[derive(Debug, Serialize, Deserialize, Object)]
+pub struct SupplyRequest {
+    pub net: Option<Network>,
+}
+
+#[derive(Debug, Serialize, Object)]
+pub struct SupplyResponse {
+    pub total: u64, // In lamports
+    pub total_sol: f64,
+    pub circulating: u64,
+    pub circulating_sol: f64,
+    pub non_circulating: u64,
+    pub non_circulating_sol: f64,
+    pub non_circulating_accounts: usize, // Accounts the node counts as non circulating
+}
+
+#[derive(Debug, Serialize, Deserialize, Object)]
+pub struct EpochInfoRequest {
+    pub net: Option<Network>,
+}
+
+#[derive(Debug, Serialize, Object)]
+pub struct InflationRateInfo {
+    pub total: f64,
+    pub validator: f64,
+    pub foundation: f64,
+}
+
+#[derive(Debug, Serialize, Object)]
+pub struct EpochInfoResponse {
+    pub epoch: u64,
+    pub slot_index: u64, // Slot within the current epoch
+    pub slots_in_epoch: u64,
+    pub absolute_slot: u64,
+    pub block_height: u64,
+    pub epoch_progress: f64, // Percentage of the epoch that has passed
+    pub inflation: InflationRateInfo, // Annual rates for the current epoch
+}
+
+#[derive(Debug, Serialize, Deserialize, Object)]
+pub struct StakeInfoRequest {
+    pub stake_account: String,
+    pub net: Option<Network>,
+    pub reward_epochs: Option<u64>, // Number of past epochs to fetch inflation rewards for
+}
+
+#[derive(Debug, Serialize, Object)]
+pub struct StakeDelegationInfo {
+    pub voter: String,
+    pub stake: u64, // In lamports
+    pub activation_epoch: u64,
+    pub deactivation_epoch: Option<u64>, // None while the stake isn't deactivating
+}
+
+#[derive(Debug, Serialize, Object)]
+pub struct StakeRewardInfo {
+    pub epoch: u64,
+    pub amount: u64, // In lamports
+    pub post_balance: u64,
+    pub effective_slot: u64,
+    pub commission: Option<u8>,
+}
+
+#[derive(Debug, Serialize, Object)]
+pub struct StakeInfoResponse {
+    pub stake_account: String,
+    pub lamports: u64,
+    pub state: String, // "uninitialized", "initialized", "delegated" or "rewards_pool"
+    pub staker: Option<String>,
+    pub withdrawer: Option<String>,
+    pub rent_exempt_reserve: Option<u64>,
+    pub delegation: Option<StakeDelegationInfo>,
+    pub current_epoch: u64,
+    pub rewards: Vec<StakeRewardInfo>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Object)]
+pub struct ConfirmSubscribeRequest {
+    pub signature: String, // Transaction id returned by a send endpoint
+    pub net: Option<Network>,
+}
+
+#[derive(Debug, Serialize, Object)]
+pub struct ConfirmStatusUpdate {
+    pub signature: String,
+    pub status: String, // "received", "confirmed", "finalized", "failed" or "timeout"
+    pub slot: Option<u64>,
+    pub error: Option<String>, // Set when the transaction failed or timed out
+}
+
+#[derive(Debug, Serialize, Deserialize, Object)]
+pub struct ConfirmManyRequest {
+    pub signatures: Vec<String>, // Transaction ids returned by the send endpoints
+    pub net: Option<Network>,
+    pub confirm_timeout_secs: Option<u64>, // Stop waiting for the pending ones, defaults to 60
+}
+
+#[derive(Debug, Serialize, Deserialize, Object)]
+pub struct SignatureConfirmation {
+    pub signature: String,
+    pub status: String, // "confirmed", "failed", "pending" or "invalid"
+    pub slot: Option<u64>,
+    pub error: Option<String>, // Set when the transaction failed or the signature couldn't be parsed
+}
+
+#[derive(Debug, Serialize, Deserialize, Object)]
+pub struct ConfirmManyResponse {
+    pub statuses: Vec<SignatureConfirmation>, // Same order as the requested signatures
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::models::{Network, SplAuthorityType};
+
+    #[test]
+    fn test_network_names_ignore_case_and_accept_aliases() {
+        for (name, net) in [
+            ("devnet", Network::Devnet),
+            ("Devnet", Network::Devnet),
+            ("DEV", Network::Devnet),
+            ("mainnet-beta", Network::Mainnet),
+            ("Main", Network::Mainnet),
+            ("test", Network::Testnet),
+        ] {
+            assert_eq!(
+                serde_json::from_str::<Network>(&format!("\"{}\"", name)).unwrap(),
+                net
+            );
+        }
+        let err = serde_json::from_str::<Network>("\"localnet\"").unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("Unrecognized network: localnet")
+        );
+        // Still written in the canonical form
+        assert_eq!(
+            serde_json::to_string(&Network::Mainnet).unwrap(),
+            "\"mainnet\""
+        );
+    }
+
+    #[test]
+    fn test_authority_type_names_match_serde() {
+        for authority_type in [
+            SplAuthorityType::Owner,
+            SplAuthorityType::Close,
+            SplAuthorityType::Mint,
+            SplAuthorityType::Freeze,
+        ] {
+            assert_eq!(
+                serde_json::to_string(&authority_type).unwrap(),
+                format!("\"{}\"", authority_type.as_str())
+            );
+        }
+    }
 }
@@ -0,0 +1,90 @@
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{
+    account_utils::StateMut,
+    hash::Hash,
+    instruction::Instruction,
+    message::Message,
+    nonce::state::{Data as NonceData, State as NonceState, Versions},
+    pubkey::Pubkey,
+    system_instruction, system_program,
+    transaction::Transaction,
+};
+
+use crate::error::Error;
+
+/// Durable nonce to use in place of a recent blockhash.
+/// When `authority` is None the fee payer is assumed to be the nonce authority.
+#[derive(Debug, Clone, Copy)]
+pub struct NonceParams {
+    pub account: Pubkey,
+    pub authority: Option<Pubkey>,
+}
+
+impl NonceParams {
+    pub fn authority_or(&self, payer: &Pubkey) -> Pubkey {
+        self.authority.unwrap_or(*payer)
+    }
+}
+
+/// Build a message that starts with `advance_nonce_account` when a nonce is given
+pub fn new_message(
+    instructions: Vec<Instruction>,
+    payer: &Pubkey,
+    nonce: Option<&NonceParams>,
+) -> Message {
+    match nonce {
+        None => Message::new(&instructions, Some(payer)),
+        Some(nonce) => Message::new_with_nonce(
+            instructions,
+            Some(payer),
+            &nonce.account,
+            &nonce.authority_or(payer),
+        ),
+    }
+}
+
+pub fn create_nonce_account_transaction(
+    payer: &Pubkey,
+    nonce_account: &Pubkey,
+    authority: &Pubkey,
+    lamports: u64,
+) -> Transaction {
+    let instructions =
+        system_instruction::create_nonce_account(payer, nonce_account, authority, lamports);
+    let msg = Message::new(&instructions, Some(payer));
+    Transaction::new_unsigned(msg)
+}
+
+pub fn nonce_account_rent(rpc_client: &RpcClient) -> Result<u64, Error> {
+    rpc_client
+        .get_minimum_balance_for_rent_exemption(NonceState::size())
+        .map_err(|e| Error::InvalidNonceAccount(e.to_string()))
+}
+
+/// Fetch and decode an initialized nonce account
+pub fn get_nonce_data(rpc_client: &RpcClient, nonce_account: &Pubkey) -> Result<NonceData, Error> {
+    let account = rpc_client
+        .get_account(nonce_account)
+        .map_err(|e| Error::InvalidNonceAccount(format!("{}: {}", nonce_account, e)))?;
+    if account.owner != system_program::id() {
+        return Err(Error::InvalidNonceAccount(format!(
+            "{} is not owned by the system program",
+            nonce_account
+        )));
+    }
+    let versions: Versions = account.state().map_err(|_| {
+        Error::InvalidNonceAccount(format!("{} is not a nonce account", nonce_account))
+    })?;
+    match versions.state() {
+        NonceState::Initialized(data) => Ok(data.clone()),
+        NonceState::Uninitialized => Err(Error::InvalidNonceAccount(format!(
+            "{} is not initialized",
+            nonce_account
+        ))),
+    }
+}
+
+/// The stored nonce, used as the transaction's `recent_blockhash`
+pub fn get_nonce_blockhash(rpc_client: &RpcClient, nonce_account: &Pubkey) -> Result<Hash, Error> {
+    Ok(get_nonce_data(rpc_client, nonce_account)?.blockhash())
+}
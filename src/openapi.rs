@@ -0,0 +1,661 @@
+use poem_openapi::{
+    ApiResponse, OpenApi, OpenApiService, Tags,
+    param::{Header, Path, Query},
+    payload::{Json, PlainText},
+    types::{ToJSON, Type},
+};
+
+use crate::models::*;
+
+#[derive(Tags)]
+enum ApiTags {
+    /// Liveness and readiness probes
+    Health,
+    /// Single-key wallet operations
+    Wallet,
+    /// Multi-party aggregated signing
+    Tss,
+    /// Durable nonce accounts
+    Nonce,
+    /// SPL tokens
+    Spl,
+    /// Staking
+    Stake,
+}
+
+/// Every endpoint answers with the requested payload or an `ErrorResponse`,
+/// the status depends on the error code
+#[derive(ApiResponse)]
+enum ApiResult<T: ToJSON + Type> {
+    #[oai(status = 200)]
+    Ok(Json<T>),
+    /// Malformed input such as a bad key, hash, amount or JSON body
+    #[oai(status = 400)]
+    BadRequest(Json<ErrorResponse>),
+    /// API_KEY is set and the X-API-Key header is missing or wrong
+    #[oai(status = 401)]
+    Unauthorized(Json<ErrorResponse>),
+    /// SAFE_MODE is enabled and the request targets mainnet
+    #[oai(status = 403)]
+    Forbidden(Json<ErrorResponse>),
+    /// The account or mint doesn't exist
+    #[oai(status = 404)]
+    NotFound(Json<ErrorResponse>),
+    /// The account already exists, or the idempotency key is in use
+    #[oai(status = 409)]
+    Conflict(Json<ErrorResponse>),
+    /// The request body is larger than MAX_BODY_BYTES
+    #[oai(status = 413)]
+    PayloadTooLarge(Json<ErrorResponse>),
+    /// The transaction would fail, e.g. insufficient balance
+    #[oai(status = 422)]
+    UnprocessableEntity(Json<ErrorResponse>),
+    /// Either this server or the RPC node is rate limiting requests
+    #[oai(status = 429)]
+    TooManyRequests(Json<ErrorResponse>),
+    #[oai(status = 500)]
+    InternalServerError(Json<ErrorResponse>),
+    /// The RPC node returned an error
+    #[oai(status = 502)]
+    BadGateway(Json<ErrorResponse>),
+    /// The RPC node is unhealthy
+    #[oai(status = 503)]
+    ServiceUnavailable(Json<ErrorResponse>),
+    /// The RPC node or the confirmation timed out
+    #[oai(status = 504)]
+    GatewayTimeout(Json<ErrorResponse>),
+}
+
+/// Mirrors the routes registered in `main` so the spec is generated from the real
+/// request and response types. Only the spec and Swagger UI are mounted, the
+/// requests themselves are still served by the handlers in main.rs
+pub struct Api;
+
+fn documented_only<T>() -> T {
+    unreachable!("the OpenAPI operations are never mounted")
+}
+
+#[OpenApi]
+impl Api {
+    #[oai(path = "/health", method = "get", tag = "ApiTags::Health")]
+    async fn health(&self) -> ApiResult<HealthResponse> {
+        documented_only()
+    }
+
+    #[oai(path = "/api/version", method = "get", tag = "ApiTags::Health")]
+    async fn version(&self) -> ApiResult<VersionResponse> {
+        documented_only()
+    }
+
+    /// Prometheus text format: requests by path and status, errors by code and RPC latency
+    #[oai(path = "/metrics", method = "get", tag = "ApiTags::Health")]
+    async fn metrics(&self) -> PlainText<String> {
+        documented_only()
+    }
+
+    #[oai(path = "/ready", method = "get", tag = "ApiTags::Health")]
+    async fn ready(&self) -> ApiResult<ReadyResponse> {
+        documented_only()
+    }
+
+    #[oai(path = "/api/generate", method = "get", tag = "ApiTags::Wallet")]
+    async fn generate_keypair(
+        &self,
+        #[oai(name = "format")] _format: Query<Option<KeyFormat>>,
+    ) -> ApiResult<GenerateKeypairResponse> {
+        documented_only()
+    }
+
+    #[oai(
+        path = "/api/generate_vanity",
+        method = "post",
+        tag = "ApiTags::Wallet"
+    )]
+    async fn generate_vanity(
+        &self,
+        _req: Json<GenerateVanityRequest>,
+    ) -> ApiResult<GenerateVanityResponse> {
+        documented_only()
+    }
+
+    #[oai(path = "/api/balance", method = "post", tag = "ApiTags::Wallet")]
+    async fn balance(&self, _req: Json<BalanceRequest>) -> ApiResult<BalanceResponse> {
+        documented_only()
+    }
+
+    #[oai(path = "/api/balances", method = "post", tag = "ApiTags::Wallet")]
+    async fn balances(&self, _req: Json<BalancesRequest>) -> ApiResult<BalancesResponse> {
+        documented_only()
+    }
+
+    #[oai(path = "/api/confirm_many", method = "post", tag = "ApiTags::Wallet")]
+    async fn confirm_many(&self, _req: Json<ConfirmManyRequest>) -> ApiResult<ConfirmManyResponse> {
+        documented_only()
+    }
+
+    #[oai(path = "/api/airdrop", method = "post", tag = "ApiTags::Wallet")]
+    async fn airdrop(&self, _req: Json<AirdropRequest>) -> ApiResult<AirdropResponse> {
+        documented_only()
+    }
+
+    #[oai(path = "/api/send_single", method = "post", tag = "ApiTags::Wallet")]
+    async fn send_single(
+        &self,
+        _req: Json<SendSingleRequest>,
+        #[oai(name = "Idempotency-Key")] _idempotency_key: Header<Option<String>>,
+    ) -> ApiResult<SendSingleResponse> {
+        documented_only()
+    }
+
+    #[oai(
+        path = "/api/estimate_compute_units",
+        method = "post",
+        tag = "ApiTags::Wallet"
+    )]
+    async fn estimate_compute_units(
+        &self,
+        _req: Json<SendSingleRequest>,
+    ) -> ApiResult<EstimateComputeUnitsResponse> {
+        documented_only()
+    }
+
+    #[oai(
+        path = "/api/send_raw_instructions",
+        method = "post",
+        tag = "ApiTags::Wallet"
+    )]
+    async fn send_raw_instructions(
+        &self,
+        _req: Json<SendRawInstructionsRequest>,
+    ) -> ApiResult<SendRawInstructionsResponse> {
+        documented_only()
+    }
+
+    #[oai(
+        path = "/api/validate_address",
+        method = "post",
+        tag = "ApiTags::Wallet"
+    )]
+    async fn validate_address(
+        &self,
+        _req: Json<ValidateAddressRequest>,
+    ) -> ApiResult<ValidateAddressResponse> {
+        documented_only()
+    }
+
+    #[oai(path = "/api/keypair_pubkey", method = "post", tag = "ApiTags::Wallet")]
+    async fn keypair_pubkey(
+        &self,
+        _req: Json<KeypairPubkeyRequest>,
+    ) -> ApiResult<KeypairPubkeyResponse> {
+        documented_only()
+    }
+
+    #[oai(
+        path = "/api/keypair_from_mnemonic",
+        method = "post",
+        tag = "ApiTags::Wallet"
+    )]
+    async fn keypair_from_mnemonic(
+        &self,
+        _req: Json<KeypairFromMnemonicRequest>,
+    ) -> ApiResult<KeypairFromMnemonicResponse> {
+        documented_only()
+    }
+
+    #[oai(
+        path = "/api/decode_transaction",
+        method = "post",
+        tag = "ApiTags::Tss"
+    )]
+    async fn decode_transaction(
+        &self,
+        _req: Json<DecodeTransactionRequest>,
+    ) -> ApiResult<DecodeTransactionResponse> {
+        documented_only()
+    }
+
+    #[oai(
+        path = "/api/recent_block_hash",
+        method = "post",
+        tag = "ApiTags::Wallet"
+    )]
+    async fn recent_block_hash(
+        &self,
+        _req: Json<RecentBlockHashRequest>,
+    ) -> ApiResult<RecentBlockHashResponse> {
+        documented_only()
+    }
+
+    #[oai(path = "/api/aggregate_keys", method = "post", tag = "ApiTags::Tss")]
+    async fn aggregate_keys(
+        &self,
+        _req: Json<AggregateKeysRequest>,
+    ) -> ApiResult<AggregateKeysResponse> {
+        documented_only()
+    }
+
+    #[oai(path = "/api/agg_send_step_one", method = "post", tag = "ApiTags::Tss")]
+    async fn agg_send_step_one(
+        &self,
+        _req: Json<AggSendStepOneRequest>,
+    ) -> ApiResult<AggSendStepOneResponse> {
+        documented_only()
+    }
+
+    #[oai(path = "/api/agg_send_step_two", method = "post", tag = "ApiTags::Tss")]
+    async fn agg_send_step_two(
+        &self,
+        _req: Json<AggSendStepTwoRequest>,
+    ) -> ApiResult<AggSendStepTwoResponse> {
+        documented_only()
+    }
+
+    #[oai(
+        path = "/api/signing_package/create",
+        method = "post",
+        tag = "ApiTags::Tss"
+    )]
+    async fn signing_package_create(
+        &self,
+        _req: Json<SigningPackageCreateRequest>,
+    ) -> ApiResult<SigningPackageCreateResponse> {
+        documented_only()
+    }
+
+    #[oai(
+        path = "/api/signing_package/step_two",
+        method = "post",
+        tag = "ApiTags::Tss"
+    )]
+    async fn signing_package_step_two(
+        &self,
+        _req: Json<SigningPackageStepTwoRequest>,
+    ) -> ApiResult<AggSendStepTwoResponse> {
+        documented_only()
+    }
+
+    #[oai(
+        path = "/api/aggregate_signatures",
+        method = "post",
+        tag = "ApiTags::Tss"
+    )]
+    async fn aggregate_signatures(
+        &self,
+        _req: Json<AggregateSignaturesRequest>,
+    ) -> ApiResult<AggregateSignaturesResponse> {
+        documented_only()
+    }
+
+    #[oai(
+        path = "/api/verify_partial_signatures",
+        method = "post",
+        tag = "ApiTags::Tss"
+    )]
+    async fn verify_partial_signatures(
+        &self,
+        _req: Json<VerifyPartialSignaturesRequest>,
+    ) -> ApiResult<VerifyPartialSignaturesResponse> {
+        documented_only()
+    }
+
+    #[oai(path = "/api/verify_signature", method = "post", tag = "ApiTags::Tss")]
+    async fn verify_signature(
+        &self,
+        _req: Json<VerifySignatureRequest>,
+    ) -> ApiResult<VerifySignatureResponse> {
+        documented_only()
+    }
+
+    #[oai(
+        path = "/api/agg_session/create",
+        method = "post",
+        tag = "ApiTags::Tss"
+    )]
+    async fn agg_session_create(
+        &self,
+        _req: Json<AggSessionCreateRequest>,
+    ) -> ApiResult<AggSessionCreateResponse> {
+        documented_only()
+    }
+
+    /// Aggregates and broadcasts as soon as the last key submits, unless `auto_broadcast` is false
+    #[oai(
+        path = "/api/agg_session/:id/submit",
+        method = "post",
+        tag = "ApiTags::Tss"
+    )]
+    async fn agg_session_submit(
+        &self,
+        #[oai(name = "id")] _id: Path<String>,
+        _req: Json<AggSessionSubmitRequest>,
+    ) -> ApiResult<AggSessionStatusResponse> {
+        documented_only()
+    }
+
+    #[oai(
+        path = "/api/agg_session/:id/status",
+        method = "get",
+        tag = "ApiTags::Tss"
+    )]
+    async fn agg_session_status(
+        &self,
+        #[oai(name = "id")] _id: Path<String>,
+    ) -> ApiResult<AggSessionStatusResponse> {
+        documented_only()
+    }
+
+    #[oai(
+        path = "/api/create_nonce_account",
+        method = "post",
+        tag = "ApiTags::Nonce"
+    )]
+    async fn create_nonce_account(
+        &self,
+        _req: Json<CreateNonceAccountRequest>,
+    ) -> ApiResult<CreateNonceAccountResponse> {
+        documented_only()
+    }
+
+    #[oai(path = "/api/nonce_info", method = "post", tag = "ApiTags::Nonce")]
+    async fn nonce_info(&self, _req: Json<NonceInfoRequest>) -> ApiResult<NonceInfoResponse> {
+        documented_only()
+    }
+
+    #[oai(path = "/api/spl_token_balance", method = "post", tag = "ApiTags::Spl")]
+    async fn spl_token_balance(
+        &self,
+        _req: Json<SplTokenBalanceRequest>,
+    ) -> ApiResult<SplTokenBalanceResponse> {
+        documented_only()
+    }
+
+    #[oai(path = "/api/token_metadata", method = "post", tag = "ApiTags::Spl")]
+    async fn token_metadata(
+        &self,
+        _req: Json<TokenMetadataRequest>,
+    ) -> ApiResult<TokenMetadataResponse> {
+        documented_only()
+    }
+
+    #[oai(path = "/api/solana_pay_url", method = "post", tag = "ApiTags::Wallet")]
+    async fn solana_pay_url(
+        &self,
+        _req: Json<SolanaPayUrlRequest>,
+    ) -> ApiResult<SolanaPayUrlResponse> {
+        documented_only()
+    }
+
+    #[oai(path = "/api/derive_ata", method = "post", tag = "ApiTags::Spl")]
+    async fn derive_ata(&self, _req: Json<DeriveAtaRequest>) -> ApiResult<DeriveAtaResponse> {
+        documented_only()
+    }
+
+    #[oai(path = "/api/spl_all_balances", method = "post", tag = "ApiTags::Spl")]
+    async fn spl_all_balances(
+        &self,
+        _req: Json<SplAllBalancesRequest>,
+    ) -> ApiResult<SplAllBalancesResponse> {
+        documented_only()
+    }
+
+    #[oai(path = "/api/spl_send_single", method = "post", tag = "ApiTags::Spl")]
+    async fn spl_send_single(
+        &self,
+        _req: Json<SplSendSingleRequest>,
+    ) -> ApiResult<SplSendSingleResponse> {
+        documented_only()
+    }
+
+    #[oai(
+        path = "/api/spl_create_account",
+        method = "post",
+        tag = "ApiTags::Spl"
+    )]
+    async fn spl_create_account(
+        &self,
+        _req: Json<SplCreateAccountRequest>,
+    ) -> ApiResult<SplCreateAccountResponse> {
+        documented_only()
+    }
+
+    #[oai(path = "/api/spl_approve", method = "post", tag = "ApiTags::Spl")]
+    async fn spl_approve(&self, _req: Json<SplApproveRequest>) -> ApiResult<SplApproveResponse> {
+        documented_only()
+    }
+
+    #[oai(path = "/api/spl_revoke", method = "post", tag = "ApiTags::Spl")]
+    async fn spl_revoke(&self, _req: Json<SplRevokeRequest>) -> ApiResult<SplRevokeResponse> {
+        documented_only()
+    }
+
+    #[oai(path = "/api/spl_close_account", method = "post", tag = "ApiTags::Spl")]
+    async fn spl_close_account(
+        &self,
+        _req: Json<SplCloseAccountRequest>,
+    ) -> ApiResult<SplCloseAccountResponse> {
+        documented_only()
+    }
+
+    #[oai(path = "/api/spl_set_authority", method = "post", tag = "ApiTags::Spl")]
+    async fn spl_set_authority(
+        &self,
+        _req: Json<SplSetAuthorityRequest>,
+    ) -> ApiResult<SplSetAuthorityResponse> {
+        documented_only()
+    }
+
+    #[oai(
+        path = "/api/spl_agg_send_step_two",
+        method = "post",
+        tag = "ApiTags::Spl"
+    )]
+    async fn spl_agg_send_step_two(
+        &self,
+        _req: Json<SplAggSendStepTwoRequest>,
+    ) -> ApiResult<SplAggSendStepTwoResponse> {
+        documented_only()
+    }
+
+    #[oai(
+        path = "/api/spl_aggregate_signatures",
+        method = "post",
+        tag = "ApiTags::Spl"
+    )]
+    async fn spl_aggregate_signatures(
+        &self,
+        _req: Json<SplAggregateSignaturesRequest>,
+    ) -> ApiResult<SplAggregateSignaturesResponse> {
+        documented_only()
+    }
+
+    #[oai(path = "/api/stake", method = "post", tag = "ApiTags::Stake")]
+    async fn stake_account(
+        &self,
+        _req: Json<StakeAccountRequest>,
+    ) -> ApiResult<StakeAccountResponse> {
+        documented_only()
+    }
+
+    #[oai(
+        path = "/api/derive_stake_account",
+        method = "post",
+        tag = "ApiTags::Stake"
+    )]
+    async fn derive_stake_account(
+        &self,
+        _req: Json<DeriveStakeAccountRequest>,
+    ) -> ApiResult<DeriveStakeAccountResponse> {
+        documented_only()
+    }
+
+    #[oai(
+        path = "/api/deactivate_stake",
+        method = "post",
+        tag = "ApiTags::Stake"
+    )]
+    async fn deactivate_stake(
+        &self,
+        _req: Json<DeactivateStakeRequest>,
+    ) -> ApiResult<DeactivateStakeResponse> {
+        documented_only()
+    }
+
+    #[oai(path = "/api/withdraw_stake", method = "post", tag = "ApiTags::Stake")]
+    async fn withdraw_stake(
+        &self,
+        _req: Json<WithdrawStakeRequest>,
+    ) -> ApiResult<WithdrawStakeResponse> {
+        documented_only()
+    }
+
+    #[oai(
+        path = "/api/agg_stake_step_one",
+        method = "post",
+        tag = "ApiTags::Stake"
+    )]
+    async fn agg_stake_step_one(
+        &self,
+        _req: Json<AggStakeStepOneRequest>,
+    ) -> ApiResult<AggStakeStepOneResponse> {
+        documented_only()
+    }
+
+    #[oai(
+        path = "/api/agg_stake_step_two",
+        method = "post",
+        tag = "ApiTags::Stake"
+    )]
+    async fn agg_stake_step_two(
+        &self,
+        _req: Json<AggStakeStepTwoRequest>,
+    ) -> ApiResult<AggStakeStepTwoResponse> {
+        documented_only()
+    }
+
+    #[oai(
+        path = "/api/agg_deactivate_stake_step_one",
+        method = "post",
+        tag = "ApiTags::Stake"
+    )]
+    async fn agg_deactivate_stake_step_one(
+        &self,
+        _req: Json<AggDeactivateStakeStepOneRequest>,
+    ) -> ApiResult<AggDeactivateStakeStepOneResponse> {
+        documented_only()
+    }
+
+    #[oai(
+        path = "/api/agg_deactivate_stake_step_two",
+        method = "post",
+        tag = "ApiTags::Stake"
+    )]
+    async fn agg_deactivate_stake_step_two(
+        &self,
+        _req: Json<AggDeactivateStakeStepTwoRequest>,
+    ) -> ApiResult<AggDeactivateStakeStepTwoResponse> {
+        documented_only()
+    }
+
+    #[oai(
+        path = "/api/agg_withdraw_stake_step_one",
+        method = "post",
+        tag = "ApiTags::Stake"
+    )]
+    async fn agg_withdraw_stake_step_one(
+        &self,
+        _req: Json<AggWithdrawStakeStepOneRequest>,
+    ) -> ApiResult<AggWithdrawStakeStepOneResponse> {
+        documented_only()
+    }
+
+    #[oai(
+        path = "/api/agg_withdraw_stake_step_two",
+        method = "post",
+        tag = "ApiTags::Stake"
+    )]
+    async fn agg_withdraw_stake_step_two(
+        &self,
+        _req: Json<AggWithdrawStakeStepTwoRequest>,
+    ) -> ApiResult<AggWithdrawStakeStepTwoResponse> {
+        documented_only()
+    }
+
+    #[oai(
+        path = "/api/aggregate_stake_signatures",
+        method = "post",
+        tag = "ApiTags::Stake"
+    )]
+    async fn aggregate_stake_signatures(
+        &self,
+        _req: Json<AggregateStakeSignaturesRequest>,
+    ) -> ApiResult<AggregateStakeSignaturesResponse> {
+        documented_only()
+    }
+
+    #[oai(
+        path = "/api/aggregate_deactivate_stake_signatures",
+        method = "post",
+        tag = "ApiTags::Stake"
+    )]
+    async fn aggregate_deactivate_stake_signatures(
+        &self,
+        _req: Json<AggregateDeactivateStakeSignaturesRequest>,
+    ) -> ApiResult<AggregateDeactivateStakeSignaturesResponse> {
+        documented_only()
+    }
+
+    #[oai(
+        path = "/api/aggregate_withdraw_stake_signatures",
+        method = "post",
+        tag = "ApiTags::Stake"
+    )]
+    async fn aggregate_withdraw_stake_signatures(
+        &self,
+        _req: Json<AggregateWithdrawStakeSignaturesRequest>,
+    ) -> ApiResult<AggregateWithdrawStakeSignaturesResponse> {
+        documented_only()
+    }
+
+    #[oai(path = "/api/validators", method = "post", tag = "ApiTags::Stake")]
+    async fn validators(&self, _req: Json<ValidatorsRequest>) -> ApiResult<ValidatorsResponse> {
+        documented_only()
+    }
+
+    #[oai(path = "/api/account_info", method = "post", tag = "ApiTags::Wallet")]
+    async fn account_info(&self, _req: Json<AccountInfoRequest>) -> ApiResult<AccountInfoResponse> {
+        documented_only()
+    }
+
+    #[oai(path = "/api/rent_exemption", method = "post", tag = "ApiTags::Wallet")]
+    async fn rent_exemption(
+        &self,
+        _req: Json<RentExemptionRequest>,
+    ) -> ApiResult<RentExemptionResponse> {
+        documented_only()
+    }
+
+    #[oai(path = "/api/rent_reserve", method = "post", tag = "ApiTags::Wallet")]
+    async fn rent_reserve(&self, _req: Json<RentReserveRequest>) -> ApiResult<RentReserveResponse> {
+        documented_only()
+    }
+
+    #[oai(path = "/api/epoch_info", method = "post", tag = "ApiTags::Stake")]
+    async fn epoch_info(&self, _req: Json<EpochInfoRequest>) -> ApiResult<EpochInfoResponse> {
+        documented_only()
+    }
+
+    #[oai(path = "/api/supply", method = "post", tag = "ApiTags::Stake")]
+    async fn supply(&self, _req: Json<SupplyRequest>) -> ApiResult<SupplyResponse> {
+        documented_only()
+    }
+
+    #[oai(path = "/api/stake_info", method = "post", tag = "ApiTags::Stake")]
+    async fn stake_info(&self, _req: Json<StakeInfoRequest>) -> ApiResult<StakeInfoResponse> {
+        documented_only()
+    }
+}
+
+pub fn api_service() -> OpenApiService<Api, ()> {
+    OpenApiService::new(Api, "Solana TSS API", env!("CARGO_PKG_VERSION"))
+}
@@ -0,0 +1,502 @@
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use solana_client::{
+    client_error::{ClientError, ClientErrorKind},
+    rpc_client::RpcClient,
+    rpc_config::RpcSimulateTransactionConfig,
+};
+use solana_sdk::{
+    hash::Hash,
+    message::Message,
+    pubkey::Pubkey,
+    signature::Signature,
+    transaction::{Transaction, TransactionError},
+};
+
+use crate::{
+    error::{Error, is_rate_limited, is_timeout},
+    models::SendOptions,
+};
+
+/// Used when a request doesn't set `max_retries`
+pub const DEFAULT_MAX_RETRIES: u32 = 3;
+
+/// Used when a request doesn't set `confirm_timeout_secs`
+pub const DEFAULT_CONFIRM_TIMEOUT: Duration = Duration::from_secs(60);
+
+const CONFIRM_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+// getSignatureStatuses accepts at most this many signatures per call
+const MAX_SIGNATURE_STATUSES: usize = 256;
+
+const BASE_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(8);
+
+/// Errors worth retrying: throttling, timeouts, dropped connections and 5xx from the node.
+/// Anything the node actually evaluated (e.g. a failed preflight) is returned as is
+pub fn is_transient(e: &ClientError) -> bool {
+    if is_rate_limited(e) || is_timeout(e) {
+        return true;
+    }
+    match e.kind() {
+        ClientErrorKind::Io(_) => true,
+        ClientErrorKind::Reqwest(err) => {
+            err.is_connect() || err.status().is_some_and(|status| status.is_server_error())
+        }
+        _ => false,
+    }
+}
+
+/// 500ms, 1s, 2s, ... capped at 8s
+fn backoff(attempt: u32) -> Duration {
+    BASE_BACKOFF
+        .saturating_mul(2u32.saturating_pow(attempt))
+        .min(MAX_BACKOFF)
+}
+
+/// Call `f` until it succeeds, fails with a non transient error or `max_retries` is exhausted
+pub fn with_retry<T>(
+    max_retries: u32,
+    f: impl FnMut() -> Result<T, ClientError>,
+) -> Result<T, ClientError> {
+    retry_if(max_retries, is_transient, f)
+}
+
+/// Like `with_retry`, but only errors matching `retryable` are retried
+pub fn retry_if<T>(
+    max_retries: u32,
+    retryable: impl Fn(&ClientError) -> bool,
+    mut f: impl FnMut() -> Result<T, ClientError>,
+) -> Result<T, ClientError> {
+    let mut attempt = 0;
+    loop {
+        match f() {
+            Err(e) if attempt < max_retries && retryable(&e) => {
+                let delay = backoff(attempt);
+                tracing::warn!(attempt, ?delay, "Transient RPC error, retrying: {}", e);
+                thread::sleep(delay);
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Faucets throttle hard, only their rate limit is worth waiting out. Anything else,
+/// e.g. a faucet that is dry, fails right away
+pub fn request_airdrop(
+    rpc_client: &RpcClient,
+    to: &Pubkey,
+    lamports: u64,
+    max_retries: u32,
+) -> Result<Signature, Error> {
+    retry_if(max_retries, is_rate_limited, || {
+        rpc_client.request_airdrop(to, lamports)
+    })
+    .map_err(|e| {
+        if is_rate_limited(&e) {
+            Error::AirdropRateLimited(e)
+        } else {
+            Error::AirdropFailed(e)
+        }
+    })
+}
+
+/// Splits `lamports` into airdrops of at most `cap` each
+pub fn split_airdrop(lamports: u64, cap: u64) -> Vec<u64> {
+    let mut amounts = vec![cap; (lamports / cap) as usize];
+    if lamports % cap > 0 || lamports == 0 {
+        amounts.push(lamports % cap);
+    }
+    amounts
+}
+
+pub fn get_latest_blockhash(rpc_client: &RpcClient, max_retries: u32) -> Result<Hash, Error> {
+    with_retry(max_retries, || rpc_client.get_latest_blockhash()).map_err(Error::RecentHashFailed)
+}
+
+/// The blockhash along with the last block height at which it is still accepted
+pub fn get_latest_blockhash_with_expiry(
+    rpc_client: &RpcClient,
+    max_retries: u32,
+) -> Result<(Hash, u64), Error> {
+    with_retry(max_retries, || {
+        rpc_client.get_latest_blockhash_with_commitment(rpc_client.commitment())
+    })
+    .map_err(Error::RecentHashFailed)
+}
+
+/// Fails with `InvalidBlockHash` once `hash` is too old to land a transaction
+pub fn check_blockhash_valid(rpc_client: &RpcClient, hash: &Hash) -> Result<(), Error> {
+    let valid = rpc_client
+        .is_blockhash_valid(hash, rpc_client.commitment())
+        .map_err(Error::RecentHashFailed)?;
+    if !valid {
+        return Err(Error::InvalidBlockHash(format!(
+            "{} has expired, fetch a new one",
+            hash
+        )));
+    }
+    Ok(())
+}
+
+pub fn get_block_height(rpc_client: &RpcClient, max_retries: u32) -> Result<u64, Error> {
+    with_retry(max_retries, || rpc_client.get_block_height()).map_err(Error::RecentHashFailed)
+}
+
+/// Whether the node still accepts transactions signed over `hash`
+pub fn is_blockhash_valid(
+    rpc_client: &RpcClient,
+    hash: &Hash,
+    max_retries: u32,
+) -> Result<bool, Error> {
+    with_retry(max_retries, || {
+        rpc_client.is_blockhash_valid(hash, rpc_client.commitment())
+    })
+    .map_err(Error::RecentHashFailed)
+}
+
+/// Base fee of `message` at its blockhash, priority fees aren't included
+pub fn get_fee_for_message(
+    rpc_client: &RpcClient,
+    message: &Message,
+    max_retries: u32,
+) -> Result<u64, Error> {
+    with_retry(max_retries, || rpc_client.get_fee_for_message(message))
+        .map_err(Error::FeeLookupFailed)
+}
+
+/// Logs kept in a failed simulation's error, the end is where the failure is
+const SIMULATION_LOG_LINES: usize = 5;
+
+/// Compute units `tx` consumes when simulated against the current state. The blockhash is
+/// replaced and signatures aren't verified, so the transaction can be left unsigned
+pub fn simulate_compute_units(rpc_client: &RpcClient, tx: &Transaction) -> Result<u64, Error> {
+    let config = RpcSimulateTransactionConfig {
+        sig_verify: false,
+        replace_recent_blockhash: true,
+        commitment: Some(rpc_client.commitment()),
+        ..RpcSimulateTransactionConfig::default()
+    };
+    let result = rpc_client
+        .simulate_transaction_with_config(tx, config)
+        .map_err(Error::SimulationFailed)?
+        .value;
+    if let Some(err) = result.err {
+        let logs = result.logs.unwrap_or_default();
+        let tail = &logs[logs.len().saturating_sub(SIMULATION_LOG_LINES)..];
+        return Err(Error::TransactionWouldFail(format!(
+            "{}, logs: {}",
+            err,
+            tail.join(" | ")
+        )));
+    }
+    result.units_consumed.ok_or_else(|| {
+        Error::TransactionWouldFail("the node did not report units_consumed".to_string())
+    })
+}
+
+/// Resending the same signed transaction is safe, it can only land once
+#[tracing::instrument(skip_all)]
+pub fn send_transaction(
+    rpc_client: &RpcClient,
+    tx: &Transaction,
+    options: &SendOptions,
+) -> Result<Signature, Error> {
+    let config = options.rpc_send_config(rpc_client.commitment().commitment);
+    with_retry(options.max_retries(), || {
+        rpc_client.send_transaction_with_config(tx, config)
+    })
+    .map_err(|e| {
+        if let Some(tx_err) = e.get_transaction_error() {
+            tracing::warn!("Transaction error details: {:?}", tx_err);
+        }
+        if is_blockhash_not_found(&e) {
+            return Error::BlockhashExpired(tx.message.recent_blockhash.to_string());
+        }
+        Error::SendTransactionFailed(e)
+    })
+}
+
+/// A send that failed this way may still have reached the node, e.g. the response timed out.
+/// Only a failure to connect or an answer from the node proves it was not accepted
+pub fn send_outcome_unknown(e: &Error) -> bool {
+    match e {
+        Error::SendTransactionFailed(e) => match e.kind() {
+            ClientErrorKind::Io(_) => true,
+            ClientErrorKind::Reqwest(err) => !err.is_connect() && err.status().is_none(),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// The node no longer knows the blockhash the transaction was signed with. Preflight
+/// reports it as a transaction error, with preflight skipped it only shows in the message
+fn is_blockhash_not_found(e: &ClientError) -> bool {
+    matches!(
+        e.get_transaction_error(),
+        Some(TransactionError::BlockhashNotFound)
+    ) || e.to_string().to_lowercase().contains("blockhash not found")
+}
+
+/// Poll the signature status until it reaches the client's commitment. Gives up with
+/// `ConfirmationTimeout` after `timeout`, so the caller can check on the signature later
+#[tracing::instrument(skip_all, fields(signature = %sig))]
+pub fn confirm_transaction(
+    rpc_client: &RpcClient,
+    sig: &Signature,
+    recent_hash: &Hash,
+    timeout: Duration,
+) -> Result<(), Error> {
+    let start = Instant::now();
+    loop {
+        match rpc_client.get_signature_status_with_commitment(sig, rpc_client.commitment()) {
+            Ok(Some(Ok(()))) => return Ok(()),
+            Ok(Some(Err(e))) => return Err(Error::ConfirmingTransactionFailed(e.into())),
+            Ok(None) => {
+                // Once the blockhash expires an unseen transaction can never land
+                if let Ok(false) =
+                    rpc_client.is_blockhash_valid(recent_hash, rpc_client.commitment())
+                {
+                    return Err(Error::ConfirmingTransactionFailed(
+                        ClientErrorKind::Custom(format!(
+                            "blockhash {} expired before the transaction was confirmed",
+                            recent_hash
+                        ))
+                        .into(),
+                    ));
+                }
+            }
+            Err(e) if is_transient(&e) => {
+                tracing::warn!("Transient RPC error while confirming: {}", e);
+            }
+            Err(e) => return Err(Error::ConfirmingTransactionFailed(e)),
+        }
+
+        if start.elapsed() >= timeout {
+            return Err(Error::ConfirmationTimeout(sig.to_string()));
+        }
+        thread::sleep(CONFIRM_POLL_INTERVAL);
+    }
+}
+
+/// Where one of the signatures passed to `confirm_signatures` ended up
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Confirmation {
+    Confirmed {
+        slot: u64,
+    },
+    Failed {
+        slot: u64,
+        error: String,
+    },
+    /// Not seen at the client's commitment before the timeout
+    Pending,
+}
+
+/// Confirms `sigs` together, each round polls the ones still pending with one
+/// getSignatureStatuses call per 256 signatures until none is left or `timeout` passes
+pub fn confirm_signatures(
+    rpc_client: &RpcClient,
+    sigs: &[Signature],
+    timeout: Duration,
+) -> Result<Vec<Confirmation>, Error> {
+    let start = Instant::now();
+    let mut confirmations = vec![Confirmation::Pending; sigs.len()];
+    loop {
+        let pending: Vec<usize> = (0..sigs.len())
+            .filter(|i| confirmations[*i] == Confirmation::Pending)
+            .collect();
+        for chunk in pending.chunks(MAX_SIGNATURE_STATUSES) {
+            let chunk_sigs: Vec<Signature> = chunk.iter().map(|i| sigs[*i]).collect();
+            let statuses = match rpc_client.get_signature_statuses(&chunk_sigs) {
+                Ok(response) => response.value,
+                Err(e) if is_transient(&e) => {
+                    tracing::warn!("Transient RPC error while confirming: {}", e);
+                    continue;
+                }
+                Err(e) => return Err(Error::ConfirmingTransactionFailed(e)),
+            };
+            for (i, status) in chunk.iter().zip(statuses) {
+                let Some(status) = status else { continue };
+                if !status.satisfies_commitment(rpc_client.commitment()) {
+                    continue;
+                }
+                confirmations[*i] = match status.err {
+                    Some(e) => Confirmation::Failed {
+                        slot: status.slot,
+                        error: e.to_string(),
+                    },
+                    None => Confirmation::Confirmed { slot: status.slot },
+                };
+            }
+        }
+
+        if !confirmations.contains(&Confirmation::Pending) || start.elapsed() >= timeout {
+            return Ok(confirmations);
+        }
+        thread::sleep(CONFIRM_POLL_INTERVAL);
+    }
+}
+
+/// Slot and block time of a confirmed transaction. Either can still be missing right after
+/// confirmation, that is reported as None rather than failing the request that sent it
+pub fn get_landed_slot(rpc_client: &RpcClient, sig: &Signature) -> (Option<u64>, Option<i64>) {
+    let slot = match rpc_client.get_signature_statuses(&[*sig]) {
+        Ok(response) => response
+            .value
+            .into_iter()
+            .flatten()
+            .next()
+            .map(|status| status.slot),
+        Err(e) => {
+            tracing::warn!("Failed fetching the slot of {}: {}", sig, e);
+            None
+        }
+    };
+    let block_time = slot.and_then(|slot| rpc_client.get_block_time(slot).ok());
+    (slot, block_time)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, collections::HashMap, io, time::Duration};
+
+    use serde_json::json;
+    use solana_client::{
+        client_error::{ClientError, ClientErrorKind},
+        rpc_client::RpcClient,
+        rpc_request::RpcRequest,
+    };
+    use solana_sdk::{signature::Signature, transaction::TransactionError};
+
+    use crate::rpc_utils::{
+        Confirmation, backoff, confirm_signatures, is_blockhash_not_found, is_transient, retry_if,
+        split_airdrop, with_retry,
+    };
+
+    fn io_error() -> ClientError {
+        ClientErrorKind::Io(io::Error::new(io::ErrorKind::ConnectionReset, "reset")).into()
+    }
+
+    #[test]
+    fn test_backoff_doubles_and_caps() {
+        assert_eq!(backoff(0), Duration::from_millis(500));
+        assert_eq!(backoff(1), Duration::from_secs(1));
+        assert_eq!(backoff(3), Duration::from_secs(4));
+        assert_eq!(backoff(10), Duration::from_secs(8));
+    }
+
+    #[test]
+    fn test_only_transient_errors_are_retried() {
+        assert!(is_transient(&io_error()));
+        let logic_error: ClientError = ClientErrorKind::Custom("insufficient funds".into()).into();
+        assert!(!is_transient(&logic_error));
+
+        let calls = Cell::new(0);
+        let result: Result<(), _> = with_retry(3, || {
+            calls.set(calls.get() + 1);
+            Err(ClientErrorKind::Custom("insufficient funds".into()).into())
+        });
+        assert!(result.is_err());
+        assert_eq!(calls.get(), 1);
+    }
+
+    #[test]
+    fn test_retries_until_success() {
+        let calls = Cell::new(0);
+        let result = with_retry(3, || {
+            calls.set(calls.get() + 1);
+            if calls.get() < 2 {
+                Err(io_error())
+            } else {
+                Ok(42)
+            }
+        });
+        assert_eq!(result.unwrap(), 42);
+        assert_eq!(calls.get(), 2);
+    }
+
+    #[test]
+    fn test_confirm_signatures_reports_each_signature() {
+        let status = |err: serde_json::Value, confirmation_status: &str| {
+            json!({
+                "slot": 7,
+                "confirmations": null,
+                "status": if err.is_null() { json!({"Ok": null}) } else { json!({"Err": err}) },
+                "err": err,
+                "confirmationStatus": confirmation_status,
+            })
+        };
+        let mut mocks = HashMap::new();
+        mocks.insert(
+            RpcRequest::GetSignatureStatuses,
+            json!({
+                "context": {"slot": 8},
+                "value": [
+                    status(json!(null), "finalized"),
+                    status(json!("AccountInUse"), "finalized"),
+                    status(json!(null), "processed"),
+                    null,
+                ],
+            }),
+        );
+        let rpc_client = RpcClient::new_mock_with_mocks("succeeds".to_string(), mocks);
+        let sigs = vec![Signature::new_unique(); 4];
+
+        let confirmations = confirm_signatures(&rpc_client, &sigs, Duration::ZERO).unwrap();
+        assert_eq!(
+            confirmations,
+            vec![
+                Confirmation::Confirmed { slot: 7 },
+                Confirmation::Failed {
+                    slot: 7,
+                    error: "Account in use".to_string(),
+                },
+                // Not finalized yet, the mock client waits for finalized
+                Confirmation::Pending,
+                Confirmation::Pending,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_retry_if_only_retries_matching_errors() {
+        let calls = Cell::new(0);
+        let result: Result<(), _> = retry_if(
+            3,
+            |e| e.to_string().contains("429"),
+            || {
+                calls.set(calls.get() + 1);
+                Err(io_error())
+            },
+        );
+        assert!(result.is_err());
+        assert_eq!(calls.get(), 1);
+    }
+
+    #[test]
+    fn test_split_airdrop() {
+        assert_eq!(split_airdrop(5, 2), vec![2, 2, 1]);
+        assert_eq!(split_airdrop(4, 2), vec![2, 2]);
+        assert_eq!(split_airdrop(1, 2), vec![1]);
+        assert_eq!(split_airdrop(0, 2), vec![0]);
+    }
+
+    #[test]
+    fn test_blockhash_not_found_is_detected() {
+        let preflight: ClientError =
+            ClientErrorKind::TransactionError(TransactionError::BlockhashNotFound).into();
+        assert!(is_blockhash_not_found(&preflight));
+        let skipped_preflight: ClientError =
+            ClientErrorKind::Custom("Transaction failed: Blockhash not found".into()).into();
+        assert!(is_blockhash_not_found(&skipped_preflight));
+        let other: ClientError =
+            ClientErrorKind::TransactionError(TransactionError::AccountNotFound).into();
+        assert!(!is_blockhash_not_found(&other));
+    }
+}
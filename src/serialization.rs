@@ -1,8 +1,16 @@
-use std::fmt::{Display, Formatter};
+use std::{
+    fmt::{Display, Formatter},
+    io,
+};
 
+use base64::{Engine, prelude::BASE64_STANDARD};
+use bincode::Options;
 use curv::elliptic::curves::{DeserializationError, Point, PointFromBytesError, Scalar};
 use multi_party_eddsa::protocols::musig2::{PrivatePartialNonces, PublicPartialNonces};
-use solana_sdk::signature::Signature;
+use solana_sdk::{
+    hash::Hash, message::Message, packet::PACKET_DATA_SIZE, sanitize::Sanitize,
+    signature::Signature, transaction::Transaction,
+};
 use spl_memo::solana_program::pubkey::Pubkey;
 
 #[derive(Debug)]
@@ -12,10 +20,18 @@ pub enum Error {
     InvalidPoint(PointFromBytesError),
     InvalidScalar(DeserializationError),
     WrongTag { expected: Tag, found: Tag },
+    BadMagic,
+    UnsupportedVersion { found: u8, supported: u8 },
+    InvalidEncoding(String),
+    BadBase64(base64::DecodeError),
+    InvalidTransaction(String),
 }
 
 // TODO: Also add a magic tag that is equal to all messages.
 
+/// Version of the message encodings below, bump it whenever one of their layouts changes
+pub const SERIALIZATION_VERSION: u8 = 1;
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Tag {
     AggMessage1 = 0,
@@ -69,6 +85,17 @@ impl Display for Error {
                     expected, found
                 )
             }
+            Self::BadMagic => f.write_str("Not a signing package"),
+            Self::UnsupportedVersion { found, supported } => {
+                write!(
+                    f,
+                    "Unsupported signing package version: {}, supported: {}",
+                    found, supported
+                )
+            }
+            Self::InvalidEncoding(e) => write!(f, "Invalid signing package: {}", e),
+            Self::BadBase64(e) => write!(f, "Invalid base64: {}", e),
+            Self::InvalidTransaction(e) => write!(f, "Invalid transaction: {}", e),
         }
     }
 }
@@ -89,7 +116,7 @@ impl From<DeserializationError> for Error {
 pub trait FieldError<T> {
     fn with_field(self, field_name: &'static str) -> Result<T, crate::Error>;
 }
-impl<T: Serialize> FieldError<T> for Result<T, Error> {
+impl<T> FieldError<T> for Result<T, Error> {
     fn with_field(self, field_name: &'static str) -> Result<T, crate::Error> {
         self.map_err(|error| crate::Error::DeserializationFailed { error, field_name })
     }
@@ -241,15 +268,136 @@ impl Serialize for SecretAggStepOne {
     }
 }
 
+/// Marks a signing package, so any other message or file is rejected before decoding
+pub const SIGNING_PACKAGE_MAGIC: [u8; 4] = *b"TSSP";
+/// Bumped whenever the fields of `SigningPackage` change
+pub const SIGNING_PACKAGE_VERSION: u8 = 1;
+const SIGNING_PACKAGE_HEADER: usize = SIGNING_PACKAGE_MAGIC.len() + 1;
+
+/// Everything a signer needs for step two apart from its own key and secret state,
+/// so it can be carried to an offline machine as a single string
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct SigningPackage {
+    pub amount: u64,
+    pub to: Pubkey,
+    pub memo: Option<String>,
+    pub tip: Option<(Pubkey, u64)>,
+    pub recent_block_hash: Hash,
+    pub nonce_account: Option<Pubkey>,
+    pub nonce_authority: Option<Pubkey>,
+    pub keys: Vec<Pubkey>,
+    /// Serialized `AggMessage1`s of every signer
+    pub first_messages: Vec<Vec<u8>>,
+}
+
+impl SigningPackage {
+    pub fn first_messages(&self) -> Result<Vec<AggMessage1>, Error> {
+        self.first_messages
+            .iter()
+            .map(|msg| AggMessage1::deserialize(msg))
+            .collect()
+    }
+}
+
+impl Serialize for SigningPackage {
+    fn serialize(&self, append_to: &mut Vec<u8>) {
+        append_to.reserve(self.size_hint());
+        append_to.extend(SIGNING_PACKAGE_MAGIC);
+        append_to.push(SIGNING_PACKAGE_VERSION);
+        // Only fixed-size fields, strings and vectors, bincode can't fail on them
+        bincode::serialize_into(append_to, self).expect("signing package is serializable");
+    }
+    fn deserialize(b: &[u8]) -> Result<Self, Error> {
+        if b.len() < SIGNING_PACKAGE_HEADER {
+            return Err(Error::InputTooShort {
+                expected: SIGNING_PACKAGE_HEADER,
+                found: b.len(),
+            });
+        }
+        if b[..SIGNING_PACKAGE_MAGIC.len()] != SIGNING_PACKAGE_MAGIC {
+            return Err(Error::BadMagic);
+        }
+        let version = b[SIGNING_PACKAGE_MAGIC.len()];
+        if version != SIGNING_PACKAGE_VERSION {
+            return Err(Error::UnsupportedVersion {
+                found: version,
+                supported: SIGNING_PACKAGE_VERSION,
+            });
+        }
+        bincode::deserialize(&b[SIGNING_PACKAGE_HEADER..])
+            .map_err(|e| Error::InvalidEncoding(e.to_string()))
+    }
+    fn size_hint(&self) -> usize {
+        SIGNING_PACKAGE_HEADER + bincode::serialized_size(self).unwrap_or_default() as usize
+    }
+}
+
+pub fn serialize_transaction_bs58(tx: &Transaction) -> String {
+    bs58::encode(serialize_transaction(tx)).into_string()
+}
+
+pub fn serialize_transaction_base64(tx: &Transaction) -> String {
+    BASE64_STANDARD.encode(serialize_transaction(tx))
+}
+
+pub fn deserialize_transaction_bs58(s: impl AsRef<[u8]>) -> Result<Transaction, Error> {
+    let bytes = bs58::decode(s).into_vec().map_err(Error::BadBase58)?;
+    deserialize_transaction(&bytes)
+}
+
+pub fn deserialize_transaction_base64(s: impl AsRef<[u8]>) -> Result<Transaction, Error> {
+    let bytes = BASE64_STANDARD.decode(s).map_err(Error::BadBase64)?;
+    deserialize_transaction(&bytes)
+}
+
+/// Unsigned transaction for `message` and its serialized size. The signature slots are already
+/// zero filled, so the size is the same once signed
+pub fn build_and_measure(message: Message) -> (Transaction, usize) {
+    let tx = Transaction::new_unsigned(message);
+    let size = bincode::serialized_size(&tx).expect("transaction is serializable") as usize;
+    (tx, size)
+}
+
+/// The wire format the RPC node accepts, bincode with short-vec lengths
+fn serialize_transaction(tx: &Transaction) -> Vec<u8> {
+    bincode::serialize(tx).expect("transaction is serializable")
+}
+
+/// Same limits as the node: at most a packet, no trailing bytes and a well formed message
+fn deserialize_transaction(bytes: &[u8]) -> Result<Transaction, Error> {
+    let tx: Transaction = bincode::options()
+        .with_limit(PACKET_DATA_SIZE as u64)
+        .with_fixint_encoding()
+        .reject_trailing_bytes()
+        .deserialize(bytes)
+        .map_err(|e| match *e {
+            bincode::ErrorKind::Io(ref err) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                Error::InvalidTransaction(format!("truncated, only {} bytes", bytes.len()))
+            }
+            _ => Error::InvalidTransaction(e.to_string()),
+        })?;
+    tx.sanitize()
+        .map_err(|e| Error::InvalidTransaction(e.to_string()))?;
+    Ok(tx)
+}
+
 #[cfg(test)]
 mod tests {
     use std::fmt::Debug;
 
     use multi_party_eddsa::protocols::{ExpandedKeyPair, musig2};
-    use solana_sdk::signature::Signature;
+    use solana_sdk::{
+        hash::Hash,
+        signature::{Keypair, Signature},
+        system_transaction,
+    };
     use spl_memo::solana_program::pubkey::Pubkey;
 
-    use crate::serialization::{AggMessage1, PartialSignature, SecretAggStepOne, Serialize};
+    use crate::serialization::{
+        AggMessage1, Error, PartialSignature, SIGNING_PACKAGE_VERSION, SecretAggStepOne, Serialize,
+        SigningPackage, build_and_measure, deserialize_transaction_base64,
+        deserialize_transaction_bs58, serialize_transaction_base64, serialize_transaction_bs58,
+    };
 
     #[derive(PartialEq, Debug)]
     struct PanicEq<T: PartialEq + Debug>(T);
@@ -305,4 +453,95 @@ mod tests {
             assert_eq!(PanicEq(secret_agg1), PanicEq(deserialized));
         }
     }
+
+    #[test]
+    fn test_signing_package() {
+        let (_, public_nonces) =
+            musig2::generate_partial_nonces(&ExpandedKeyPair::create(), Some(&[1u8; 32]));
+        let sender = Pubkey::new_unique();
+        let aggmsg1 = AggMessage1 {
+            public_nonces,
+            sender,
+        };
+        let package = SigningPackage {
+            amount: 1_000_000,
+            to: Pubkey::new_unique(),
+            memo: Some("offline".to_string()),
+            tip: Some((Pubkey::new_unique(), 1_000)),
+            recent_block_hash: Hash::new_unique(),
+            nonce_account: None,
+            nonce_authority: None,
+            keys: vec![sender, Pubkey::new_unique()],
+            first_messages: vec![{
+                let mut msg = Vec::new();
+                aggmsg1.serialize(&mut msg);
+                msg
+            }],
+        };
+        let serialized = package.serialize_bs58();
+        let deserialized = SigningPackage::deserialize_bs58(&serialized).unwrap();
+        assert_eq!(package, deserialized);
+        assert_eq!(
+            PanicEq(deserialized.first_messages().unwrap()),
+            PanicEq(vec![aggmsg1])
+        );
+
+        // Another message is not mistaken for a package
+        let partial_sig = PartialSignature(Signature::new(&[7u8; 64])).serialize_bs58();
+        assert!(matches!(
+            SigningPackage::deserialize_bs58(partial_sig),
+            Err(Error::BadMagic)
+        ));
+
+        let mut bytes = bs58::decode(&serialized).into_vec().unwrap();
+        bytes[4] = SIGNING_PACKAGE_VERSION + 1;
+        assert!(matches!(
+            SigningPackage::deserialize_bs58(bs58::encode(bytes).into_string()),
+            Err(Error::UnsupportedVersion { .. })
+        ));
+    }
+
+    #[test]
+    fn test_transaction_encodings() {
+        let from = Keypair::new();
+        let tx = system_transaction::transfer(&from, &Pubkey::new_unique(), 42, Hash::new_unique());
+
+        let bs58 = serialize_transaction_bs58(&tx);
+        assert_eq!(deserialize_transaction_bs58(&bs58).unwrap(), tx);
+        let base64 = serialize_transaction_base64(&tx);
+        assert_eq!(deserialize_transaction_base64(&base64).unwrap(), tx);
+
+        let bytes = bincode::serialize(&tx).unwrap();
+        let truncated = bs58::encode(&bytes[..bytes.len() - 10]).into_string();
+        assert!(matches!(
+            deserialize_transaction_bs58(truncated),
+            Err(Error::InvalidTransaction(e)) if e.starts_with("truncated")
+        ));
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(matches!(
+            deserialize_transaction_bs58(bs58::encode(trailing).into_string()),
+            Err(Error::InvalidTransaction(_))
+        ));
+        assert!(matches!(
+            deserialize_transaction_base64("not base64!"),
+            Err(Error::BadBase64(_))
+        ));
+        assert!(matches!(
+            deserialize_transaction_bs58("0OIl"),
+            Err(Error::BadBase58(_))
+        ));
+    }
+
+    #[test]
+    fn test_build_and_measure() {
+        let from = Keypair::new();
+        let tx = system_transaction::transfer(&from, &Pubkey::new_unique(), 1, Hash::default());
+        let (mut unsigned, size) = build_and_measure(tx.message.clone());
+        assert_eq!(size, bincode::serialize(&tx).unwrap().len());
+
+        // Signing fills the placeholder signatures without growing the transaction
+        unsigned.sign(&[&from], Hash::default());
+        assert_eq!(size, bincode::serialize(&unsigned).unwrap().len());
+    }
 }
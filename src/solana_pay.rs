@@ -0,0 +1,137 @@
+//! Solana Pay transfer request URLs (https://docs.solanapay.com/spec), a transfer handed to a
+//! mobile wallet as a link or QR code. The wallet builds and signs the transaction itself.
+
+use solana_sdk::pubkey::Pubkey;
+
+/// Decimals of an amount in lamports
+pub const SOL_DECIMALS: u8 = 9;
+
+pub struct TransferRequest<'a> {
+    pub recipient: Pubkey,
+    /// Base units with their decimals, None lets the wallet ask for an amount
+    pub amount: Option<(u64, u8)>,
+    /// Requests this SPL token instead of SOL
+    pub spl_token: Option<Pubkey>,
+    pub references: &'a [Pubkey],
+    pub label: Option<&'a str>,
+    pub message: Option<&'a str>,
+    pub memo: Option<&'a str>,
+}
+
+impl<'a> TransferRequest<'a> {
+    /// A plain transfer without references, label or message
+    pub fn new(
+        recipient: Pubkey,
+        amount: Option<(u64, u8)>,
+        spl_token: Option<Pubkey>,
+        memo: Option<&'a str>,
+    ) -> Self {
+        Self {
+            recipient,
+            amount,
+            spl_token,
+            references: &[],
+            label: None,
+            message: None,
+            memo,
+        }
+    }
+
+    pub fn to_url(&self) -> String {
+        let mut params = Vec::new();
+        if let Some((amount, decimals)) = self.amount {
+            params.push(format!("amount={}", format_amount(amount, decimals)));
+        }
+        if let Some(mint) = self.spl_token {
+            params.push(format!("spl-token={}", mint));
+        }
+        for reference in self.references {
+            params.push(format!("reference={}", reference));
+        }
+        for (name, value) in [
+            ("label", self.label),
+            ("message", self.message),
+            ("memo", self.memo),
+        ] {
+            if let Some(value) = value {
+                params.push(format!("{}={}", name, percent_encode(value)));
+            }
+        }
+
+        let mut url = format!("solana:{}", self.recipient);
+        if !params.is_empty() {
+            url.push('?');
+            url.push_str(&params.join("&"));
+        }
+        url
+    }
+}
+
+/// `amount` base units as a plain decimal without trailing zeros, 1500000000 with 9 decimals is "1.5"
+pub fn format_amount(amount: u64, decimals: u8) -> String {
+    let decimals = decimals as usize;
+    let digits = format!("{:0>width$}", amount, width = decimals + 1);
+    let (whole, fraction) = digits.split_at(digits.len() - decimals);
+    let fraction = fraction.trim_end_matches('0');
+    if fraction.is_empty() {
+        whole.to_string()
+    } else {
+        format!("{}.{}", whole, fraction)
+    }
+}
+
+/// Escapes everything but the RFC 3986 unreserved characters
+fn percent_encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use solana_sdk::pubkey::Pubkey;
+
+    use crate::solana_pay::{SOL_DECIMALS, TransferRequest, format_amount};
+
+    #[test]
+    fn test_format_amount() {
+        assert_eq!(format_amount(1_500_000_000, SOL_DECIMALS), "1.5");
+        assert_eq!(format_amount(1, SOL_DECIMALS), "0.000000001");
+        assert_eq!(format_amount(2_000_000_000, SOL_DECIMALS), "2");
+        assert_eq!(format_amount(42, 0), "42");
+        assert_eq!(format_amount(0, 6), "0");
+    }
+
+    #[test]
+    fn test_url() {
+        let recipient = Pubkey::new_unique();
+        let bare = TransferRequest::new(recipient, None, None, None);
+        assert_eq!(bare.to_url(), format!("solana:{}", recipient));
+
+        let mint = Pubkey::new_unique();
+        let reference = Pubkey::new_unique();
+        let full = TransferRequest {
+            amount: Some((1_250_000, 6)),
+            spl_token: Some(mint),
+            references: &[reference],
+            label: Some("Coffee & Co"),
+            message: Some("Thanks!"),
+            memo: Some("order #1"),
+            ..bare
+        };
+        assert_eq!(
+            full.to_url(),
+            format!(
+                "solana:{}?amount=1.25&spl-token={}&reference={}&label=Coffee%20%26%20Co&message=Thanks%21&memo=order%20%231",
+                recipient, mint, reference
+            )
+        );
+    }
+}
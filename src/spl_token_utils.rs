@@ -1,15 +1,150 @@
-use crate::Error;
+use crate::{
+    Error,
+    models::{SplAuthorityType, TokenProgram},
+};
+use solana_client::rpc_client::RpcClient;
 use solana_sdk::{
-    instruction::Instruction, message::Message, pubkey::Pubkey, transaction::Transaction,
+    account::Account as SolanaAccount, instruction::Instruction, message::Message, pubkey,
+    pubkey::Pubkey, transaction::Transaction,
 };
 use spl_associated_token_account::{
-    get_associated_token_address, instruction::create_associated_token_account,
+    get_associated_token_address_with_program_id, instruction::create_associated_token_account,
 };
 use spl_token::instruction as token_instruction;
-use solana_client::rpc_client::RpcClient;
+use spl_token_2022::{
+    extension::StateWithExtensions,
+    instruction::AuthorityType,
+    state::{Account as TokenAccount, Mint},
+};
+
+/// Associated token account of `owner` for a mint owned by `token_program`
+pub fn get_associated_token_address(
+    owner: &Pubkey,
+    token_mint: &Pubkey,
+    token_program: TokenProgram,
+) -> Pubkey {
+    get_associated_token_address_with_program_id(owner, token_mint, &token_program.id())
+}
+
+/// Metaplex Token Metadata program, the same address on every cluster
+pub const TOKEN_METADATA_PROGRAM_ID: Pubkey =
+    pubkey!("metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s");
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenMetadata {
+    pub name: String,
+    pub symbol: String,
+    pub uri: String,
+}
 
+/// Address of the Metaplex metadata account of `token_mint`
+pub fn get_metadata_address(token_mint: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[
+            b"metadata",
+            TOKEN_METADATA_PROGRAM_ID.as_ref(),
+            token_mint.as_ref(),
+        ],
+        &TOKEN_METADATA_PROGRAM_ID,
+    )
+    .0
+}
+
+/// Reads name, symbol and uri from a Metaplex metadata account. They follow the key, update
+/// authority and mint as borsh strings, padded with zeros to a fixed length
+pub fn parse_metadata(metadata_account: &Pubkey, data: &[u8]) -> Result<TokenMetadata, Error> {
+    let invalid = || Error::InvalidTokenMetadata(metadata_account.to_string());
+    let read_string = |data: &[u8]| -> Option<(String, usize)> {
+        let (len, tail) = data.split_first_chunk::<4>()?;
+        let len = u32::from_le_bytes(*len) as usize;
+        let value = std::str::from_utf8(tail.get(..len)?).ok()?;
+        Some((value.trim_end_matches('\0').to_string(), 4 + len))
+    };
+    let mut offset = 1 + 32 + 32;
+    let mut next_string = || -> Result<String, Error> {
+        let (value, len) =
+            read_string(data.get(offset..).ok_or_else(invalid)?).ok_or_else(invalid)?;
+        offset += len;
+        Ok(value)
+    };
+    Ok(TokenMetadata {
+        name: next_string()?,
+        symbol: next_string()?,
+        uri: next_string()?,
+    })
+}
+
+/// None when nothing exists at `address`, any other RPC failure is an error
+pub fn get_optional_account(
+    rpc_client: &RpcClient,
+    address: &Pubkey,
+) -> Result<Option<SolanaAccount>, Error> {
+    rpc_client
+        .get_account_with_commitment(address, rpc_client.commitment())
+        .map(|response| response.value)
+        .map_err(Error::AccountInfoFailed)
+}
+
+/// An explicit `requested` program wins, otherwise it is read from the owner of the mint
+pub fn resolve_token_program(
+    rpc_client: &RpcClient,
+    token_mint: &Pubkey,
+    requested: Option<TokenProgram>,
+) -> Result<TokenProgram, Error> {
+    if let Some(token_program) = requested {
+        return Ok(token_program);
+    }
+    let mint_account =
+        get_optional_account(rpc_client, token_mint)?.ok_or(Error::TokenMintNotFound)?;
+    TokenProgram::from_owner(&mint_account.owner).ok_or_else(|| {
+        Error::InvalidRequest(format!(
+            "{} is not a token mint, it is owned by {}",
+            token_mint, mint_account.owner
+        ))
+    })
+}
 
+/// Fills in the token program and decimals from the mint, it is only fetched when one is missing
+pub fn resolve_mint(
+    rpc_client: &RpcClient,
+    token_mint: &Pubkey,
+    token_program: Option<TokenProgram>,
+    decimals: Option<u8>,
+) -> Result<(TokenProgram, u8), Error> {
+    if let (Some(token_program), Some(decimals)) = (token_program, decimals) {
+        return Ok((token_program, decimals));
+    }
+    let mint_account =
+        get_optional_account(rpc_client, token_mint)?.ok_or(Error::TokenMintNotFound)?;
+    let token_program = match token_program {
+        Some(token_program) => token_program,
+        None => TokenProgram::from_owner(&mint_account.owner).ok_or_else(|| {
+            Error::InvalidRequest(format!(
+                "{} is not a token mint, it is owned by {}",
+                token_mint, mint_account.owner
+            ))
+        })?,
+    };
+    let decimals = match decimals {
+        Some(decimals) => decimals,
+        None => unpack_mint(&mint_account.data)?.decimals,
+    };
+    Ok((token_program, decimals))
+}
+
+/// Token-2022 appends extensions after the classic layout, this reads accounts of either program
+pub fn unpack_token_account(data: &[u8]) -> Result<TokenAccount, Error> {
+    Ok(StateWithExtensions::<TokenAccount>::unpack(data)?.base)
+}
+
+/// Same as `unpack_token_account`, for mints
+pub fn unpack_mint(data: &[u8]) -> Result<Mint, Error> {
+    Ok(StateWithExtensions::<Mint>::unpack(data)?.base)
+}
+
+/// `rpc_client` has to be on the cluster the transaction is sent to, it decides which ATAs to create
 pub fn create_spl_token_transaction(
+    rpc_client: &RpcClient,
     amount: u64,
     from: &Pubkey,
     to: &Pubkey,
@@ -17,50 +152,102 @@ pub fn create_spl_token_transaction(
     payer: &Pubkey,
     memo: Option<String>,
     decimals: u8,
+    token_program: TokenProgram,
 ) -> Result<Transaction, Error> {
-    let mut instructions = Vec::new();
-
     // Get associated token addresses
-    let from_ata = get_associated_token_address(from, token_mint);
-    let to_ata = get_associated_token_address(to, token_mint);
-
-    let rpc_client = RpcClient::new("https://api.testnet.solana.com".to_string());
+    let from_ata = get_associated_token_address(from, token_mint, token_program);
+    let to_ata = get_associated_token_address(to, token_mint, token_program);
 
-    if rpc_client.get_account(&from_ata).is_err() {
+    let from_ata_exists = get_optional_account(rpc_client, &from_ata)?.is_some();
+    let to_ata_exists = get_optional_account(rpc_client, &to_ata)?.is_some();
 
-    // For now,  always try to create it (instruction will fail if it already exists)
-    let create_ata_instruction = create_associated_token_account(
-        payer, // fee payer
-        to,    // wallet owner
+    let instructions = spl_token_transfer_instructions(
+        amount,
+        from,
+        None,
+        to,
         token_mint,
-        &spl_token::id(),
-    );
-    instructions.push(create_ata_instruction);
-    }
+        payer,
+        memo,
+        from_ata_exists,
+        to_ata_exists,
+        token_program,
+        decimals,
+    )?;
 
+    let message = Message::new(&instructions, Some(payer));
+    Ok(Transaction::new_unsigned(message))
+}
 
-    if rpc_client.get_account(&to_ata).is_err() {
-    let create_to_ata_instruction = create_associated_token_account(
-            payer,     // fee payer
-            to,        // wallet owner
+/// Build the transfer instructions, creating whichever ATAs are missing first. `source`
+/// sends from another token account of `from`, or one it is a delegate of, instead of its ATA
+#[allow(clippy::too_many_arguments)]
+pub fn spl_token_transfer_instructions(
+    amount: u64,
+    from: &Pubkey,
+    source: Option<&Pubkey>,
+    to: &Pubkey,
+    token_mint: &Pubkey,
+    payer: &Pubkey,
+    memo: Option<String>,
+    from_ata_exists: bool,
+    to_ata_exists: bool,
+    token_program: TokenProgram,
+    decimals: u8,
+) -> Result<Vec<Instruction>, Error> {
+    let mut instructions = Vec::new();
+
+    let from_ata = match source {
+        Some(source) => *source,
+        None => get_associated_token_address(from, token_mint, token_program),
+    };
+    let to_ata = get_associated_token_address(to, token_mint, token_program);
+
+    // An explicit source was checked to exist by the caller
+    if !from_ata_exists && source.is_none() {
+        let create_from_ata_instruction = create_associated_token_account(
+            payer, // fee payer
+            from,  // wallet owner
             token_mint,
-            &spl_token::id(),
+            &token_program.id(),
         );
-        instructions.push(create_to_ata_instruction);
+        instructions.push(create_from_ata_instruction);
+    }
 
+    // A self-transfer shares the source ATA, which is already handled above
+    if !to_ata_exists && to_ata != from_ata {
+        let create_to_ata_instruction = create_associated_token_account(
+            payer, // fee payer
+            to,    // wallet owner
+            token_mint,
+            &token_program.id(),
+        );
+        instructions.push(create_to_ata_instruction);
     }
 
     // Create the token transfer instruction
-    let transfer_instruction = token_instruction::transfer(
-        &spl_token::id(),
-        &from_ata, // source token account
-        &to_ata,   // destination token account
-        from,      // source account owner
-        &[],       // signer pubkeys (empty for single signer)
-        amount,
-    )?;
+    let transfer_instruction = match token_program {
+        TokenProgram::Token => token_instruction::transfer(
+            &spl_token::id(),
+            &from_ata, // source token account
+            &to_ata,   // destination token account
+            from,      // source account owner
+            &[],       // signer pubkeys (empty for single signer)
+            amount,
+        )?,
+        // Mints with extensions such as transfer fees reject the unchecked transfer
+        TokenProgram::Token2022 => spl_token_2022::instruction::transfer_checked(
+            &spl_token_2022::id(),
+            &from_ata,
+            token_mint,
+            &to_ata,
+            from,
+            &[],
+            amount,
+            decimals,
+        )?,
+    };
     instructions.push(transfer_instruction);
-    
 
     //  memo instruction if provided
     if let Some(memo_text) = memo {
@@ -72,10 +259,547 @@ pub fn create_spl_token_transaction(
         instructions.push(memo_instruction);
     }
 
-    let message = Message::new(&instructions, Some(payer));
+    Ok(instructions)
+}
+
+pub fn create_associated_token_account_transaction(
+    payer: &Pubkey,
+    owner: &Pubkey,
+    token_mint: &Pubkey,
+    token_program: TokenProgram,
+) -> Transaction {
+    let create_ata_instruction =
+        create_associated_token_account(payer, owner, token_mint, &token_program.id());
+    let message = Message::new(&[create_ata_instruction], Some(payer));
+    Transaction::new_unsigned(message)
+}
+
+/// Close `owner`'s ATA and send its rent to `destination`, the account must hold no tokens
+pub fn close_token_account_transaction(
+    owner: &Pubkey,
+    token_mint: &Pubkey,
+    destination: &Pubkey,
+    token_program: TokenProgram,
+) -> Result<Transaction, Error> {
+    let token_account = get_associated_token_address(owner, token_mint, token_program);
+    // The Token-2022 builder accepts either program id, the encoded instruction is the same
+    let close_instruction = spl_token_2022::instruction::close_account(
+        &token_program.id(),
+        &token_account,
+        destination,
+        owner,
+        &[],
+    )?;
+    let message = Message::new(&[close_instruction], Some(owner));
+    Ok(Transaction::new_unsigned(message))
+}
+
+/// Let `delegate` move up to `amount` base units out of `owner`'s ATA
+pub fn approve_transaction(
+    owner: &Pubkey,
+    token_mint: &Pubkey,
+    delegate: &Pubkey,
+    amount: u64,
+    decimals: u8,
+    token_program: TokenProgram,
+) -> Result<Transaction, Error> {
+    let token_account = get_associated_token_address(owner, token_mint, token_program);
+    let approve_instruction = spl_token_2022::instruction::approve_checked(
+        &token_program.id(),
+        &token_account,
+        token_mint,
+        delegate,
+        owner,
+        &[],
+        amount,
+        decimals,
+    )?;
+    let message = Message::new(&[approve_instruction], Some(owner));
+    Ok(Transaction::new_unsigned(message))
+}
+
+/// Remove whatever delegate `owner`'s ATA currently has
+pub fn revoke_transaction(
+    owner: &Pubkey,
+    token_mint: &Pubkey,
+    token_program: TokenProgram,
+) -> Result<Transaction, Error> {
+    let token_account = get_associated_token_address(owner, token_mint, token_program);
+    let revoke_instruction =
+        spl_token_2022::instruction::revoke(&token_program.id(), &token_account, owner, &[])?;
+    let message = Message::new(&[revoke_instruction], Some(owner));
     Ok(Transaction::new_unsigned(message))
 }
 
-pub fn get_token_amount_with_decimals(amount: f64, decimals: u8) -> u64 {
-    (amount * 10_f64.powi(decimals as i32)) as u64
+/// Hands `authority_type` of `account`, a token account or a mint, from `current_authority`
+/// to `new_authority`. None removes the authority for good
+pub fn set_authority_transaction(
+    account: &Pubkey,
+    current_authority: &Pubkey,
+    authority_type: SplAuthorityType,
+    new_authority: Option<&Pubkey>,
+    token_program: TokenProgram,
+) -> Result<Transaction, Error> {
+    let authority_type = match authority_type {
+        SplAuthorityType::Owner => AuthorityType::AccountOwner,
+        SplAuthorityType::Close => AuthorityType::CloseAccount,
+        SplAuthorityType::Mint => AuthorityType::MintTokens,
+        SplAuthorityType::Freeze => AuthorityType::FreezeAccount,
+    };
+    let set_authority_instruction = spl_token_2022::instruction::set_authority(
+        &token_program.id(),
+        account,
+        new_authority,
+        authority_type,
+        current_authority,
+        &[],
+    )?;
+    let message = Message::new(&[set_authority_instruction], Some(current_authority));
+    Ok(Transaction::new_unsigned(message))
+}
+
+/// Who currently holds `authority_type`, None when it was removed. Without an explicit
+/// close authority the owner can close the account
+pub fn current_authority(
+    data: &[u8],
+    authority_type: SplAuthorityType,
+) -> Result<Option<Pubkey>, Error> {
+    Ok(match authority_type {
+        SplAuthorityType::Owner => Some(unpack_token_account(data)?.owner),
+        SplAuthorityType::Close => {
+            let account = unpack_token_account(data)?;
+            Some(account.close_authority.unwrap_or(account.owner))
+        }
+        SplAuthorityType::Mint => unpack_mint(data)?.mint_authority.into(),
+        SplAuthorityType::Freeze => unpack_mint(data)?.freeze_authority.into(),
+    })
+}
+
+/// An explicit transfer source has to hold `token_mint` and be spendable by `authority`,
+/// as its owner or as a delegate approved for at least `amount`
+pub fn check_source_account(
+    source: &Pubkey,
+    account: &SolanaAccount,
+    token_mint: &Pubkey,
+    token_program: TokenProgram,
+    authority: &Pubkey,
+    amount: u64,
+) -> Result<(), Error> {
+    if account.owner != token_program.id() {
+        return Err(Error::InvalidRequest(format!(
+            "{} is not a token account of {}, it is owned by {}",
+            source,
+            token_program.id(),
+            account.owner
+        )));
+    }
+    let token_account = unpack_token_account(&account.data)
+        .map_err(|_| Error::InvalidRequest(format!("{} is not a token account", source)))?;
+    if token_account.mint != *token_mint {
+        return Err(Error::InvalidRequest(format!(
+            "{} holds mint {}, not {}",
+            source, token_account.mint, token_mint
+        )));
+    }
+    if token_account.owner == *authority {
+        return Ok(());
+    }
+    match Option::<Pubkey>::from(token_account.delegate) {
+        Some(delegate) if delegate == *authority && token_account.delegated_amount >= amount => {
+            Ok(())
+        }
+        Some(delegate) if delegate == *authority => Err(Error::InvalidRequest(format!(
+            "{} is a delegate of {} for only {} base units, {} requested",
+            authority, source, token_account.delegated_amount, amount
+        ))),
+        _ => Err(Error::InvalidRequest(format!(
+            "{} is neither the owner of {} ({}) nor its delegate",
+            authority, source, token_account.owner
+        ))),
+    }
+}
+
+/// Integers above 2^53 can't all be represented by an f64
+const MAX_EXACT_F64_INTEGER: f64 = (1u64 << f64::MANTISSA_DIGITS) as f64;
+
+/// Rounds rather than truncates, `0.29 * 100.0` is `28.999999999999996`. The bound also
+/// keeps the `as u64` cast from saturating
+pub fn get_token_amount_with_decimals(amount: f64, decimals: u8) -> Result<u64, Error> {
+    if !amount.is_finite() || amount < 0.0 {
+        return Err(Error::InvalidAmount(amount));
+    }
+    let base_units = (amount * 10_f64.powi(decimals as i32)).round();
+    if base_units > MAX_EXACT_F64_INTEGER {
+        return Err(Error::AmountPrecisionLoss { amount, decimals });
+    }
+    Ok(base_units as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use solana_sdk::{
+        account::Account, instruction::Instruction, program_option::COption, program_pack::Pack,
+        pubkey::Pubkey,
+    };
+    use spl_associated_token_account::{
+        get_associated_token_address, get_associated_token_address_with_program_id,
+        instruction::create_associated_token_account,
+    };
+    use spl_token::state::AccountState;
+
+    use crate::error::Error;
+    use crate::models::{SplAuthorityType, TokenProgram};
+    use crate::spl_token_utils::{
+        MAX_EXACT_F64_INTEGER, TokenMetadata, approve_transaction, check_source_account,
+        close_token_account_transaction, get_token_amount_with_decimals, parse_metadata,
+        set_authority_transaction, spl_token_transfer_instructions,
+    };
+
+    fn create_ata(payer: &Pubkey, owner: &Pubkey, mint: &Pubkey) -> Instruction {
+        create_associated_token_account(payer, owner, mint, &spl_token::id())
+    }
+
+    fn transfer(from: &Pubkey, to: &Pubkey, mint: &Pubkey, amount: u64) -> Instruction {
+        spl_token::instruction::transfer(
+            &spl_token::id(),
+            &get_associated_token_address(from, mint),
+            &get_associated_token_address(to, mint),
+            from,
+            &[],
+            amount,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_transfer_instructions_per_missing_account() {
+        let from = Pubkey::new_unique();
+        let to = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let payer = from;
+        let amount = 42;
+
+        let cases = [
+            (true, true, vec![transfer(&from, &to, &mint, amount)]),
+            (
+                false,
+                true,
+                vec![
+                    create_ata(&payer, &from, &mint),
+                    transfer(&from, &to, &mint, amount),
+                ],
+            ),
+            (
+                true,
+                false,
+                vec![
+                    create_ata(&payer, &to, &mint),
+                    transfer(&from, &to, &mint, amount),
+                ],
+            ),
+            (
+                false,
+                false,
+                vec![
+                    create_ata(&payer, &from, &mint),
+                    create_ata(&payer, &to, &mint),
+                    transfer(&from, &to, &mint, amount),
+                ],
+            ),
+        ];
+
+        for (from_exists, to_exists, expected) in cases {
+            let instructions = spl_token_transfer_instructions(
+                amount,
+                &from,
+                None,
+                &to,
+                &mint,
+                &payer,
+                None,
+                from_exists,
+                to_exists,
+                TokenProgram::Token,
+                6,
+            )
+            .unwrap();
+            assert_eq!(
+                instructions, expected,
+                "from_exists: {}, to_exists: {}",
+                from_exists, to_exists
+            );
+        }
+    }
+
+    #[test]
+    fn test_self_transfer_creates_ata_once() {
+        let owner = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+
+        let instructions = spl_token_transfer_instructions(
+            1,
+            &owner,
+            None,
+            &owner,
+            &mint,
+            &owner,
+            None,
+            false,
+            false,
+            TokenProgram::Token,
+            6,
+        )
+        .unwrap();
+        assert_eq!(
+            instructions,
+            vec![
+                create_ata(&owner, &owner, &mint),
+                transfer(&owner, &owner, &mint, 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_token_2022_transfer_is_checked() {
+        let from = Pubkey::new_unique();
+        let to = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let program = spl_token_2022::id();
+        let from_ata = get_associated_token_address_with_program_id(&from, &mint, &program);
+        let to_ata = get_associated_token_address_with_program_id(&to, &mint, &program);
+
+        let instructions = spl_token_transfer_instructions(
+            7,
+            &from,
+            None,
+            &to,
+            &mint,
+            &from,
+            None,
+            true,
+            false,
+            TokenProgram::Token2022,
+            2,
+        )
+        .unwrap();
+        assert_eq!(
+            instructions,
+            vec![
+                create_associated_token_account(&from, &to, &mint, &program),
+                spl_token_2022::instruction::transfer_checked(
+                    &program,
+                    &from_ata,
+                    &mint,
+                    &to_ata,
+                    &from,
+                    &[],
+                    7,
+                    2
+                )
+                .unwrap(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_close_account_sends_rent_to_destination() {
+        let owner = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let destination = Pubkey::new_unique();
+
+        let tx = close_token_account_transaction(&owner, &mint, &destination, TokenProgram::Token)
+            .unwrap();
+        let expected = spl_token::instruction::close_account(
+            &spl_token::id(),
+            &get_associated_token_address(&owner, &mint),
+            &destination,
+            &owner,
+            &[],
+        )
+        .unwrap();
+        assert_eq!(tx.message.account_keys[0], owner);
+        assert_eq!(tx.message.instructions.len(), 1);
+        assert_eq!(tx.message.instructions[0].data, expected.data);
+        let accounts: Vec<_> = tx.message.instructions[0]
+            .accounts
+            .iter()
+            .map(|&index| tx.message.account_keys[index as usize])
+            .collect();
+        let expected_accounts: Vec<_> = expected.accounts.iter().map(|meta| meta.pubkey).collect();
+        assert_eq!(accounts, expected_accounts);
+    }
+
+    #[test]
+    fn test_approve_is_checked_against_the_mint() {
+        let owner = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let delegate = Pubkey::new_unique();
+
+        let tx =
+            approve_transaction(&owner, &mint, &delegate, 500, 6, TokenProgram::Token2022).unwrap();
+        let expected = spl_token_2022::instruction::approve_checked(
+            &spl_token_2022::id(),
+            &get_associated_token_address_with_program_id(&owner, &mint, &spl_token_2022::id()),
+            &mint,
+            &delegate,
+            &owner,
+            &[],
+            500,
+            6,
+        )
+        .unwrap();
+        let instruction = &tx.message.instructions[0];
+        assert_eq!(instruction.data, expected.data);
+        assert_eq!(
+            tx.message.account_keys[instruction.program_id_index as usize],
+            spl_token_2022::id()
+        );
+    }
+
+    #[test]
+    fn test_set_authority_to_none_revokes() {
+        let mint = Pubkey::new_unique();
+        let authority = Pubkey::new_unique();
+
+        let tx = set_authority_transaction(
+            &mint,
+            &authority,
+            SplAuthorityType::Freeze,
+            None,
+            TokenProgram::Token,
+        )
+        .unwrap();
+        let expected = spl_token::instruction::set_authority(
+            &spl_token::id(),
+            &mint,
+            None,
+            spl_token::instruction::AuthorityType::FreezeAccount,
+            &authority,
+            &[],
+        )
+        .unwrap();
+        assert_eq!(tx.message.account_keys[0], authority);
+        assert_eq!(tx.message.instructions[0].data, expected.data);
+    }
+
+    #[test]
+    fn test_parse_metadata() {
+        let padded = |value: &str, len: usize| {
+            let mut bytes = (len as u32).to_le_bytes().to_vec();
+            bytes.extend(value.as_bytes());
+            bytes.resize(4 + len, 0);
+            bytes
+        };
+        let mut data = vec![4];
+        data.extend(Pubkey::new_unique().to_bytes());
+        data.extend(Pubkey::new_unique().to_bytes());
+        data.extend(padded("USD Coin", 32));
+        data.extend(padded("USDC", 10));
+        data.extend(padded("https://example.com/usdc.json", 200));
+        // Fields after the uri are not read
+        data.extend([0; 100]);
+
+        let account = Pubkey::new_unique();
+        assert_eq!(
+            parse_metadata(&account, &data).unwrap(),
+            TokenMetadata {
+                name: "USD Coin".to_string(),
+                symbol: "USDC".to_string(),
+                uri: "https://example.com/usdc.json".to_string(),
+            }
+        );
+        assert!(matches!(
+            parse_metadata(&account, &data[..100]),
+            Err(Error::InvalidTokenMetadata(_))
+        ));
+    }
+
+    #[test]
+    fn test_source_account_owner_or_delegate() {
+        let (source, mint, owner, delegate) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let token_account = |delegated_amount| {
+            let mut data = vec![0; spl_token::state::Account::LEN];
+            spl_token::state::Account::pack(
+                spl_token::state::Account {
+                    mint,
+                    owner,
+                    amount: 100,
+                    delegate: COption::Some(delegate),
+                    state: AccountState::Initialized,
+                    delegated_amount,
+                    ..Default::default()
+                },
+                &mut data,
+            )
+            .unwrap();
+            Account {
+                lamports: 1,
+                data,
+                owner: spl_token::id(),
+                executable: false,
+                rent_epoch: 0,
+            }
+        };
+        let check = |account: &Account, mint: &Pubkey, authority: &Pubkey| {
+            check_source_account(&source, account, mint, TokenProgram::Token, authority, 50)
+        };
+
+        assert!(check(&token_account(0), &mint, &owner).is_ok());
+        assert!(check(&token_account(50), &mint, &delegate).is_ok());
+        for (account, mint, authority) in [
+            (token_account(49), mint, delegate),
+            (token_account(50), mint, Pubkey::new_unique()),
+            (token_account(50), Pubkey::new_unique(), owner),
+        ] {
+            assert!(matches!(
+                check(&account, &mint, &authority),
+                Err(Error::InvalidRequest(_))
+            ));
+        }
+        let mut wrong_program = token_account(0);
+        wrong_program.owner = spl_token_2022::id();
+        assert!(matches!(
+            check(&wrong_program, &mint, &owner),
+            Err(Error::InvalidRequest(_))
+        ));
+    }
+
+    #[test]
+    fn test_token_amount_conversion() {
+        assert_eq!(get_token_amount_with_decimals(0.29, 2).unwrap(), 29);
+        assert_eq!(
+            get_token_amount_with_decimals(1.5, 9).unwrap(),
+            1_500_000_000
+        );
+        assert!(matches!(
+            get_token_amount_with_decimals(10_000_000.0, 9),
+            Err(Error::AmountPrecisionLoss { .. })
+        ));
+        assert_eq!(
+            get_token_amount_with_decimals(MAX_EXACT_F64_INTEGER, 0).unwrap(),
+            1 << f64::MANTISSA_DIGITS
+        );
+        for amount in [f64::MAX, 1e30] {
+            assert!(matches!(
+                get_token_amount_with_decimals(amount, 0),
+                Err(Error::AmountPrecisionLoss { .. })
+            ));
+        }
+        // A high decimals count overflows on its own
+        assert!(matches!(
+            get_token_amount_with_decimals(1.0, 255),
+            Err(Error::AmountPrecisionLoss { .. })
+        ));
+        for amount in [f64::INFINITY, f64::NAN, -1.0] {
+            assert!(matches!(
+                get_token_amount_with_decimals(amount, 6),
+                Err(Error::InvalidAmount(_))
+            ));
+        }
+    }
 }
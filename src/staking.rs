@@ -1,6 +1,7 @@
-use solana_client::rpc_client::RpcClient;
+use solana_client::{rpc_client::RpcClient, rpc_response::RpcVoteAccountInfo};
 use solana_sdk::{
-    pubkey::Pubkey,
+    account_utils::StateMut,
+    pubkey::{MAX_SEED_LEN, Pubkey},
     stake::{
         instruction as stake_instruction,
         state::{Authorized, Lockup, StakeStateV2},
@@ -9,19 +10,41 @@ use solana_sdk::{
     transaction::Transaction,
 };
 
-use crate::error::Error;
+use crate::{
+    error::Error,
+    models::{ValidatorSort, ValidatorsRequest},
+};
+
+/// Seeds are limited to MAX_SEED_LEN bytes, checked before anything is signed or sent
+pub fn check_stake_seed(seed: &str) -> Result<(), Error> {
+    if seed.len() > MAX_SEED_LEN {
+        return Err(Error::InvalidStakeAccountSeed(format!(
+            "seed is {} bytes, at most {} are allowed",
+            seed.len(),
+            MAX_SEED_LEN
+        )));
+    }
+    Ok(())
+}
+
+/// The stake account `payer` creates with `seed`, the same address every time
+pub fn stake_account_address(payer: &Pubkey, seed: &str) -> Result<Pubkey, Error> {
+    check_stake_seed(seed)?;
+    Pubkey::create_with_seed(payer, seed, &solana_sdk::stake::program::id())
+        .map_err(|e| Error::InvalidStakeAccountSeed(e.to_string()))
+}
 
 pub fn create_stake_account_transaction(
+    rpc_client: &RpcClient,
     stake_amount: u64,
     seed: &str,
     payer: &Pubkey,
     validator_vote_accont: &Pubkey,
 ) -> Result<Transaction, Error> {
-    let stake_account = Pubkey::create_with_seed(payer, seed, &solana_sdk::stake::program::id())
-        .map_err(|e| Error::InvalidStakeAccountSeed(e.to_string()))?;
+    let stake_account = stake_account_address(payer, seed)?;
 
     let space = std::mem::size_of::<StakeStateV2>() as u64;
-    let rent = RpcClient::new("https://api.testnet.solana.com")
+    let rent = rpc_client
         .get_minimum_balance_for_rent_exemption(space as usize)
         .map_err(|e| Error::StakeAccountCreationFailed(e.to_string()))?;
 
@@ -55,6 +78,20 @@ pub fn create_stake_account_transaction(
     Ok(Transaction::new_unsigned(msg))
 }
 
+/// The stake program rejects smaller delegations with an opaque error, after the fee is paid
+pub fn check_minimum_delegation(rpc_client: &RpcClient, stake_amount: u64) -> Result<(), Error> {
+    let minimum = rpc_client
+        .get_stake_minimum_delegation()
+        .map_err(|e| Error::StakeDelegationFailed(format!("minimum delegation lookup: {}", e)))?;
+    if stake_amount < minimum {
+        return Err(Error::StakeDelegationFailed(format!(
+            "{} lamports is below the minimum delegation of {} lamports",
+            stake_amount, minimum
+        )));
+    }
+    Ok(())
+}
+
 pub fn create_deactivate_stake_transaction(
     stake_account: &Pubkey,
     authorized: &Pubkey,
@@ -75,3 +112,357 @@ pub fn create_withdraw_stake_transaction(
     let msg = solana_sdk::message::Message::new(&[withdraw_ins], Some(authorized));
     Transaction::new_unsigned(msg)
 }
+
+/// Fetch a stake account, returning its lamports together with the decoded state
+pub fn get_stake_account(
+    rpc_client: &RpcClient,
+    stake_account: &Pubkey,
+) -> Result<(u64, StakeStateV2), Error> {
+    let account = rpc_client
+        .get_account(stake_account)
+        .map_err(|e| Error::InvalidStakeAccount(format!("{}: {}", stake_account, e)))?;
+    if account.owner != solana_sdk::stake::program::id() {
+        return Err(Error::InvalidStakeAccount(format!(
+            "{} is not owned by the stake program",
+            stake_account
+        )));
+    }
+    let state: StakeStateV2 = account.state().map_err(|_| {
+        Error::InvalidStakeAccount(format!("{} has malformed stake data", stake_account))
+    })?;
+    Ok((account.lamports, state))
+}
+
+/// How much of a stake account can be withdrawn at `current_epoch` while keeping it open.
+/// The rent-exempt reserve always stays, taking it too is `closable_lamports`. Delegated
+/// stake stays locked until the epoch after its deactivation, only lamports above it and the
+/// reserve are free before that
+pub fn withdrawable_lamports(
+    lamports: u64,
+    state: &StakeStateV2,
+    current_epoch: u64,
+) -> Result<u64, String> {
+    match state {
+        // Nothing was initialized, so there is no reserve to keep
+        StakeStateV2::Uninitialized => Ok(lamports),
+        StakeStateV2::Initialized(meta) => Ok(lamports.saturating_sub(meta.rent_exempt_reserve)),
+        StakeStateV2::Stake(meta, stake, _) => {
+            let deactivation_epoch = stake.delegation.deactivation_epoch;
+            if deactivation_epoch < current_epoch {
+                return Ok(lamports.saturating_sub(meta.rent_exempt_reserve));
+            }
+            let locked = stake.delegation.stake + meta.rent_exempt_reserve;
+            let free = lamports.saturating_sub(locked);
+            if free > 0 {
+                Ok(free)
+            } else if deactivation_epoch == u64::MAX {
+                Err("stake is still active, deactivate first".to_string())
+            } else {
+                Err(format!(
+                    "stake is deactivating in epoch {}, it can be withdrawn from epoch {}",
+                    deactivation_epoch,
+                    deactivation_epoch + 1
+                ))
+            }
+        }
+        StakeStateV2::RewardsPool => Err("account is a rewards pool".to_string()),
+    }
+}
+
+/// The stake account balance and the part of it that can be withdrawn right now
+pub fn get_withdrawable(
+    rpc_client: &RpcClient,
+    stake_account: &Pubkey,
+) -> Result<(u64, u64), Error> {
+    let (lamports, state) = get_stake_account(rpc_client, stake_account)?;
+    let current_epoch = rpc_client
+        .get_epoch_info()
+        .map_err(Error::EpochInfoFailed)?
+        .epoch;
+    let withdrawable =
+        withdrawable_lamports(lamports, &state, current_epoch).map_err(Error::WithdrawalFailed)?;
+    Ok((lamports, withdrawable))
+}
+
+/// The whole balance, rent reserve included, once nothing is delegated anymore. Withdrawing
+/// all of it closes the account
+pub fn closable_lamports(
+    lamports: u64,
+    state: &StakeStateV2,
+    current_epoch: u64,
+) -> Result<u64, String> {
+    match state {
+        StakeStateV2::Uninitialized | StakeStateV2::Initialized(_) => Ok(lamports),
+        StakeStateV2::Stake(_, stake, _) if stake.delegation.deactivation_epoch < current_epoch => {
+            Ok(lamports)
+        }
+        StakeStateV2::Stake(_, stake, _) if stake.delegation.deactivation_epoch != u64::MAX => {
+            Err(format!(
+                "stake is deactivating in epoch {}, it can be closed from epoch {}",
+                stake.delegation.deactivation_epoch,
+                stake.delegation.deactivation_epoch + 1
+            ))
+        }
+        StakeStateV2::Stake(..) => {
+            Err("stake is still active, deactivate it before closing".to_string())
+        }
+        StakeStateV2::RewardsPool => Err("account is a rewards pool".to_string()),
+    }
+}
+
+/// Balance of a fully deactivated stake account, to close it
+pub fn get_closable(rpc_client: &RpcClient, stake_account: &Pubkey) -> Result<u64, Error> {
+    let (lamports, state) = get_stake_account(rpc_client, stake_account)?;
+    let current_epoch = rpc_client
+        .get_epoch_info()
+        .map_err(Error::EpochInfoFailed)?
+        .epoch;
+    closable_lamports(lamports, &state, current_epoch).map_err(Error::WithdrawalFailed)
+}
+
+/// Refuses a withdrawal the stake program would reject, with the reason instead of its error.
+/// The whole balance of a fully deactivated account is accepted, withdrawing it closes the account
+pub fn check_withdrawable(
+    rpc_client: &RpcClient,
+    stake_account: &Pubkey,
+    amount: u64,
+) -> Result<(), Error> {
+    let (lamports, state) = get_stake_account(rpc_client, stake_account)?;
+    let current_epoch = rpc_client
+        .get_epoch_info()
+        .map_err(Error::EpochInfoFailed)?
+        .epoch;
+    let closable = closable_lamports(lamports, &state, current_epoch).is_ok();
+    if closable && amount == lamports {
+        return Ok(());
+    }
+    let withdrawable =
+        withdrawable_lamports(lamports, &state, current_epoch).map_err(Error::WithdrawalFailed)?;
+    if amount > withdrawable {
+        return Err(Error::WithdrawalFailed(format!(
+            "only {} of {} lamports are withdrawable, {}",
+            withdrawable,
+            lamports,
+            if closable {
+                "the rest is the rent-exempt reserve, withdraw the whole balance to close the account"
+            } else {
+                "the rest is still delegated, deactivate first"
+            }
+        )));
+    }
+    Ok(())
+}
+
+/// Filters, sorts and pages one list of vote accounts. Also returns how many matched the
+/// filters, for paging through the rest
+pub fn select_validators(
+    mut accounts: Vec<RpcVoteAccountInfo>,
+    filter: &ValidatorsRequest,
+) -> (Vec<RpcVoteAccountInfo>, usize) {
+    accounts.retain(|a| {
+        filter.min_stake.is_none_or(|min| a.activated_stake >= min)
+            && filter.max_commission.is_none_or(|max| a.commission <= max)
+    });
+    // The vote account breaks ties, so pages stay stable between calls
+    match filter.sort_by.unwrap_or_default() {
+        ValidatorSort::Stake => accounts.sort_by(|a, b| {
+            (b.activated_stake, &a.vote_pubkey).cmp(&(a.activated_stake, &b.vote_pubkey))
+        }),
+        ValidatorSort::Commission => accounts.sort_by(|a, b| {
+            (a.commission, b.activated_stake, &a.vote_pubkey).cmp(&(
+                b.commission,
+                a.activated_stake,
+                &b.vote_pubkey,
+            ))
+        }),
+    }
+    let total = accounts.len();
+    let page = accounts
+        .into_iter()
+        .skip(filter.offset.unwrap_or(0))
+        .take(filter.limit.unwrap_or(usize::MAX))
+        .collect();
+    (page, total)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde_json::json;
+    use solana_client::{
+        rpc_client::RpcClient, rpc_request::RpcRequest, rpc_response::RpcVoteAccountInfo,
+    };
+    use solana_sdk::{
+        pubkey::Pubkey,
+        stake::state::{Delegation, Meta, Stake, StakeFlags, StakeStateV2},
+    };
+
+    use crate::{
+        error::Error,
+        models::{Network, ValidatorSort, ValidatorsRequest},
+        staking::{
+            check_minimum_delegation, closable_lamports, select_validators, stake_account_address,
+            withdrawable_lamports,
+        },
+    };
+
+    fn delegated(stake: u64, deactivation_epoch: u64) -> StakeStateV2 {
+        let meta = Meta {
+            rent_exempt_reserve: 2_282_880,
+            ..Meta::default()
+        };
+        let delegation = Delegation {
+            voter_pubkey: Pubkey::new_unique(),
+            stake,
+            activation_epoch: 10,
+            deactivation_epoch,
+            ..Delegation::default()
+        };
+        StakeStateV2::Stake(
+            meta,
+            Stake {
+                delegation,
+                credits_observed: 0,
+            },
+            StakeFlags::empty(),
+        )
+    }
+
+    #[test]
+    fn test_only_deactivated_stake_is_withdrawable() {
+        let lamports = 1_000_000_000 + 2_282_880;
+
+        let active = delegated(1_000_000_000, u64::MAX);
+        assert!(withdrawable_lamports(lamports, &active, 20).is_err());
+        // Lamports sent to the account on top of the delegation can always leave
+        assert_eq!(withdrawable_lamports(lamports + 5, &active, 20), Ok(5));
+
+        let deactivating = delegated(1_000_000_000, 20);
+        assert!(withdrawable_lamports(lamports, &deactivating, 20).is_err());
+        assert_eq!(
+            withdrawable_lamports(lamports, &deactivating, 21),
+            Ok(1_000_000_000)
+        );
+    }
+
+    #[test]
+    fn test_withdrawal_keeps_rent_reserve() {
+        let reserve = 2_282_880;
+        let deactivated = delegated(1_000_000_000, 20);
+        assert_eq!(withdrawable_lamports(reserve + 7, &deactivated, 21), Ok(7));
+        assert_eq!(withdrawable_lamports(reserve, &deactivated, 21), Ok(0));
+
+        let initialized = StakeStateV2::Initialized(Meta {
+            rent_exempt_reserve: reserve,
+            ..Meta::default()
+        });
+        assert_eq!(withdrawable_lamports(reserve + 7, &initialized, 0), Ok(7));
+
+        // Only closing takes the reserve as well
+        assert_eq!(
+            closable_lamports(reserve + 7, &deactivated, 21),
+            Ok(reserve + 7)
+        );
+    }
+
+    fn vote_account(name: &str, activated_stake: u64, commission: u8) -> RpcVoteAccountInfo {
+        RpcVoteAccountInfo {
+            vote_pubkey: name.to_string(),
+            node_pubkey: name.to_string(),
+            activated_stake,
+            commission,
+            epoch_vote_account: true,
+            epoch_credits: Vec::new(),
+            last_vote: 0,
+            root_slot: 0,
+        }
+    }
+
+    #[test]
+    fn test_select_validators() {
+        let accounts = vec![
+            vote_account("a", 500, 10),
+            vote_account("b", 300, 5),
+            vote_account("c", 900, 5),
+            vote_account("d", 50, 0),
+            vote_account("e", 700, 7),
+        ];
+        let mut filter = ValidatorsRequest {
+            net: Some(Network::Devnet),
+            limit: Some(2),
+            offset: Some(1),
+            min_stake: Some(100),
+            max_commission: Some(7),
+            include_delinquent: None,
+            sort_by: None,
+        };
+        let names = |(page, total): (Vec<RpcVoteAccountInfo>, usize)| {
+            let names: Vec<String> = page.into_iter().map(|a| a.vote_pubkey).collect();
+            (names, total)
+        };
+
+        // "a" charges too much and "d" has too little stake
+        assert_eq!(
+            names(select_validators(accounts.clone(), &filter)),
+            (vec!["e".to_string(), "b".to_string()], 3)
+        );
+
+        filter.sort_by = Some(ValidatorSort::Commission);
+        filter.offset = None;
+        assert_eq!(
+            names(select_validators(accounts, &filter)),
+            (vec!["c".to_string(), "b".to_string()], 3)
+        );
+    }
+
+    #[test]
+    fn test_close_needs_full_deactivation() {
+        let lamports = 1_000_000_000 + 2_282_880;
+
+        // Free lamports on top are withdrawable, but closing has to wait for the delegation
+        let active = delegated(1_000_000_000, u64::MAX);
+        assert!(closable_lamports(lamports + 5, &active, 20).is_err());
+        let deactivating = delegated(1_000_000_000, 20);
+        assert!(closable_lamports(lamports, &deactivating, 20).is_err());
+
+        assert_eq!(
+            closable_lamports(lamports, &deactivating, 21).unwrap(),
+            lamports
+        );
+        assert_eq!(
+            closable_lamports(lamports, &StakeStateV2::Initialized(Meta::default()), 0).unwrap(),
+            lamports
+        );
+    }
+
+    #[test]
+    fn test_minimum_delegation() {
+        let mut mocks = HashMap::new();
+        mocks.insert(
+            RpcRequest::GetStakeMinimumDelegation,
+            json!({"context": {"slot": 1}, "value": 1_000_000_000u64}),
+        );
+        let rpc_client = RpcClient::new_mock_with_mocks("succeeds".to_string(), mocks);
+
+        assert!(matches!(
+            check_minimum_delegation(&rpc_client, 999_999_999),
+            Err(Error::StakeDelegationFailed(_))
+        ));
+        assert!(check_minimum_delegation(&rpc_client, 1_000_000_000).is_ok());
+    }
+
+    #[test]
+    fn test_stake_seed_length() {
+        let payer = Pubkey::new_unique();
+        let address = stake_account_address(&payer, "stake:0").unwrap();
+        assert_eq!(stake_account_address(&payer, "stake:0").unwrap(), address);
+        assert_ne!(stake_account_address(&payer, "stake:1").unwrap(), address);
+
+        assert!(stake_account_address(&payer, &"s".repeat(32)).is_ok());
+        assert!(matches!(
+            stake_account_address(&payer, &"s".repeat(33)),
+            Err(Error::InvalidStakeAccountSeed(_))
+        ));
+    }
+}
@@ -0,0 +1,86 @@
+use std::{collections::HashMap, sync::Arc};
+
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{hash::Hash, signature::Signature, transaction::Transaction};
+
+use crate::{
+    agg_session::{AggSessionStore, SESSION_TTL},
+    auto_withdraw::AutoWithdrawStore,
+    blockhash_cache::{BLOCKHASH_CACHE_TTL, BlockhashCache},
+    config::Config,
+    error::Error,
+    idempotency::IdempotencyStore,
+    metrics::Metrics,
+    models::{Network, SendOptions},
+    rpc_utils::{get_latest_blockhash, send_transaction},
+};
+
+/// Shared by every handler, built once at startup
+pub struct AppState {
+    pub config: Config,
+    /// One client per cluster so connections are reused across requests
+    clients: HashMap<Network, Arc<RpcClient>>,
+    pub idempotency: IdempotencyStore,
+    pub agg_sessions: AggSessionStore,
+    /// Shared with the background task that carries the withdrawals out
+    pub auto_withdraw: Arc<AutoWithdrawStore>,
+    blockhashes: BlockhashCache,
+    pub metrics: Arc<Metrics>,
+}
+
+impl AppState {
+    pub fn new(config: Config, metrics: Arc<Metrics>) -> Self {
+        let clients = [Network::Mainnet, Network::Testnet, Network::Devnet]
+            .into_iter()
+            .map(|net| (net, Arc::new(config.rpc_client(net, metrics.clone()))))
+            .collect();
+        let idempotency = IdempotencyStore::new(config.idempotency_ttl);
+        Self {
+            config,
+            clients,
+            idempotency,
+            agg_sessions: AggSessionStore::new(SESSION_TTL),
+            auto_withdraw: Arc::default(),
+            blockhashes: BlockhashCache::new(BLOCKHASH_CACHE_TTL),
+            metrics,
+        }
+    }
+
+    pub fn rpc_client(&self, net: Network) -> Arc<RpcClient> {
+        self.clients[&net].clone()
+    }
+
+    /// Every cluster's client, for tasks running outside of a request
+    pub fn rpc_clients(&self) -> HashMap<Network, Arc<RpcClient>> {
+        self.clients.clone()
+    }
+
+    /// Latest blockhash of `net`, shared with other requests sending at about the same time.
+    /// The RPC client blocks and retries with sleeps, so a miss is fetched on the blocking pool
+    pub async fn latest_blockhash(&self, net: Network, max_retries: u32) -> Result<Hash, Error> {
+        if let Some(hash) = self.blockhashes.get(net) {
+            return Ok(hash);
+        }
+        let rpc_client = self.rpc_client(net);
+        let hash =
+            tokio::task::spawn_blocking(move || get_latest_blockhash(&rpc_client, max_retries))
+                .await
+                .map_err(|e| Error::Internal(e.to_string()))??;
+        self.blockhashes.store(net, hash);
+        Ok(hash)
+    }
+
+    /// `send_transaction` that drops the cached blockhash once the node no longer accepts it
+    pub fn send_transaction(
+        &self,
+        net: Network,
+        tx: &Transaction,
+        options: &SendOptions,
+    ) -> Result<Signature, Error> {
+        let result = send_transaction(&self.rpc_client(net), tx, options);
+        if let Err(Error::BlockhashExpired(_)) = result {
+            self.blockhashes.invalidate(net);
+        }
+        result
+    }
+}
@@ -1,9 +1,15 @@
 #![allow(non_snake_case)]
 
+use std::collections::HashSet;
+
+use crate::models::TokenProgram;
 use crate::spl_token_utils::{create_spl_token_transaction, get_token_amount_with_decimals};
+use curv::arithmetic::{BigInt, Converter};
 use curv::elliptic::curves::{Ed25519, Point, Scalar};
 use multi_party_eddsa::protocols::ExpandedKeyPair;
 use multi_party_eddsa::protocols::musig2::{self, PrivatePartialNonces, PublicPartialNonces};
+use sha2::{Digest, Sha512};
+use solana_client::rpc_client::RpcClient;
 use solana_sdk::signature::{Keypair, Signature, Signer, SignerError};
 use solana_sdk::{hash::Hash, pubkey::Pubkey, transaction::Transaction};
 
@@ -11,15 +17,92 @@ use crate::serialization::{
     AggMessage1, Error as DeserializationError, PartialSignature, SecretAggStepOne,
 };
 
+use crate::nonce::NonceParams;
 use crate::staking::{
     create_deactivate_stake_transaction, create_stake_account_transaction,
     create_withdraw_stake_transaction,
 };
 
-use crate::{Error, create_unsigned_transaction};
+use crate::{Error, Memos, Tip, create_unsigned_transaction};
+
+/// Step two takes the first message of every other signer exactly once,
+/// the signer's own nonces come from its secret state
+pub fn check_first_messages(
+    keys: &[Pubkey],
+    signer: &Pubkey,
+    first_messages: &[AggMessage1],
+) -> Result<(), Error> {
+    if !keys.contains(signer) {
+        return Err(Error::KeyPairIsNotInKeys);
+    }
+    check_senders(keys, Some(signer), first_messages)
+}
+
+/// Every key but `signer` has to have sent exactly one of `first_messages`, without a
+/// signer that is every key
+fn check_senders(
+    keys: &[Pubkey],
+    signer: Option<&Pubkey>,
+    first_messages: &[AggMessage1],
+) -> Result<(), Error> {
+    let mismatch = |reason: String| Err(Error::FirstMessagesMismatch(reason));
+
+    // A repeated key would count as a second participant that can never sign
+    let mut distinct = HashSet::new();
+    if let Some(key) = keys.iter().find(|key| !distinct.insert(**key)) {
+        return mismatch(format!("{} appears more than once in keys", key));
+    }
+
+    if let Some(signer) =
+        signer.filter(|signer| first_messages.iter().any(|msg| msg.sender == **signer))
+    {
+        return mismatch(format!(
+            "they include the message of the signer {} itself, pass only the other signers' ones",
+            signer
+        ));
+    }
+    if let Some(msg) = first_messages
+        .iter()
+        .find(|msg| !keys.contains(&msg.sender))
+    {
+        return mismatch(format!("{} is not one of the keys", msg.sender));
+    }
+    let mut seen = HashSet::new();
+    if let Some(msg) = first_messages.iter().find(|msg| !seen.insert(msg.sender)) {
+        return mismatch(format!("{} sent more than one message", msg.sender));
+    }
+
+    let missing: Vec<String> = keys
+        .iter()
+        .filter(|key| Some(*key) != signer && !seen.contains(*key))
+        .map(|key| key.to_string())
+        .collect();
+    if !missing.is_empty() {
+        return mismatch(match signer {
+            Some(_) => format!(
+                "{} signers take part, so step two needs {} messages besides the local signer's \
+                 own, got {}, missing the ones of {}",
+                distinct.len(),
+                distinct.len() - 1,
+                first_messages.len(),
+                missing.join(", ")
+            ),
+            None => format!(
+                "{} signers take part, got {} messages, missing the ones of {}",
+                distinct.len(),
+                first_messages.len(),
+                missing.join(", ")
+            ),
+        });
+    }
+    Ok(())
+}
 
 /// Create the aggregate public key, pass key=None if you don't care about the coefficient
 pub fn key_agg(keys: Vec<Pubkey>, key: Option<Pubkey>) -> Result<musig2::PublicKeyAgg, Error> {
+    if keys.is_empty() {
+        return Err(Error::InvalidRequest("keys must not be empty".to_string()));
+    }
     let convert_keys = |k: Pubkey| {
         Point::from_bytes(&k.to_bytes()).map_err(|e| Error::DeserializationFailed {
             error: DeserializationError::InvalidPoint(e),
@@ -36,6 +119,17 @@ pub fn key_agg(keys: Vec<Pubkey>, key: Option<Pubkey>) -> Result<musig2::PublicK
     musig2::PublicKeyAgg::key_aggregation_n(keys, &key).ok_or(Error::KeyPairIsNotInKeys)
 }
 
+/// The aggregated key is the only signer, so it has to be the one advancing the nonce
+fn check_nonce_authority(nonce: Option<&NonceParams>, aggpubkey: &Pubkey) -> Result<(), Error> {
+    match nonce.and_then(|n| n.authority) {
+        Some(authority) if authority != *aggpubkey => Err(Error::InvalidNonceAccount(format!(
+            "nonce authority {} must be the aggregated key {}",
+            authority, aggpubkey
+        ))),
+        _ => Ok(()),
+    }
+}
+
 /// Generate Message1 which contains nonce, public nonce, and commitment to nonces
 pub fn step_one(keypair: Keypair) -> (AggMessage1, SecretAggStepOne) {
     let extended_kepair = ExpandedKeyPair::create_from_private_key(keypair.secret().to_bytes());
@@ -57,14 +151,17 @@ pub fn step_one(keypair: Keypair) -> (AggMessage1, SecretAggStepOne) {
 #[allow(clippy::too_many_arguments)]
 pub fn step_two(
     keypair: Keypair,
-    amount: f64,
+    amount: u64,
     to: Pubkey,
     memo: Option<String>,
     recent_block_hash: Hash,
     keys: Vec<Pubkey>,
     first_messages: Vec<AggMessage1>,
     secret_state: SecretAggStepOne,
+    nonce: Option<NonceParams>,
+    tip: Option<Tip>,
 ) -> Result<PartialSignature, Error> {
+    check_first_messages(&keys, &keypair.pubkey(), &first_messages)?;
     let other_nonces: Vec<_> = first_messages
         .into_iter()
         .map(|msg1| msg1.public_nonces.R)
@@ -74,9 +171,17 @@ pub fn step_two(
     let aggkey = key_agg(keys, Some(keypair.pubkey()))?;
     let aggpubkey = Pubkey::new(&*aggkey.agg_public_key.to_bytes(true));
     let extended_kepair = ExpandedKeyPair::create_from_private_key(keypair.secret().to_bytes());
+    check_nonce_authority(nonce.as_ref(), &aggpubkey)?;
 
     // Create the unsigned transaction
-    let mut tx = create_unsigned_transaction(amount, &to, memo, &aggpubkey);
+    let mut tx = create_unsigned_transaction(
+        amount,
+        &to,
+        &Memos::from(memo),
+        &aggpubkey,
+        nonce.as_ref(),
+        tip.as_ref(),
+    );
 
     let signer = PartialSigner {
         signer_private_nonce: secret_state.private_nonces,
@@ -91,16 +196,174 @@ pub fn step_two(
     Ok(PartialSignature(sig))
 }
 
+/// Run every step of the protocol in-process, only meant for tests and trusted setups
+/// where a single party holds all the keys
+#[cfg(feature = "testing-helpers")]
+pub fn sign_all(
+    keypairs: Vec<Keypair>,
+    amount: u64,
+    to: Pubkey,
+    memo: Option<String>,
+    recent_block_hash: Hash,
+) -> Result<Transaction, Error> {
+    let keys: Vec<_> = keypairs.iter().map(|k| k.pubkey()).collect();
+    let (first_msgs, first_secrets): (Vec<_>, Vec<_>) = keypairs
+        .iter()
+        .map(|k| step_one(Keypair::from_bytes(&k.to_bytes()).unwrap()))
+        .unzip();
+
+    let signatures = keypairs
+        .into_iter()
+        .zip(first_secrets)
+        .enumerate()
+        .map(|(i, (keypair, secret))| {
+            let other_msgs = first_msgs
+                .iter()
+                .enumerate()
+                .filter(|(j, _)| *j != i)
+                .map(|(_, msg)| AggMessage1 {
+                    public_nonces: msg.public_nonces.clone(),
+                    sender: msg.sender,
+                })
+                .collect();
+            step_two(
+                keypair,
+                amount,
+                to,
+                memo.clone(),
+                recent_block_hash,
+                keys.clone(),
+                other_msgs,
+                secret,
+                None,
+                None,
+            )
+        })
+        .collect::<Result<_, _>>()?;
+
+    sign_and_broadcast(
+        amount,
+        to,
+        memo,
+        recent_block_hash,
+        keys,
+        signatures,
+        None,
+        None,
+    )
+}
+
+/// Check every partial signature on its own against the transaction it should sign,
+/// `signatures[i]` must come from `first_messages[i].sender` and `first_messages` has to
+/// include every signer. Returns the senders whose partial signature doesn't verify
+#[allow(clippy::too_many_arguments)]
+pub fn verify_partial_signatures(
+    amount: u64,
+    to: Pubkey,
+    memo: Option<String>,
+    recent_block_hash: Hash,
+    keys: Vec<Pubkey>,
+    first_messages: &[AggMessage1],
+    signatures: &[PartialSignature],
+    nonce: Option<&NonceParams>,
+    tip: Option<&Tip>,
+) -> Result<Vec<Pubkey>, Error> {
+    if first_messages.len() != signatures.len() || first_messages.is_empty() {
+        return Err(Error::InvalidRequest(format!(
+            "expected one first message per partial signature, got {} first messages and {} signatures",
+            first_messages.len(),
+            signatures.len()
+        )));
+    }
+    check_senders(&keys, None, first_messages)?;
+    let aggkey = key_agg(keys.clone(), None)?;
+    let aggpubkey = Pubkey::new(&*aggkey.agg_public_key.to_bytes(true));
+    check_nonce_authority(nonce, &aggpubkey)?;
+
+    let mut tx =
+        create_unsigned_transaction(amount, &to, &Memos::from(memo), &aggpubkey, nonce, tip);
+    tx.message.recent_blockhash = recent_block_hash;
+    let message = tx.message_data();
+
+    // The nonce coefficient `b` isn't exposed by the musig2 crate, so recover it from two
+    // signatures that only differ in the second private nonce: s(r2 = 1) - s(r2 = 0) = b
+    let other_nonces: Vec<_> = first_messages[1..]
+        .iter()
+        .map(|msg1| msg1.public_nonces.R.clone())
+        .collect();
+    let probe_keypair = ExpandedKeyPair::create_from_private_key([1u8; 32]);
+    let probe = |r2: Scalar<Ed25519>| {
+        musig2::partial_sign(
+            &other_nonces,
+            PrivatePartialNonces {
+                r: [Scalar::zero(), r2],
+            },
+            first_messages[0].public_nonces.clone(),
+            &aggkey,
+            &probe_keypair,
+            &message,
+        )
+    };
+    let with_r2 = probe(Scalar::from_bigint(&BigInt::from_bytes(&[1])));
+    let without_r2 = probe(Scalar::zero());
+    let b = &with_r2.my_partial_s - &without_r2.my_partial_s;
+    let R = without_r2.R;
+    let c = challenge(&R, &aggkey.agg_public_key, &message);
+
+    let mut invalid = Vec::new();
+    for (msg1, signature) in first_messages.iter().zip(signatures) {
+        let bytes = signature.0.as_ref();
+        let s = match Scalar::<Ed25519>::from_bytes(&bytes[32..]) {
+            Ok(s) if bytes[..32] == *R.to_bytes(true) => s,
+            _ => {
+                invalid.push(msg1.sender);
+                continue;
+            }
+        };
+        let coefficient = key_agg(keys.clone(), Some(msg1.sender))?.musig_coefficient;
+        let X = Point::from_bytes(&msg1.sender.to_bytes()).map_err(|e| {
+            Error::DeserializationFailed {
+                error: DeserializationError::InvalidPoint(e),
+                field_name: "first_messages",
+            }
+        })?;
+        // s_i·G == R_i1 + b·R_i2 + c·a_i·X_i
+        let [R1, R2] = &msg1.public_nonces.R;
+        let expected = R1 + R2 * &b + &X * &(&c * &coefficient);
+        if Point::generator() * &s != expected {
+            invalid.push(msg1.sender);
+        }
+    }
+    Ok(invalid)
+}
+
+/// The Ed25519 challenge H(R || A || M), read as a little endian number
+fn challenge(R: &Point<Ed25519>, A: &Point<Ed25519>, message: &[u8]) -> Scalar<Ed25519> {
+    let mut hash = Sha512::new()
+        .chain(&*R.to_bytes(true))
+        .chain(&*A.to_bytes(true))
+        .chain(message)
+        .finalize()
+        .to_vec();
+    hash.reverse();
+    Scalar::from_bigint(&BigInt::from_bytes(&hash))
+}
+
+/// When `nonce` is given, `recent_block_hash` must be the value stored in the nonce account
+#[allow(clippy::too_many_arguments)]
 pub fn sign_and_broadcast(
-    amount: f64,
+    amount: u64,
     to: Pubkey,
     memo: Option<String>,
     recent_block_hash: Hash,
     keys: Vec<Pubkey>,
     signatures: Vec<PartialSignature>,
+    nonce: Option<NonceParams>,
+    tip: Option<Tip>,
 ) -> Result<Transaction, Error> {
     let aggkey = key_agg(keys, None)?;
     let aggpubkey = Pubkey::new(&*aggkey.agg_public_key.to_bytes(true));
+    check_nonce_authority(nonce.as_ref(), &aggpubkey)?;
 
     // Make sure all the `R`s are the same
     if !signatures[1..]
@@ -142,7 +405,14 @@ pub fn sign_and_broadcast(
     let sig = Signature::new(&sig_bytes);
 
     // Create the same transaction again
-    let mut tx = create_unsigned_transaction(amount, &to, memo, &aggpubkey);
+    let mut tx = create_unsigned_transaction(
+        amount,
+        &to,
+        &Memos::from(memo),
+        &aggpubkey,
+        nonce.as_ref(),
+        tip.as_ref(),
+    );
     // Insert the recent_block_hash and the signature to the right places
     tx.message.recent_blockhash = recent_block_hash;
     assert_eq!(tx.signatures.len(), 1);
@@ -158,6 +428,7 @@ pub fn sign_and_broadcast(
 /// SPL Token Step Two - generates partial signature for SPL token transfer
 #[allow(clippy::too_many_arguments)]
 pub fn spl_step_two(
+    rpc_client: &RpcClient,
     keypair: Keypair,
     amount: f64,
     to: Pubkey,
@@ -168,7 +439,9 @@ pub fn spl_step_two(
     keys: Vec<Pubkey>,
     first_messages: Vec<AggMessage1>,
     secret_state: SecretAggStepOne,
+    token_program: TokenProgram,
 ) -> Result<PartialSignature, Error> {
+    check_first_messages(&keys, &keypair.pubkey(), &first_messages)?;
     let other_nonces: Vec<_> = first_messages
         .into_iter()
         .map(|msg1| msg1.public_nonces.R)
@@ -180,10 +453,11 @@ pub fn spl_step_two(
     let extended_kepair = ExpandedKeyPair::create_from_private_key(keypair.secret().to_bytes());
 
     // Convert amount to proper token units
-    let token_amount = get_token_amount_with_decimals(amount, decimals);
+    let token_amount = get_token_amount_with_decimals(amount, decimals)?;
 
     // Create the unsigned SPL token transaction
     let mut tx = create_spl_token_transaction(
+        rpc_client,
         token_amount,
         &aggpubkey, // from (the aggregated pubkey owns the tokens)
         &to,
@@ -191,6 +465,7 @@ pub fn spl_step_two(
         &aggpubkey, // payer (same as from in this case)
         memo,
         decimals,
+        token_program,
     )
     .map_err(|e| {
         Error::TransactionCreationFailed(format!("SPL token transaction creation failed: {:?}", e))
@@ -211,7 +486,9 @@ pub fn spl_step_two(
 }
 
 /// SPL Token Sign and Broadcast - aggregates signatures and broadcasts SPL token transaction
+#[allow(clippy::too_many_arguments)]
 pub fn spl_sign_and_broadcast(
+    rpc_client: &RpcClient,
     amount: f64,
     to: Pubkey,
     token_mint: Pubkey,
@@ -220,6 +497,7 @@ pub fn spl_sign_and_broadcast(
     recent_block_hash: Hash,
     keys: Vec<Pubkey>,
     signatures: Vec<PartialSignature>,
+    token_program: TokenProgram,
 ) -> Result<Transaction, Error> {
     let aggkey = key_agg(keys, None)?;
     let aggpubkey = Pubkey::new(&*aggkey.agg_public_key.to_bytes(true));
@@ -265,10 +543,11 @@ pub fn spl_sign_and_broadcast(
     let sig = Signature::new(&sig_bytes);
 
     // Convert amount to proper token units
-    let token_amount = get_token_amount_with_decimals(amount, decimals);
+    let token_amount = get_token_amount_with_decimals(amount, decimals)?;
 
     // Create the same SPL token transaction again
     let mut tx = create_spl_token_transaction(
+        rpc_client,
         token_amount,
         &aggpubkey,
         &to,
@@ -276,6 +555,7 @@ pub fn spl_sign_and_broadcast(
         &aggpubkey,
         memo,
         decimals,
+        token_program,
     )
     .map_err(|e| {
         Error::TransactionCreationFailed(format!("SPL token transaction creation failed: {:?}", e))
@@ -299,6 +579,7 @@ pub fn spl_sign_and_broadcast(
 
 #[allow(clippy::too_many_arguments)]
 pub fn stake_step_two(
+    rpc_client: &RpcClient,
     keypair: Keypair,
     stake_amount: u64,
     seed: String,
@@ -308,6 +589,7 @@ pub fn stake_step_two(
     first_messages: Vec<AggMessage1>,
     secret_state: SecretAggStepOne,
 ) -> Result<PartialSignature, Error> {
+    check_first_messages(&keys, &keypair.pubkey(), &first_messages)?;
     let other_nonces: Vec<_> = first_messages
         .into_iter()
         .map(|msg1| msg1.public_nonces.R)
@@ -319,8 +601,13 @@ pub fn stake_step_two(
     let extended_kepair = ExpandedKeyPair::create_from_private_key(keypair.secret().to_bytes());
 
     //creating unsigned transaction
-    let mut tx =
-        create_stake_account_transaction(stake_amount, &seed, &aggpubkey, &validator_vote_accont)?;
+    let mut tx = create_stake_account_transaction(
+        rpc_client,
+        stake_amount,
+        &seed,
+        &aggpubkey,
+        &validator_vote_accont,
+    )?;
 
     let signer = PartialSigner {
         signer_private_nonce: secret_state.private_nonces,
@@ -344,6 +631,7 @@ pub fn deactivate_stake_step_two(
     first_messages: Vec<AggMessage1>,
     secret_state: SecretAggStepOne,
 ) -> Result<PartialSignature, Error> {
+    check_first_messages(&keys, &keypair.pubkey(), &first_messages)?;
     let other_nonces: Vec<_> = first_messages
         .into_iter()
         .map(|msg1| msg1.public_nonces.R)
@@ -381,6 +669,7 @@ pub fn withdraw_stake_step_two(
     first_messages: Vec<AggMessage1>,
     secret_state: SecretAggStepOne,
 ) -> Result<PartialSignature, Error> {
+    check_first_messages(&keys, &keypair.pubkey(), &first_messages)?;
     let other_nonces: Vec<_> = first_messages
         .into_iter()
         .map(|msg1| msg1.public_nonces.R)
@@ -408,7 +697,9 @@ pub fn withdraw_stake_step_two(
     Ok(PartialSignature(sig))
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn aggregate_stake_signatures_and_broadcast(
+    rpc_client: &RpcClient,
     stake_amount: u64,
     seed: String,
     validator_vote_accont: Pubkey,
@@ -459,14 +750,21 @@ pub fn aggregate_stake_signatures_and_broadcast(
     sig_bytes[32..].copy_from_slice(&full_sig.s.to_bytes());
     let sig = Signature::new(&sig_bytes);
 
-    println!("reacted in tss before create_stake_account_transaction");
-    println!(
+    tracing::debug!(
         "stake_amount: {:?}, seed: {:?}, aggpubkey: {:?}, vote_account: {:?}",
-        stake_amount, seed, aggpubkey, validator_vote_accont
+        stake_amount,
+        seed,
+        aggpubkey,
+        validator_vote_accont
     );
-    let mut tx =
-        create_stake_account_transaction(stake_amount, &seed, &aggpubkey, &validator_vote_accont)?;
-    println!("print tx: {:?}", tx);
+    let mut tx = create_stake_account_transaction(
+        rpc_client,
+        stake_amount,
+        &seed,
+        &aggpubkey,
+        &validator_vote_accont,
+    )?;
+    tracing::debug!("stake transaction: {:?}", tx);
 
     // Insert the recent_block_hash and the signature to the right places
     tx.message.recent_blockhash = recent_block_hash;
@@ -640,10 +938,16 @@ impl Signer for PartialSigner {
 
 #[cfg(test)]
 mod tests {
-    use crate::native_token::lamports_to_sol;
+    use crate::Error;
+    use crate::serialization::PartialSignature;
     use crate::serialization::Serialize;
-    use crate::tss::{key_agg, sign_and_broadcast, step_one, step_two};
+    use crate::tss::{
+        check_first_messages, key_agg, sign_and_broadcast, step_one, step_two,
+        verify_partial_signatures,
+    };
+    use solana_sdk::hash::Hash;
     use solana_sdk::pubkey::Pubkey;
+    use solana_sdk::signature::Signature;
     use solana_sdk::signature::{Keypair, Signer};
     use solana_streamer::socket::SocketAddrSpace;
     use solana_test_validator::TestValidator;
@@ -678,7 +982,7 @@ mod tests {
 
         let recent_block_hash = rpc_client.get_latest_blockhash().unwrap();
         // step 2
-        let amount = lamports_to_sol(full_amount / 2);
+        let amount = full_amount / 2;
         let memo = Some("test_roundtrip".to_string());
 
         let partial_sigs: Vec<_> = keys
@@ -698,6 +1002,8 @@ mod tests {
                     pubkeys.clone(),
                     first_msgs,
                     secret,
+                    None,
+                    None,
                 )
                 .unwrap()
             })
@@ -710,6 +1016,8 @@ mod tests {
             recent_block_hash,
             pubkeys,
             partial_sigs,
+            None,
+            None,
         )
         .unwrap();
         let sig = rpc_client.send_transaction(&full_tx).unwrap();
@@ -719,4 +1027,140 @@ mod tests {
             .confirm_transaction_with_spinner(&sig, &recent_block_hash, rpc_client.commitment())
             .unwrap();
     }
+
+    #[test]
+    fn test_verify_partial_signatures_finds_bad_signer() {
+        let n = 3;
+        let mut rng = rand07::thread_rng();
+        let keys: Vec<_> = (0..n).map(|_| Keypair::generate(&mut rng)).collect();
+        let pubkeys: Vec<_> = keys.iter().map(|k| k.pubkey()).collect();
+        let to = Keypair::generate(&mut rng).pubkey();
+        let recent_block_hash = Hash::new_unique();
+
+        let (first_msgs, first_secrets): (Vec<_>, Vec<_>) =
+            keys.iter().map(clone_keypair).map(step_one).unzip();
+        let mut partial_sigs: Vec<_> = keys
+            .iter()
+            .map(clone_keypair)
+            .zip(first_secrets.into_iter())
+            .enumerate()
+            .map(|(i, (key, secret))| {
+                let mut first_msgs: Vec<_> = first_msgs.iter().map(clone_serialize).collect();
+                first_msgs.remove(i);
+                step_two(
+                    key,
+                    1_000_000_000,
+                    to,
+                    None,
+                    recent_block_hash,
+                    pubkeys.clone(),
+                    first_msgs,
+                    secret,
+                    None,
+                    None,
+                )
+                .unwrap()
+            })
+            .collect();
+
+        let verify = |sigs: &[PartialSignature]| {
+            verify_partial_signatures(
+                1_000_000_000,
+                to,
+                None,
+                recent_block_hash,
+                pubkeys.clone(),
+                &first_msgs,
+                sigs,
+                None,
+                None,
+            )
+            .unwrap()
+        };
+        assert!(verify(&partial_sigs).is_empty());
+
+        // Signer 1 signs with the right nonce but a different s
+        let mut bytes = [0u8; 64];
+        bytes.copy_from_slice(partial_sigs[1].0.as_ref());
+        bytes[32..].copy_from_slice(&partial_sigs[2].0.as_ref()[32..]);
+        partial_sigs[1] = PartialSignature(Signature::new(&bytes));
+        assert_eq!(verify(&partial_sigs), vec![pubkeys[1]]);
+
+        // Leaving out a signer is a mismatch rather than a passing check
+        let result = verify_partial_signatures(
+            1_000_000_000,
+            to,
+            None,
+            recent_block_hash,
+            pubkeys.clone(),
+            &first_msgs[..2],
+            &partial_sigs[..2],
+            None,
+            None,
+        );
+        assert!(matches!(result, Err(Error::FirstMessagesMismatch(_))));
+    }
+
+    #[test]
+    fn test_first_messages_must_match_keys() {
+        let mut rng = rand07::thread_rng();
+        let keys: Vec<_> = (0..3).map(|_| Keypair::generate(&mut rng)).collect();
+        let pubkeys: Vec<_> = keys.iter().map(|k| k.pubkey()).collect();
+        let first_msgs: Vec<_> = keys
+            .iter()
+            .map(clone_keypair)
+            .map(step_one)
+            .map(|(msg, _)| msg)
+            .collect();
+        let check = |indices: &[usize]| {
+            let msgs: Vec<_> = indices
+                .iter()
+                .map(|i| clone_serialize(&first_msgs[*i]))
+                .collect();
+            check_first_messages(&pubkeys, &pubkeys[0], &msgs)
+        };
+
+        assert!(check(&[1, 2]).is_ok());
+        assert!(check(&[2, 1]).is_ok());
+        // Own message, a missing one and a duplicate are all named
+        for indices in [&[0, 1, 2][..], &[1][..], &[1, 1][..]] {
+            assert!(matches!(
+                check(indices),
+                Err(Error::FirstMessagesMismatch(_))
+            ));
+        }
+        assert!(matches!(
+            check_first_messages(&pubkeys[1..], &pubkeys[0], &[]),
+            Err(Error::KeyPairIsNotInKeys)
+        ));
+
+        let repeated = vec![pubkeys[0], pubkeys[1], pubkeys[1]];
+        let msgs = vec![clone_serialize(&first_msgs[1])];
+        match check_first_messages(&repeated, &pubkeys[0], &msgs) {
+            Err(Error::FirstMessagesMismatch(reason)) => {
+                assert!(reason.contains("more than once in keys"))
+            }
+            other => panic!("expected a mismatch, got {:?}", other),
+        }
+
+        // The counts are spelled out when a message is missing
+        match check(&[2]) {
+            Err(Error::FirstMessagesMismatch(reason)) => {
+                assert!(reason.contains("3 signers take part"));
+                assert!(reason.contains("needs 2 messages"));
+                assert!(reason.contains("got 1"));
+            }
+            other => panic!("expected a mismatch, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_key_agg_without_keys() {
+        for signer in [None, Some(Pubkey::new_unique())] {
+            assert!(matches!(
+                key_agg(Vec::new(), signer),
+                Err(Error::InvalidRequest(_))
+            ));
+        }
+    }
 }
@@ -0,0 +1,95 @@
+use solana_sdk::signature::{Keypair, Signer};
+
+use crate::error::Error;
+
+const BASE58_ALPHABET: &str = "123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+/// Used when a request doesn't set `max_attempts`
+pub const DEFAULT_VANITY_ATTEMPTS: u64 = 1_000_000;
+
+/// Each extra character multiplies the expected attempts by 58 (or about 34 ignoring case),
+/// past this a search would tie up a blocking thread for minutes
+pub const MAX_VANITY_ATTEMPTS: u64 = 50_000_000;
+
+/// A prefix with a character outside the base58 alphabet can never match
+pub fn check_prefix(prefix: &str, case_sensitive: bool) -> Result<(), Error> {
+    if prefix.is_empty() {
+        return Err(Error::InvalidRequest(
+            "prefix must not be empty".to_string(),
+        ));
+    }
+    let invalid = prefix.chars().find(|c| {
+        if case_sensitive {
+            !BASE58_ALPHABET.contains(*c)
+        } else {
+            !BASE58_ALPHABET.contains(c.to_ascii_lowercase())
+                && !BASE58_ALPHABET.contains(c.to_ascii_uppercase())
+        }
+    });
+    match invalid {
+        Some(c) => Err(Error::InvalidRequest(format!(
+            "prefix can't contain '{}', base58 has no 0, O, I or l",
+            c
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Generates keypairs until the pubkey starts with `prefix`. Returns the keypair and the
+/// number of attempts it took, or None when `max_attempts` ran out
+pub fn search(prefix: &str, case_sensitive: bool, max_attempts: u64) -> Option<(Keypair, u64)> {
+    let prefix = if case_sensitive {
+        prefix.to_string()
+    } else {
+        prefix.to_lowercase()
+    };
+    let mut rng = rand07::thread_rng();
+    (1..=max_attempts).find_map(|attempt| {
+        let keypair = Keypair::generate(&mut rng);
+        let pubkey = keypair.pubkey().to_string();
+        let matches = if case_sensitive {
+            pubkey.starts_with(&prefix)
+        } else {
+            pubkey.to_lowercase().starts_with(&prefix)
+        };
+        matches.then_some((keypair, attempt))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use solana_sdk::signature::Signer;
+
+    use crate::{
+        error::Error,
+        vanity::{check_prefix, search},
+    };
+
+    #[test]
+    fn test_prefix_must_be_base58() {
+        assert!(check_prefix("Sol", true).is_ok());
+        assert!(matches!(
+            check_prefix("", true),
+            Err(Error::InvalidRequest(_))
+        ));
+        assert!(matches!(
+            check_prefix("l0", true),
+            Err(Error::InvalidRequest(_))
+        ));
+        // "L" exists in base58, so "l" can match ignoring case, while "0" never can
+        assert!(check_prefix("l", false).is_ok());
+        assert!(matches!(
+            check_prefix("0", false),
+            Err(Error::InvalidRequest(_))
+        ));
+    }
+
+    #[test]
+    fn test_search_finds_short_prefix() {
+        let (keypair, attempts) = search("a", false, 100_000).unwrap();
+        assert!(keypair.pubkey().to_string().to_lowercase().starts_with('a'));
+        assert!(attempts >= 1);
+
+        assert!(search("zzzzzzzz", true, 10).is_none());
+    }
+}
@@ -0,0 +1,143 @@
+use std::{str::FromStr, time::Duration};
+
+use futures_util::{SinkExt, StreamExt, stream};
+use poem::web::websocket::{Message, WebSocketStream};
+use solana_client::{
+    nonblocking::pubsub_client::PubsubClient,
+    rpc_config::RpcSignatureSubscribeConfig,
+    rpc_response::{Response as RpcResponse, RpcSignatureResult},
+};
+use solana_sdk::{commitment_config::CommitmentConfig, signature::Signature};
+
+use crate::{
+    config::resolve_network,
+    error::Error,
+    models::{ConfirmStatusUpdate, ConfirmSubscribeRequest, ErrorResponse, Network},
+};
+
+/// Give up on transactions that never land, a blockhash expires well before this
+const CONFIRM_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Serves one `/ws/confirm` connection: the client sends a `ConfirmSubscribeRequest`,
+/// the server answers with a `ConfirmStatusUpdate` per stage until the transaction
+/// is finalized, fails or times out, then closes the socket. The session outlives the
+/// upgrade request, so its `request_id` and the configured `default_network` are passed along
+pub async fn confirm_session(
+    mut socket: WebSocketStream,
+    request_id: String,
+    default_network: Option<Network>,
+) {
+    let request = match socket.next().await {
+        Some(Ok(Message::Text(text))) => serde_json::from_str::<ConfirmSubscribeRequest>(&text)
+            .map_err(|e| Error::InvalidRequest(e.to_string())),
+        _ => return,
+    };
+
+    let result = match request {
+        Ok(request) => stream_signature_status(&mut socket, request, default_network).await,
+        Err(e) => Err(e),
+    };
+    if let Err(e) = result {
+        let error = ErrorResponse {
+            code: e.code().to_string(),
+            error: e.to_string(),
+            errors: None,
+            request_id: Some(request_id),
+        };
+        send_json(&mut socket, &error).await;
+    }
+    let _ = socket.close().await;
+}
+
+async fn stream_signature_status(
+    socket: &mut WebSocketStream,
+    request: ConfirmSubscribeRequest,
+    default_network: Option<Network>,
+) -> Result<(), Error> {
+    let net = resolve_network(request.net, default_network)?;
+
+    let signature = Signature::from_str(&request.signature)
+        .map_err(|e| Error::InvalidRequest(format!("signature: {}", e)))?;
+
+    let pubsub = PubsubClient::new(&net.get_websocket_url())
+        .await
+        .map_err(|e| Error::SubscriptionFailed(e.to_string()))?;
+
+    let (confirmed, _) = pubsub
+        .signature_subscribe(
+            &signature,
+            subscribe_config(CommitmentConfig::confirmed(), true),
+        )
+        .await
+        .map_err(|e| Error::SubscriptionFailed(e.to_string()))?;
+    let (finalized, _) = pubsub
+        .signature_subscribe(
+            &signature,
+            subscribe_config(CommitmentConfig::finalized(), false),
+        )
+        .await
+        .map_err(|e| Error::SubscriptionFailed(e.to_string()))?;
+
+    let mut updates = stream::select(
+        confirmed.map(|response| to_update(&signature, "confirmed", response)),
+        finalized.map(|response| to_update(&signature, "finalized", response)),
+    );
+
+    let stream_updates = async {
+        while let Some(update) = updates.next().await {
+            let done = matches!(update.status.as_str(), "finalized" | "failed");
+            if !send_json(socket, &update).await || done {
+                break;
+            }
+        }
+    };
+    if tokio::time::timeout(CONFIRM_TIMEOUT, stream_updates)
+        .await
+        .is_err()
+    {
+        let update = ConfirmStatusUpdate {
+            signature: signature.to_string(),
+            status: "timeout".to_string(),
+            slot: None,
+            error: Some(format!("not finalized after {:?}", CONFIRM_TIMEOUT)),
+        };
+        send_json(socket, &update).await;
+    }
+    Ok(())
+}
+
+fn subscribe_config(
+    commitment: CommitmentConfig,
+    enable_received_notification: bool,
+) -> Option<RpcSignatureSubscribeConfig> {
+    Some(RpcSignatureSubscribeConfig {
+        commitment: Some(commitment),
+        enable_received_notification: Some(enable_received_notification),
+    })
+}
+
+fn to_update(
+    signature: &Signature,
+    commitment: &str,
+    response: RpcResponse<RpcSignatureResult>,
+) -> ConfirmStatusUpdate {
+    let (status, error) = match response.value {
+        RpcSignatureResult::ReceivedSignature(_) => ("received", None),
+        RpcSignatureResult::ProcessedSignature(result) => match result.err {
+            Some(err) => ("failed", Some(err.to_string())),
+            None => (commitment, None),
+        },
+    };
+    ConfirmStatusUpdate {
+        signature: signature.to_string(),
+        status: status.to_string(),
+        slot: Some(response.context.slot),
+        error,
+    }
+}
+
+/// Returns false once the client has gone away
+async fn send_json<T: serde::Serialize>(socket: &mut WebSocketStream, value: &T) -> bool {
+    let text = serde_json::to_string(value).unwrap_or_default();
+    socket.send(Message::Text(text)).await.is_ok()
+}